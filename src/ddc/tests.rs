@@ -29,6 +29,12 @@ fn get_accounts() -> DefaultAccounts<DefaultEnvironment> {
 
 const DDC_NODE_PERMISSION_TRUSTED: u64 = 1;
 
+#[ink::test]
+fn contract_api_version_works() {
+    let contract = make_contract();
+    assert_eq!(contract.contract_api_version(), 2);
+}
+
 #[ink::test]
 fn new_works() {
     // Default constructor should do its job
@@ -92,6 +98,64 @@ fn subscribe_works() {
     // assert_eq!(contract.balance_of(payer), 2);
 }
 
+#[ink::test]
+fn change_tier_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let payer = accounts.alice;
+
+    set_exec_context(payer, 0);
+    assert_eq!(contract.change_tier(2), Err(Error::NoSubscription));
+    undo_set_exec_context();
+
+    set_exec_context(payer, 2);
+    contract.subscribe(1).unwrap();
+    assert_eq!(contract.change_tier(4), Err(Error::TidOutOfBound));
+    assert_eq!(contract.change_tier(2), Ok(()));
+    undo_set_exec_context();
+
+    // The remaining balance lasts half as long at the new fee
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionTierChanged(SubscriptionTierChanged {
+        app,
+        old_tier_id,
+        new_tier_id,
+        end_date_ms,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(app, payer);
+        assert_eq!(old_tier_id, 1);
+        assert_eq!(new_tier_id, 2);
+        assert_eq!(end_date_ms, PERIOD_MS / 2);
+    } else {
+        panic!("Wrong event type")
+    }
+    assert_eq!(contract.tier_id_of(payer), 2);
+    assert_eq!(
+        contract.get_app_limit_at_time(payer, 0),
+        Ok(AppSubscriptionLimit::new(4000, 4000, 4000))
+    );
+
+    // Only the owner can forbid implicit tier changes
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.set_reject_implicit_tier_change(true),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert!(!contract.get_reject_implicit_tier_change());
+    assert_eq!(contract.set_reject_implicit_tier_change(true), Ok(()));
+    assert!(contract.get_reject_implicit_tier_change());
+
+    // Renewing the same tier still works, another tier is rejected
+    set_exec_context(payer, 8);
+    assert_eq!(contract.subscribe(3), Err(Error::TierMismatch));
+    assert_eq!(contract.tier_id_of(payer), 2);
+    assert_eq!(contract.subscribe(2), Ok(()));
+    assert_eq!(contract.change_tier(3), Ok(()));
+    assert_eq!(contract.tier_id_of(payer), 3);
+}
+
 /// Test the total balance of the contract is correct
 #[ink::test]
 fn balance_of_contract_works() {
@@ -103,6 +167,73 @@ fn balance_of_contract_works() {
     assert_eq!(contract.balance_of_contract(), 0);
 }
 
+#[ink::test]
+fn set_payment_sink_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let sink = accounts.frank;
+
+    assert_eq!(contract.get_payment_sink(), None);
+    assert_eq!(contract.settle_revenue(), Err(Error::NoPaymentSink));
+
+    // Only the owner can set the payment sink
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(contract.set_payment_sink(Some(sink)), Err(Error::OnlyOwner));
+    assert_eq!(contract.settle_revenue(), Err(Error::OnlyOwner));
+    undo_set_exec_context();
+
+    assert_eq!(
+        contract.set_payment_sink(Some(AccountId::default())),
+        Err(Error::InvalidAccount)
+    );
+    assert_eq!(contract.set_payment_sink(Some(sink)), Ok(()));
+    assert_eq!(contract.get_payment_sink(), Some(sink));
+
+    // Nothing to forward before any revenue is settled
+    assert_eq!(contract.get_unforwarded_revenue(), 0);
+    assert_eq!(contract.settle_revenue(), Err(Error::ZeroBalance));
+
+    assert_eq!(contract.set_payment_sink(None), Ok(()));
+    assert_eq!(contract.get_payment_sink(), None);
+}
+
+#[ink::test]
+fn set_revenue_burn_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let burn_address = AccountId::from([0xee; 32]);
+
+    assert_eq!(contract.total_burned(), 0);
+    assert_eq!(contract.split_revenue(100), Ok((0, 100)));
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.set_revenue_burn(1000, burn_address),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.set_revenue_burn(10_001, burn_address),
+        Err(Error::InvalidShare)
+    );
+    assert_eq!(
+        contract.set_revenue_burn(1000, AccountId::default()),
+        Err(Error::InvalidAccount)
+    );
+
+    assert_eq!(contract.set_revenue_burn(1000, burn_address), Ok(()));
+    assert_eq!(contract.get_revenue_burn(), (1000, burn_address));
+    assert_eq!(contract.split_revenue(100), Ok((10, 90)));
+    assert_eq!(contract.split_revenue(9), Ok((0, 9)));
+
+    assert_eq!(contract.set_revenue_burn(10_000, burn_address), Ok(()));
+    assert_eq!(contract.split_revenue(100), Ok((100, 0)));
+    assert_eq!(
+        contract.split_revenue(Balance::MAX),
+        Err(Error::ArithmeticError)
+    );
+}
+
 /// Test the contract can return the correct tier if given an account id
 #[ink::test]
 fn tier_id_of_works() {
@@ -139,7 +270,181 @@ fn get_all_tiers_works() {
     assert_eq!(tiers[2].rcu_per_minute, 8000);
 }
 
+#[ink::test]
+fn get_tiers_page_works() {
+    let contract = make_contract();
+    let tiers = contract.get_all_tiers();
+
+    let page = contract.get_tiers_page(0, 2);
+    assert_eq!(page.items, tiers[0..2].to_vec());
+    assert_eq!(page.next_cursor, Some(2));
+    assert_eq!(page.total, 3);
+
+    let page = contract.get_tiers_page(2, 2);
+    assert_eq!(page.items, tiers[2..].to_vec());
+    assert_eq!(page.next_cursor, None);
+    assert_eq!(page.total, 3);
+
+    // Past the end
+    let page = contract.get_tiers_page(5, 2);
+    assert_eq!(page.items, vec![]);
+    assert_eq!(page.next_cursor, None);
+}
+
+#[ink::test]
+fn get_subscriptions_page_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let apps = vec![accounts.bob, accounts.charlie, accounts.django];
+
+    for app in apps.iter() {
+        set_exec_context(*app, 2);
+        contract.subscribe(1).unwrap();
+        undo_set_exec_context();
+    }
+    assert_eq!(contract.subscription_count(), 3);
+
+    let page = contract.get_subscriptions_page(0, 2);
+    assert_eq!(page.next_cursor, Some(2));
+    assert_eq!(page.total, 3);
+    let mut listed: Vec<AccountId> = page.items.iter().map(|(app, _)| *app).collect();
+    assert_eq!(
+        page.items[0].1,
+        contract
+            .get_subscription_details_of(page.items[0].0)
+            .unwrap()
+    );
+
+    let page = contract.get_subscriptions_page(2, 2);
+    assert_eq!(page.next_cursor, None);
+    listed.extend(page.items.iter().map(|(app, _)| *app));
+    listed.sort();
+    let mut expected = apps;
+    expected.sort();
+    assert_eq!(listed, expected);
+}
+
 /// Test the contract owner can change tier fees for all 3 tiers
+#[ink::test]
+fn schedule_tier_fee_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let other_app_id = accounts.django;
+
+    for app in [app_id, other_app_id].iter() {
+        set_exec_context(*app, 2);
+        contract.subscribe(1).unwrap();
+        undo_set_exec_context();
+    }
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.schedule_tier_fee(1, 4, 10),
+        Err(Error::MissingRole)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.schedule_tier_fee(9, 4, 10),
+        Err(Error::TidOutOfBound)
+    );
+    assert_eq!(
+        contract.schedule_tier_fee(1, 4, 0),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(contract.schedule_tier_fee(1, 4, 10), Ok(()));
+
+    // The change is upcoming
+    assert_eq!(contract.tier_deposit(1), 2);
+    assert_eq!(contract.tier_fee_at_time(1, 9), 2);
+    assert_eq!(contract.tier_fee_at_time(1, 10), 4);
+    assert_eq!(contract.get_scheduled_fees().len(), 1);
+
+    // Update block time from 0 to 10
+    advance_block::<DefaultEnvironment>().unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+
+    assert_eq!(contract.tier_deposit(1), 4);
+    assert_eq!(contract.get_scheduled_fees(), vec![]);
+
+    // The queries use the new fee before the change is applied
+    assert_eq!(contract.get_tier_limit(1).tier_fee, 4);
+    assert_eq!(contract.get_all_tiers()[0].tier_fee, 4);
+    assert_eq!(
+        contract
+            .get_subscription_details_of(app_id)
+            .unwrap()
+            .end_date_ms,
+        10 + PERIOD_MS / 2
+    );
+
+    // The change applies to the tier when one of its subscriptions is touched: they are
+    // settled at the old fee until the change
+    set_exec_context(app_id, 0);
+    contract.set_spending_cap(None).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.subscriptions.get(&app_id).unwrap().last_update_ms,
+        10
+    );
+    assert_eq!(contract.service_tiers.get(&1).unwrap().tier_fee, 4);
+    assert_eq!(
+        contract
+            .get_subscription_details_of(app_id)
+            .unwrap()
+            .end_date_ms,
+        10 + PERIOD_MS / 2
+    );
+
+    // The other subscriptions are settled when they are touched in turn
+    assert_eq!(
+        contract
+            .subscriptions
+            .get(&other_app_id)
+            .unwrap()
+            .last_update_ms,
+        0
+    );
+    assert_eq!(
+        contract
+            .get_subscription_details_of(other_app_id)
+            .unwrap()
+            .end_date_ms,
+        10 + PERIOD_MS / 2
+    );
+    set_exec_context(other_app_id, 0);
+    contract.set_spending_cap(None).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract
+            .subscriptions
+            .get(&other_app_id)
+            .unwrap()
+            .last_update_ms,
+        10
+    );
+    assert_eq!(
+        contract
+            .get_subscription_details_of(other_app_id)
+            .unwrap()
+            .end_date_ms,
+        10 + PERIOD_MS / 2
+    );
+
+    set_exec_context(app_id, 2);
+    assert_eq!(contract.subscribe(1), Err(Error::InsufficientDeposit));
+}
+
+#[ink::test]
+fn change_tier_fee_cancels_scheduled_fee() {
+    let mut contract = make_contract();
+
+    contract.schedule_tier_fee(1, 4, 10).unwrap();
+    assert_eq!(contract.change_tier_fee(1, 3), Ok(()));
+    assert_eq!(contract.get_scheduled_fees(), vec![]);
+    assert_eq!(contract.tier_fee_at_time(1, 10), 3);
+}
+
 #[ink::test]
 fn change_tier_fee_works() {
     let mut contract = make_contract();
@@ -174,6 +479,89 @@ fn change_tier_limit_works() {
     );
 }
 
+/// Test a tier can be limited to a number of subscribers
+#[ink::test]
+fn tier_max_subscribers_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+
+    // Only the owner can limit a tier
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(
+        contract.set_tier_max_subscribers(1, Some(1)),
+        Err(Error::MissingRole)
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.set_tier_max_subscribers(1, Some(1)), Ok(()));
+
+    set_exec_context(accounts.alice, 2);
+    assert_eq!(contract.subscribe(1), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(contract.tier_subscriber_count(1), 1);
+
+    // The tier is full for new subscribers
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(contract.subscribe(1), Err(Error::TierFull));
+    undo_set_exec_context();
+
+    // And for subscribers changing their tier
+    set_exec_context(accounts.bob, 4);
+    assert_eq!(contract.subscribe(2), Ok(()));
+    assert_eq!(contract.subscribe(1), Err(Error::TierFull));
+    undo_set_exec_context();
+
+    // The existing subscriber can still top up
+    set_exec_context(accounts.alice, 2);
+    assert_eq!(contract.subscribe(1), Ok(()));
+    undo_set_exec_context();
+
+    // Remove the limit
+    assert_eq!(contract.set_tier_max_subscribers(1, None), Ok(()));
+    set_exec_context(accounts.bob, 4);
+    assert_eq!(contract.subscribe(1), Ok(()));
+    assert_eq!(contract.tier_subscriber_count(1), 2);
+    assert_eq!(contract.tier_subscriber_count(2), 0);
+}
+
+/// Test the apps which leave a tier stop counting towards its capacity
+#[ink::test]
+fn tier_capacity_is_released() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    contract.set_tier_max_subscribers(1, Some(1)).unwrap();
+
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(contract.subscribe(1), Ok(()));
+    undo_set_exec_context();
+
+    // An active subscription keeps its place
+    assert_eq!(
+        contract.release_expired_subscription(accounts.bob),
+        Err(Error::SubscriptionActive)
+    );
+    assert_eq!(
+        contract.release_expired_subscription(accounts.charlie),
+        Err(Error::NoSubscription)
+    );
+
+    // Anyone can release an expired subscription
+    assert_eq!(
+        contract.release_expired_subscription_at_time(accounts.bob, 2 * PERIOD_MS),
+        Ok(())
+    );
+    assert_eq!(contract.tier_subscriber_count(1), 0);
+
+    set_exec_context(accounts.charlie, 2);
+    assert_eq!(contract.subscribe(1), Ok(()));
+    assert_eq!(contract.tier_subscriber_count(1), 1);
+
+    // A refund releases the place too
+    set_balance(contract_id(), 1000);
+    assert_eq!(contract.refund(), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(contract.tier_subscriber_count(1), 0);
+}
+
 /// Test the contract owner can flip the status of the contract
 /// Can pause and unpause the contract
 #[ink::test]
@@ -187,6 +575,38 @@ fn flip_contract_status_works() {
     assert_eq!(contract.paused_or_not(), false);
 }
 
+/// Test the state fingerprint follows the state changes
+#[ink::test]
+fn state_fingerprint_works() {
+    let mut contract = make_contract();
+    let mut other_contract = make_contract();
+    let accounts = get_accounts();
+
+    // Same changes, same fingerprint
+    let fingerprint = contract.state_fingerprint();
+    assert_ne!(fingerprint, Hash::default());
+    assert_eq!(other_contract.state_fingerprint(), fingerprint);
+
+    // Queries do not change the fingerprint
+    contract.get_all_tiers();
+    assert_eq!(contract.state_fingerprint(), fingerprint);
+
+    // Failed messages do not change the fingerprint
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(contract.add_inspector(accounts.bob), Err(Error::OnlyOwner));
+    undo_set_exec_context();
+    assert_eq!(contract.state_fingerprint(), fingerprint);
+
+    // Divergent changes lead to different fingerprints
+    contract.add_inspector(accounts.bob).unwrap();
+    other_contract.add_inspector(accounts.charlie).unwrap();
+    assert_ne!(contract.state_fingerprint(), fingerprint);
+    assert_ne!(
+        contract.state_fingerprint(),
+        other_contract.state_fingerprint()
+    );
+}
+
 /// Test the contract owner can transfer all the balance out of the contract after it is paused
 #[ink::test]
 fn withdraw_works() {
@@ -200,7 +620,7 @@ fn withdraw_works() {
 
     // Non-owner cannot withdraw.
     set_exec_context(accounts.bob, 2);
-    assert_eq!(contract.withdraw(accounts.bob, 200), Err(OnlyOwner));
+    assert_eq!(contract.withdraw(accounts.bob, 200), Err(MissingRole));
     assert_eq!(balance_of(contract_id()), 1000);
     undo_set_exec_context(); // Back to Alice owner.
 
@@ -223,6 +643,43 @@ fn withdraw_works() {
     assert_eq!(contract.balance_of_contract(), 800);
 }
 
+#[ink::test]
+fn withdraw_is_locked_during_disputes() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let day_ms = MS_PER_DAY;
+    set_balance(contract_id(), 1000);
+    set_balance(accounts.bob, 0);
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.report_metrics(app_id, day_ms, 10, 10, 10).unwrap();
+    set_exec_context(app_id, 0);
+    contract
+        .dispute_metric_at_time(day_ms, Hash::from([1; 32]), day_ms)
+        .unwrap();
+    undo_set_exec_context();
+
+    // Only the allowance can be withdrawn while the dispute is open
+    assert_eq!(contract.withdraw(accounts.bob, 1), Err(WithdrawLocked));
+    contract.set_dispute_withdraw_allowance(100).unwrap();
+    assert_eq!(contract.get_dispute_withdraw_allowance(), 100);
+    assert_eq!(contract.withdraw(accounts.bob, 60), Ok(()));
+    assert_eq!(contract.withdraw(accounts.bob, 60), Err(WithdrawLocked));
+    assert_eq!(contract.withdraw(accounts.bob, 40), Ok(()));
+    assert_eq!(balance_of(accounts.bob), 100);
+
+    // The lock is lifted once the disputes are resolved
+    contract.grant_role(accounts.django, Role::Arbiter).unwrap();
+    set_exec_context(accounts.django, 0);
+    contract
+        .resolve_dispute_at_time(app_id, day_ms, None, day_ms)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.withdraw(accounts.bob, 500), Ok(()));
+    assert_eq!(balance_of(accounts.bob), 600);
+}
+
 fn set_exec_context(caller: AccountId, endowement: Balance) {
     let callee = ink_env::account_id::<DefaultEnvironment>().unwrap_or([0x0; 32].into());
     test::push_execution_context::<Environment>(
@@ -256,6 +713,20 @@ fn get_median_works() {
     assert_eq!(get_median(vec), Some(7));
 }
 
+#[ink::test]
+fn get_weighted_median_works() {
+    assert_eq!(get_weighted_median(vec![]), None);
+    assert_eq!(
+        get_weighted_median(vec![(3, 1), (1, 1), (2, 1), (4, 1)]),
+        Some(2)
+    );
+    assert_eq!(
+        get_weighted_median(vec![(3, 1), (1, 1), (2, 1), (4, 5)]),
+        Some(4)
+    );
+    assert_eq!(get_weighted_median(vec![(3, 2), (1, 1), (2, 1)]), Some(3));
+}
+
 #[ink::test]
 fn get_median_by_key_works() {
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -277,37 +748,181 @@ fn get_median_by_key_works() {
 }
 
 #[ink::test]
-fn report_metrics_works() {
+fn inspector_delegation_works() {
     let mut contract = make_contract();
     let accounts = get_accounts();
-    let inspector_id = accounts.alice;
-    let app_id = accounts.charlie;
+    let delegator = accounts.django;
+    let app_id = AccountId::from([0x42; 32]);
 
-    let mut metrics = MetricValue {
-        storage_bytes: 11,
-        wcu_used: 12,
-        rcu_used: 13,
-        start_ms: 0,
-    };
-    let mut big_metrics = MetricValue {
-        storage_bytes: 100,
-        wcu_used: 101,
-        rcu_used: 102,
-        start_ms: 0,
-    };
-    let mut double_big_metrics = MetricValue {
-        storage_bytes: 200,
-        wcu_used: 202,
-        rcu_used: 204,
-        start_ms: 0,
-    };
-    // Note: the values of start_ms will be updated to use in assert_eq!
+    set_exec_context(delegator, 300);
+    assert_eq!(
+        contract.delegate_to_inspector(accounts.charlie),
+        Err(Error::InspectorNotFound)
+    );
+    undo_set_exec_context();
 
-    let some_day = 9999;
-    let period_start_ms = some_day / PERIOD_DAYS * PERIOD_MS;
+    for (inspector, value) in [
+        (accounts.alice, 10),
+        (accounts.bob, 20),
+        (accounts.charlie, 30),
+    ]
+    .iter()
+    {
+        contract.add_inspector(*inspector).unwrap();
+        set_exec_context(*inspector, 0);
+        for day_ms in [0, MS_PER_DAY].iter() {
+            contract
+                .report_metrics(app_id, *day_ms, *value, *value, *value)
+                .unwrap();
+        }
+        undo_set_exec_context();
+    }
 
-    let today_ms = some_day * MS_PER_DAY; // Midnight time on some day.
-    let today_key = MetricKey {
+    set_exec_context(delegator, 300);
+    assert_eq!(contract.delegate_to_inspector(accounts.charlie), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(contract.delegation_of(delegator, accounts.charlie), 300);
+    assert_eq!(contract.inspector_stake_of(accounts.charlie), 300);
+
+    // Delegations count once the owner sets the stake per weight unit
+    assert_eq!(contract.inspector_weight(accounts.charlie), 1);
+    assert_eq!(
+        contract
+            .preview_day_aggregate(app_id, 0)
+            .unwrap()
+            .metrics
+            .storage_bytes,
+        20
+    );
+    contract.set_stake_per_weight(100).unwrap();
+    assert_eq!(contract.inspector_weight(accounts.charlie), 4);
+
+    // The delegation counts from the next day
+    assert_eq!(
+        contract
+            .preview_day_aggregate(app_id, 0)
+            .unwrap()
+            .metrics
+            .storage_bytes,
+        20
+    );
+    assert_eq!(
+        contract
+            .preview_day_aggregate(app_id, MS_PER_DAY)
+            .unwrap()
+            .metrics
+            .storage_bytes,
+        30
+    );
+
+    // Undelegate
+    set_balance(contract_id(), 1000);
+    set_balance(delegator, 0);
+    set_exec_context(delegator, 0);
+    assert_eq!(
+        contract.undelegate_at_time(accounts.charlie, 301, MS_PER_DAY),
+        Err(Error::InsufficientBalance)
+    );
+    assert_eq!(
+        contract.undelegate_at_time(accounts.charlie, 200, MS_PER_DAY),
+        Ok(())
+    );
+    assert_eq!(contract.delegation_of(delegator, accounts.charlie), 100);
+    assert_eq!(contract.inspector_weight(accounts.charlie), 2);
+
+    // The weights of the past days are kept
+    assert_eq!(
+        contract
+            .preview_day_aggregate(app_id, MS_PER_DAY)
+            .unwrap()
+            .metrics
+            .storage_bytes,
+        30
+    );
+
+    // The undelegated tokens are withdrawn after the delay
+    let unlock_ms = MS_PER_DAY + UNDELEGATION_DELAY_MS;
+    assert_eq!(
+        contract.undelegation_of(delegator, accounts.charlie),
+        (200, unlock_ms)
+    );
+    assert_eq!(
+        contract.withdraw_undelegated_at_time(accounts.charlie, unlock_ms - 1),
+        Err(Error::StakeLocked)
+    );
+    assert_eq!(
+        contract.withdraw_undelegated_at_time(accounts.charlie, unlock_ms),
+        Ok(())
+    );
+    assert_eq!(balance_of(delegator), 200);
+    assert_eq!(
+        contract.withdraw_undelegated_at_time(accounts.charlie, unlock_ms),
+        Err(Error::ZeroBalance)
+    );
+    undo_set_exec_context();
+}
+
+#[ink::test]
+fn report_metrics_deduplication_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let inspector_id = accounts.alice;
+    let app_id = accounts.charlie;
+
+    contract.add_inspector(inspector_id).unwrap();
+    contract.set_inspector_rebate(1).unwrap();
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    contract.report_metrics(app_id, 0, 11, 12, 13).unwrap();
+    let events_before = recorded_events().count();
+    let fingerprint_before = contract.state_fingerprint();
+
+    // An identical resubmission is a no-op
+    assert_eq!(contract.report_metrics(app_id, 0, 11, 12, 13), Ok(()));
+    assert_eq!(recorded_events().count(), events_before);
+    assert_eq!(contract.state_fingerprint(), fingerprint_before);
+    assert_eq!(contract.inspector_rebate_of(inspector_id), 1);
+
+    // Differing values are a correction, not credited again on the same day
+    assert_eq!(contract.report_metrics(app_id, 0, 11, 12, 14), Ok(()));
+    assert_eq!(recorded_events().count(), events_before + 1);
+    assert_eq!(contract.inspector_rebate_of(inspector_id), 1);
+}
+
+#[ink::test]
+fn report_metrics_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let inspector_id = accounts.alice;
+    let app_id = accounts.charlie;
+
+    let mut metrics = MetricValue {
+        storage_bytes: 11,
+        wcu_used: 12,
+        rcu_used: 13,
+        start_ms: 0,
+    };
+    let mut big_metrics = MetricValue {
+        storage_bytes: 100,
+        wcu_used: 101,
+        rcu_used: 102,
+        start_ms: 0,
+    };
+    let mut double_big_metrics = MetricValue {
+        storage_bytes: 200,
+        wcu_used: 202,
+        rcu_used: 204,
+        start_ms: 0,
+    };
+    // Note: the values of start_ms will be updated to use in assert_eq!
+
+    let some_day = 9999;
+    let period_start_ms = some_day / PERIOD_DAYS * PERIOD_MS;
+
+    let today_ms = some_day * MS_PER_DAY; // Midnight time on some day.
+    let today_key = MetricKey {
         inspector: inspector_id,
         app_id,
         day_of_period: some_day % PERIOD_DAYS,
@@ -435,6 +1050,19 @@ fn report_metrics_works() {
     metrics.start_ms = next_month_ms;
     assert_eq!(contract.metrics.get(&next_month_key), Some(&metrics));
 
+    // A late report for the previous period cannot overwrite the slot.
+    assert_eq!(
+        contract.report_metrics(
+            app_id,
+            today_ms,
+            big_metrics.storage_bytes,
+            big_metrics.wcu_used,
+            big_metrics.rcu_used,
+        ),
+        Err(Error::StalePeriod)
+    );
+    assert_eq!(contract.metrics.get(&next_month_key), Some(&metrics));
+
     // Some other account has no metrics.
     let other_key = MetricKey {
         inspector: inspector_id,
@@ -444,6 +1072,39 @@ fn report_metrics_works() {
     assert_eq!(contract.metrics.get(&other_key), None);
 }
 
+#[ink::test]
+fn enforce_not_stale_works() {
+    let day_ms = 100 * MS_PER_DAY;
+    let stored = MetricValue {
+        start_ms: day_ms,
+        storage_bytes: 1,
+        wcu_used: 1,
+        rcu_used: 1,
+    };
+
+    // Days of the current period window
+    assert_eq!(enforce_not_stale(day_ms, day_ms, None), Ok(()));
+    assert_eq!(
+        enforce_not_stale(day_ms, day_ms + PERIOD_MS - 1, None),
+        Ok(())
+    );
+    assert_eq!(enforce_not_stale(day_ms, day_ms, Some(&stored)), Ok(()));
+    assert_eq!(
+        enforce_not_stale(day_ms + PERIOD_MS, day_ms, Some(&stored)),
+        Ok(())
+    );
+
+    // The slot of the day belongs to the next period
+    assert_eq!(
+        enforce_not_stale(day_ms, day_ms + PERIOD_MS, None),
+        Err(Error::StalePeriod)
+    );
+    assert_eq!(
+        enforce_not_stale(day_ms - PERIOD_MS, day_ms, Some(&stored)),
+        Err(Error::StalePeriod)
+    );
+}
+
 #[ink::test]
 fn get_current_period_days_works() {
     const D: u64 = 10007; // A random day.
@@ -454,7 +1115,8 @@ fn get_current_period_days_works() {
         assert_eq!(
             get_current_period_days(
                 subscription_day * MS_PER_DAY + some_time,
-                now_day * MS_PER_DAY + another_time
+                now_day * MS_PER_DAY + another_time,
+                PERIOD_DAYS
             ),
             (period_day, now_day)
         );
@@ -1271,6 +1933,61 @@ fn report_metrics_median_works() {
     );
 }
 
+#[ink::test]
+fn preview_day_aggregate_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let day_ms = 9999 * MS_PER_DAY;
+
+    assert_eq!(
+        contract.preview_day_aggregate(app_id, day_ms + 1),
+        Err(Error::UnexpectedTimestamp)
+    );
+
+    // No reports yet
+    assert_eq!(
+        contract.preview_day_aggregate(app_id, day_ms),
+        Ok(DayAggregate {
+            metrics: MetricValue {
+                start_ms: day_ms,
+                storage_bytes: 0,
+                wcu_used: 0,
+                rcu_used: 0,
+            },
+            inspectors_count: 0,
+        })
+    );
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.add_inspector(accounts.bob).unwrap();
+    contract.add_inspector(accounts.django).unwrap();
+
+    contract.report_metrics(app_id, day_ms, 1, 2, 3).unwrap();
+    set_exec_context(accounts.bob, 0);
+    contract.report_metrics(app_id, day_ms, 10, 20, 30).unwrap();
+    undo_set_exec_context();
+    set_exec_context(accounts.django, 0);
+    contract
+        .report_metrics(app_id, day_ms, 100, 200, 300)
+        .unwrap();
+    undo_set_exec_context();
+
+    // The median of 3 reports
+    assert_eq!(
+        contract.preview_day_aggregate(app_id, day_ms),
+        Ok(DayAggregate {
+            metrics: MetricValue {
+                start_ms: day_ms,
+                storage_bytes: 10,
+                wcu_used: 20,
+                rcu_used: 30,
+            },
+            inspectors_count: 3,
+        })
+    );
+}
+
 #[ink::test]
 fn metrics_since_subscription_works() {
     let mut contract = make_contract();
@@ -1313,6 +2030,47 @@ fn metrics_since_subscription_works() {
     );
 }
 
+#[ink::test]
+fn check_limits_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    assert_eq!(contract.check_limits(app_id), Err(Error::NoSubscription));
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    let all_ok = LimitCheck {
+        storage_ok: true,
+        wcu_ok: true,
+        rcu_ok: true,
+    };
+    assert_eq!(contract.check_limits(app_id), Ok(all_ok));
+
+    // Each dimension is compared with its own limit
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.report_metrics(app_id, 0, 2000, 2001, 10).unwrap();
+    assert_eq!(
+        contract.check_limits(app_id),
+        Ok(LimitCheck {
+            wcu_ok: false,
+            ..all_ok
+        })
+    );
+
+    contract.report_metrics(app_id, 0, 2001, 10, 2001).unwrap();
+    assert_eq!(
+        contract.check_limits(app_id),
+        Ok(LimitCheck {
+            storage_ok: false,
+            wcu_ok: true,
+            rcu_ok: false,
+        })
+    );
+}
+
 #[ink::test]
 fn metrics_for_period_works() {
     let mut contract = make_contract();
@@ -1390,6 +2148,93 @@ fn metrics_for_period_works() {
     assert_eq!(total_metric.wcu_used, 32 + 0 + 34 + 35 + 36);
 }
 
+#[ink::test]
+fn no_report_policy_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let day1_ms = 9999 * MS_PER_DAY;
+    let day3_ms = day1_ms + 2 * MS_PER_DAY;
+    let now_ms = day3_ms + 1;
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.report_metrics(app_id, day1_ms, 0, 5, 0).unwrap();
+    assert_eq!(contract.get_no_report_policy(), NoReportPolicy::TreatAsZero);
+
+    // Only the owner can change the policy
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.set_no_report_policy(NoReportPolicy::CarryForward),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+
+    // Days 2 and 3 without report count as zero
+    let metrics = contract.metrics_for_period(app_id, day1_ms, now_ms);
+    assert_eq!(metrics.wcu_used, 5);
+    assert!(contract.is_period_billable(app_id, day1_ms, now_ms));
+
+    // Days 2 and 3 count as day 1
+    contract
+        .set_no_report_policy(NoReportPolicy::CarryForward)
+        .unwrap();
+    let metrics = contract.metrics_for_period(app_id, day1_ms, now_ms);
+    assert_eq!(metrics.wcu_used, 15);
+    assert!(contract.is_period_billable(app_id, day1_ms, now_ms));
+
+    // Day 2 is completed without report
+    contract
+        .set_no_report_policy(NoReportPolicy::MarkUnbillable)
+        .unwrap();
+    assert_eq!(
+        contract.get_no_report_policy(),
+        NoReportPolicy::MarkUnbillable
+    );
+    assert!(!contract.is_period_billable(app_id, day1_ms, now_ms));
+
+    contract
+        .report_metrics(app_id, day1_ms + MS_PER_DAY, 0, 5, 0)
+        .unwrap();
+    assert!(contract.is_period_billable(app_id, day1_ms, now_ms));
+}
+
+#[ink::test]
+fn unbillable_period_is_not_charged() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.report_metrics(app_id, 0, 0, 5, 0).unwrap();
+    contract
+        .set_no_report_policy(NoReportPolicy::MarkUnbillable)
+        .unwrap();
+
+    // Day 1 is completed without report, the settlement does not charge the app
+    contract
+        .actualize_subscriptions_at_time(PERIOD_MS / 2)
+        .unwrap();
+    assert_eq!(contract.balance_of(app_id), 2);
+    assert_eq!(contract.get_total_ddc_balance(), 0);
+
+    // The app is listed with the export of the period
+    contract
+        .commit_period_export_at_time(0, "cid".to_string(), PERIOD_MS)
+        .unwrap();
+    assert_eq!(contract.get_period_unbillable_apps(0), vec![app_id]);
+
+    // It is charged for its usage afterwards with another policy
+    contract
+        .set_no_report_policy(NoReportPolicy::TreatAsZero)
+        .unwrap();
+    contract.actualize_subscriptions_at_time(PERIOD_MS).unwrap();
+    assert_eq!(contract.balance_of(app_id), 1);
+    assert_eq!(contract.get_total_ddc_balance(), 1);
+}
+
 #[ink::test]
 fn finalize_metric_period_works() {
     let mut contract = make_contract();
@@ -1572,6 +2417,7 @@ fn add_ddc_node_ddn_manager_works() {
 #[ink::test]
 fn add_ddc_node_works() {
     let mut contract = make_contract();
+    let accounts = get_accounts();
     let p2p_id = String::from("test_p2p_id");
     let p2p_addr = String::from("test_p2p_addr");
     let url = String::from("test_url");
@@ -1594,6 +2440,11 @@ fn add_ddc_node_works() {
             p2p_addr: p2p_addr.clone(),
             url: url.clone(),
             permissions: DDC_NODE_PERMISSION_TRUSTED,
+            namespace: 0,
+            operator: accounts.alice,
+            storage_capacity_bytes: 0,
+            region: String::new(),
+            tier_support: vec![],
         },]
     );
 
@@ -1619,6 +2470,7 @@ fn add_ddc_node_works() {
 #[ink::test]
 fn add_ddn_node_update_url_works() {
     let mut contract = make_contract();
+    let accounts = get_accounts();
     let p2p_id = String::from("test_p2p_id");
     let p2p_addr = String::from("test_p2p_addr");
     let url = String::from("test_url");
@@ -1634,11 +2486,59 @@ fn add_ddn_node_update_url_works() {
         )
         .unwrap();
 
+    // The first update is allowed right away, but not the next one
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            p2p_addr.clone(),
+            url.clone(),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    assert_eq!(
+        contract.add_ddc_node(p2p_id.clone(), p2p_addr.clone(), new_url.clone(), 0),
+        Err(Error::NodeUpdateTooSoon)
+    );
+
+    // Removing and adding the node again is still an update
+    contract.remove_ddc_node(p2p_id.clone()).unwrap();
+    assert_eq!(
+        contract.add_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone(), 0),
+        Err(Error::NodeUpdateTooSoon)
+    );
+
+    // Update block time from 0 to 10
+    contract.set_node_update_cooldown(10).unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+
     // Update DDC node url and permissions.
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            p2p_addr.clone(),
+            url.clone(),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
     contract
         .add_ddc_node(p2p_id.clone(), p2p_addr.clone(), new_url.clone(), 0)
         .unwrap();
 
+    // Should emit the old and new values
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeUpdated(NodeUpdated {
+        old_url, new_url, ..
+    }) = decode_event(&raw_events[raw_events.len() - 2])
+    {
+        assert_eq!(old_url, url);
+        assert_eq!(new_url, String::from("test_url_new"));
+    } else {
+        panic!("Wrong event type")
+    }
+
     // Get the list of DDC nodes
     assert_eq!(
         contract.get_all_ddc_nodes(),
@@ -1647,6 +2547,11 @@ fn add_ddn_node_update_url_works() {
             p2p_addr,
             url: new_url,
             permissions: 0,
+            namespace: 0,
+            operator: accounts.alice,
+            storage_capacity_bytes: 0,
+            region: String::new(),
+            tier_support: vec![],
         }]
     );
 }
@@ -1762,14 +2667,260 @@ fn remove_ddc_node_works() {
     }
 }
 
-// ---- DDN Statuses ----
-
 #[ink::test]
-fn get_ddn_status_not_found_works() {
-    let contract = make_contract();
-    let p2p_id = String::from("test_p2p_id");
+fn namespaces_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let admin = accounts.bob;
+    let app_id = accounts.charlie;
+    let node = |p2p_id: &str| String::from(p2p_id);
 
-    // Should return an error if not found
+    // Only the owner can delegate a namespace
+    set_exec_context(admin, 0);
+    assert_eq!(
+        contract.add_namespace_admin(1, admin),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    contract.add_namespace_admin(1, admin).unwrap();
+    assert!(contract.is_namespace_admin(1, admin));
+    assert!(!contract.is_namespace_admin(2, admin));
+
+    contract
+        .add_ddc_node(node("n0"), node("addr"), node("url"), 0)
+        .unwrap();
+
+    set_exec_context(admin, 0);
+    // Tiers of the delegated namespace only
+    assert_eq!(contract.add_tier_in(1, 16, 100, 100, 100), Ok(4));
+    assert_eq!(
+        contract.add_tier_in(2, 16, 100, 100, 100),
+        Err(Error::OnlyNamespaceAdmin)
+    );
+    // Nodes of the delegated namespace only
+    assert_eq!(
+        contract.add_ddc_node_in(1, node("n1"), node("addr"), node("url"), 0),
+        Ok(())
+    );
+    assert_eq!(
+        contract.add_ddc_node_in(2, node("n2"), node("addr"), node("url"), 0),
+        Err(Error::OnlyDDNManager)
+    );
+    assert_eq!(
+        contract.add_ddc_node_in(1, node("n0"), node("addr"), node("url"), 0),
+        Err(Error::OnlyDDNManager)
+    );
+    assert_eq!(
+        contract.remove_ddc_node(node("n0")),
+        Err(Error::OnlyDDNManager)
+    );
+    undo_set_exec_context();
+
+    assert_eq!(contract.get_tiers_in(0).len(), 3);
+    assert_eq!(contract.get_tiers_in(1).len(), 1);
+    assert_eq!(contract.get_ddc_nodes_in(0).len(), 1);
+    assert_eq!(contract.get_ddc_nodes_in(1).len(), 1);
+    assert_eq!(contract.get_ddc_nodes_in(2).len(), 0);
+
+    // Updating a node keeps its namespace
+    contract.set_node_update_cooldown(0).unwrap();
+    contract
+        .add_ddc_node(node("n1"), node("addr2"), node("url"), 0)
+        .unwrap();
+    assert_eq!(contract.get_ddc_nodes_in(1).len(), 1);
+
+    // An app belongs to the namespace of its tier
+    assert_eq!(
+        contract.namespace_of_app(app_id),
+        Err(Error::NoSubscription)
+    );
+    set_exec_context(app_id, 16);
+    contract.subscribe(4).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.namespace_of_app(app_id), Ok(1));
+
+    set_exec_context(admin, 0);
+    assert_eq!(contract.remove_ddc_node(node("n1")), Ok(()));
+    undo_set_exec_context();
+
+    contract.remove_namespace_admin(1, admin).unwrap();
+    assert!(!contract.is_namespace_admin(1, admin));
+}
+
+#[ink::test]
+fn update_capacity_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    assert_eq!(
+        contract.update_capacity(p2p_id.clone(), 100, 10, 10),
+        Err(Error::DDNNotFound)
+    );
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.update_capacity(p2p_id.clone(), 100, 10, 10),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+
+    // The operator of the node declares its capacity
+    contract
+        .set_ddc_node_operator(p2p_id.clone(), accounts.charlie)
+        .unwrap();
+    assert_eq!(contract.capacity_at(p2p_id.clone(), 0), None);
+    set_exec_context(accounts.charlie, 0);
+    contract
+        .update_capacity(p2p_id.clone(), 100, 10, 10)
+        .unwrap();
+    undo_set_exec_context();
+
+    // Update block time from 0 to 10
+    advance_block::<DefaultEnvironment>().unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+    contract
+        .update_capacity(p2p_id.clone(), 200, 20, 20)
+        .unwrap();
+
+    assert_eq!(
+        contract.capacity_at(p2p_id.clone(), 9),
+        Some(NodeCapacity {
+            effective_ms: 0,
+            storage_bytes: 100,
+            wcu_per_minute: 10,
+            rcu_per_minute: 10,
+        })
+    );
+    assert_eq!(
+        contract
+            .capacity_at(p2p_id.clone(), 10)
+            .unwrap()
+            .storage_bytes,
+        200
+    );
+    assert_eq!(contract.get_capacity_history(p2p_id.clone()).len(), 2);
+
+    // The history is bounded
+    for i in 0..MAX_CAPACITY_HISTORY as u64 {
+        advance_block::<DefaultEnvironment>().unwrap();
+        contract.update_capacity(p2p_id.clone(), i, i, i).unwrap();
+    }
+    let history = contract.get_capacity_history(p2p_id.clone());
+    assert_eq!(history.len(), MAX_CAPACITY_HISTORY);
+    assert_eq!(history[0].storage_bytes, 0);
+    assert_eq!(contract.capacity_at(p2p_id.clone(), 0), None);
+
+    contract.remove_ddc_node(p2p_id.clone()).unwrap();
+    assert_eq!(contract.get_capacity_history(p2p_id), vec![]);
+}
+
+#[ink::test]
+fn set_node_payout_address_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    assert_eq!(
+        contract.set_node_payout_address(p2p_id.clone(), accounts.django),
+        Err(Error::DDNNotFound)
+    );
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+
+    // Only the operator of the node or a DDN manager can set the payout address
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.set_node_payout_address(p2p_id.clone(), accounts.django),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+
+    assert_eq!(
+        contract.set_node_payout_address(p2p_id.clone(), AccountId::default()),
+        Err(Error::InvalidAccount)
+    );
+    assert_eq!(
+        contract.set_node_payout_address(p2p_id.clone(), accounts.django),
+        Ok(())
+    );
+
+    // The address is not effective immediately
+    assert_eq!(contract.get_node_payout_address(p2p_id.clone()), None);
+    assert_eq!(
+        contract
+            .node_payout_addresses
+            .get(&p2p_id)
+            .unwrap()
+            .at_time(ADDRESS_CHANGE_DELAY_MS),
+        Some(accounts.django)
+    );
+
+    // The payout address is forgotten with the node
+    contract.remove_ddc_node(p2p_id.clone()).unwrap();
+    assert_eq!(contract.node_payout_addresses.get(&p2p_id), None);
+}
+
+/// Test the operator of a node sets its payout address without being a DDN manager
+#[ink::test]
+fn set_node_payout_address_by_operator_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let operator = accounts.bob;
+
+    contract.add_ddn_manager(operator).unwrap();
+    set_exec_context(operator, 0);
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    undo_set_exec_context();
+    contract.remove_ddn_manager(operator).unwrap();
+
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.set_node_payout_address(p2p_id.clone(), accounts.django),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract
+            .node_payout_addresses
+            .get(&p2p_id)
+            .unwrap()
+            .at_time(ADDRESS_CHANGE_DELAY_MS),
+        Some(accounts.django)
+    );
+}
+
+// ---- DDN Statuses ----
+
+#[ink::test]
+fn get_ddn_status_not_found_works() {
+    let contract = make_contract();
+    let p2p_id = String::from("test_p2p_id");
+
+    // Should return an error if not found
     assert_eq!(contract.get_ddn_status(p2p_id), Err(Error::DDNNotFound));
 }
 
@@ -1913,6 +3064,80 @@ fn report_ddn_status_unexpected_timestamp_works() {
     );
 }
 
+#[ink::test]
+fn inspector_rebate_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let inspector = accounts.alice;
+    let p2p_id = "test_p2p_id".to_string();
+
+    contract.add_inspector(inspector).unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            "test_p2p_addr".to_string(),
+            "test_url".to_string(),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+
+    // No rebate by default
+    contract.report_ddn_status(p2p_id.clone(), true).unwrap();
+    assert_eq!(contract.inspector_rebate_of(inspector), 0);
+
+    // Only the owner can set the rebate
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(contract.set_inspector_rebate(3), Err(Error::OnlyOwner));
+    undo_set_exec_context();
+    assert_eq!(contract.set_inspector_rebate(3), Ok(()));
+    assert_eq!(contract.get_inspector_rebate(), 3);
+
+    // The reports of a node are credited once per day
+    contract.report_ddn_status(p2p_id.clone(), false).unwrap();
+    contract
+        .report_metrics_ddn(p2p_id.clone(), 0, 1, 2, 3)
+        .unwrap();
+    assert_eq!(contract.inspector_rebate_of(inspector), 3);
+    contract
+        .report_metrics_ddn(p2p_id.clone(), MS_PER_DAY, 1, 2, 3)
+        .unwrap();
+    assert_eq!(contract.inspector_rebate_of(inspector), 6);
+
+    // The reports of an app are credited once per day, if it is subscribed
+    let app_id = accounts.charlie;
+    contract.report_metrics(app_id, 0, 1, 2, 3).unwrap();
+    assert_eq!(contract.inspector_rebate_of(inspector), 6);
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    contract.report_metrics(app_id, 0, 4, 5, 6).unwrap();
+    contract.report_metrics(app_id, 0, 7, 8, 9).unwrap();
+    assert_eq!(contract.inspector_rebate_of(inspector), 9);
+
+    // Rejected reports are not credited
+    assert_eq!(
+        contract.report_ddn_status("unknown".to_string(), true),
+        Err(Error::DDNNotFound)
+    );
+    assert_eq!(contract.inspector_rebate_of(inspector), 9);
+
+    // Nothing to claim while the pool is empty
+    assert_eq!(contract.claim_inspector_rebate(), Err(Error::ZeroBalance));
+
+    set_exec_context(accounts.bob, 4);
+    contract.fund_inspector_reward_pool().unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.get_inspector_reward_pool(), 4);
+
+    // The claim is capped by the pool, the rest remains credited
+    set_balance(contract_id(), 1000);
+    let balance_before = balance_of(inspector);
+    assert_eq!(contract.claim_inspector_rebate(), Ok(()));
+    assert_eq!(balance_of(inspector), balance_before + 4);
+    assert_eq!(contract.get_inspector_reward_pool(), 0);
+    assert_eq!(contract.inspector_rebate_of(inspector), 5);
+}
+
 #[ink::test]
 fn report_ddn_status_works() {
     let mut contract = make_contract();
@@ -2319,6 +3544,46 @@ fn report_ddn_status_median_works() {
     );
 }
 
+#[ink::test]
+fn ddn_status_majority_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = "test_p2p_id".to_string();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            "test_p2p_addr".to_string(),
+            "test_url".to_string(),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    for inspector in [accounts.alice, accounts.bob, accounts.charlie].iter() {
+        contract.add_inspector(*inspector).unwrap();
+    }
+
+    // One lying inspector does not flip the node offline
+    for (inspector, is_online) in [
+        (accounts.alice, true),
+        (accounts.bob, true),
+        (accounts.charlie, false),
+    ]
+    .iter()
+    {
+        set_exec_context(*inspector, 0);
+        contract
+            .report_ddn_status(p2p_id.clone(), *is_online)
+            .unwrap();
+        undo_set_exec_context();
+    }
+    assert!(contract.get_ddn_status(p2p_id.clone()).unwrap().is_online);
+
+    // A majority does
+    set_exec_context(accounts.bob, 0);
+    contract.report_ddn_status(p2p_id.clone(), false).unwrap();
+    undo_set_exec_context();
+    assert!(!contract.get_ddn_status(p2p_id).unwrap().is_online);
+}
+
 #[ink::test]
 fn report_metrics_updates_ddn_status_works() {
     let mut contract = make_contract();
@@ -3436,148 +4701,4147 @@ fn refund_works() {
 }
 
 #[ink::test]
-#[should_panic(expected = "Transfer has failed!")]
-fn refund_failed_works() {
+fn refund_fee_works() {
     let mut contract = make_contract();
-    let caller = AccountId::from([0x1; 32]);
-    set_exec_context(caller, 2);
+    let app = get_accounts().charlie;
+
+    assert_eq!(contract.set_refund_fee(10_001), Err(Error::InvalidShare));
+    assert_eq!(contract.set_refund_fee(1_000), Ok(()));
+    assert_eq!(contract.get_refund_fee(), 1_000);
 
+    set_exec_context(app, 100);
     contract.subscribe(1).unwrap();
+    set_balance(contract_id(), 1000);
+    set_balance(app, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    undo_set_exec_context();
 
-    assert_eq!(contract.refund(), Ok(())); // contract account doesn't have enough balance to refund. should panic
+    // 10% of the balance is retained as revenue
+    assert_eq!(balance_of(app), 90);
+    assert_eq!(contract.total_ddc_balance, 10);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::Refunded(Refunded {
+        app: refunded_app,
+        value,
+        fee,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(refunded_app, app);
+        assert_eq!(value, 90);
+        assert_eq!(fee, 10);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // A fee overflowing the balance type fails without touching the balance
+    contract.subscriptions.get_mut(&app).unwrap().balance = Balance::MAX;
+    set_exec_context(app, 0);
+    assert_eq!(contract.refund(), Err(Error::ArithmeticError));
+    undo_set_exec_context();
+    assert_eq!(
+        contract.subscriptions.get(&app).unwrap().balance,
+        Balance::MAX
+    );
 }
 
 #[ink::test]
-fn get_app_limit_works() {
+fn subscribe_app_works() {
     let mut contract = make_contract();
     let accounts = get_accounts();
-    let app_id = accounts.alice;
-    let now = 0;
-    let later = now + 45 * MS_PER_DAY;
+    let payer = accounts.bob;
 
+    // The apps must approve their payer
+    set_exec_context(payer, 2);
     assert_eq!(
-        contract.get_app_limit_at_time(app_id, 0),
-        Err(Error::NoSubscription)
+        contract.subscribe_app(accounts.charlie, 1),
+        Err(Error::NoPermission)
     );
-
-    set_exec_context(accounts.alice, 4);
-
-    contract.subscribe(2).unwrap();
-
+    for app_id in [accounts.charlie, accounts.django].iter() {
+        set_exec_context(*app_id, 0);
+        assert_eq!(contract.set_app_payer(Some(payer)), Ok(()));
+    }
+    set_exec_context(payer, 2);
+    assert_eq!(contract.subscribe_app(accounts.charlie, 1), Ok(()));
+    assert_eq!(contract.subscribe_app(accounts.django, 1), Ok(()));
+    // A top up keeps the payer
+    assert_eq!(contract.subscribe_app(accounts.django, 1), Ok(()));
     assert_eq!(
-        contract.get_app_limit_at_time(app_id, 0),
-        Ok(AppSubscriptionLimit::new(4000, 4000, 4000,))
+        contract.payer_funds.get(&accounts.django),
+        Some(&vec![(payer, 4)])
     );
 
+    let mut apps = contract.get_payer_apps(payer);
+    apps.sort();
+    let mut expected = vec![accounts.charlie, accounts.django];
+    expected.sort();
+    assert_eq!(apps, expected);
+    assert_eq!(contract.get_app_payer(accounts.charlie), Some(payer));
+    assert_eq!(contract.balance_of(accounts.django), 4);
     assert_eq!(
-        contract.get_app_limit_at_time(app_id, later),
-        Err(NoFreeTier)
+        contract.get_app_limit_at_time(accounts.charlie, 0),
+        Ok(AppSubscriptionLimit::new(2000, 2000, 2000))
     );
 
-    contract.add_tier(0, 1000, 1000, 1000).unwrap();
+    // Another payer cannot take over the apps
+    set_exec_context(accounts.eve, 2);
+    assert_eq!(
+        contract.subscribe_app(accounts.charlie, 1),
+        Err(Error::NoPermission)
+    );
+    assert_eq!(
+        contract.refund_app(accounts.charlie),
+        Err(Error::NoPermission)
+    );
 
+    // The refund returns to the payer what it paid, and the rest to the app
+    set_exec_context(accounts.charlie, 2);
+    contract.subscribe(1).unwrap();
+    set_balance(contract_id(), 1000);
+    set_balance(payer, 0);
+    set_balance(accounts.charlie, 0);
+    set_exec_context(payer, 0);
+    assert_eq!(contract.refund_app(accounts.charlie), Ok(()));
+    assert_eq!(balance_of(payer), 2);
+    assert_eq!(balance_of(accounts.charlie), 2);
+    assert_eq!(contract.balance_of(accounts.charlie), 0);
+    assert!(contract.payer_funds.get(&accounts.charlie).is_none());
+
+    // A revoked payer cannot pay anymore
+    set_exec_context(accounts.django, 0);
+    assert_eq!(contract.set_app_payer(None), Ok(()));
+    set_exec_context(payer, 2);
     assert_eq!(
-        contract.get_app_limit_at_time(app_id, later),
-        Ok(AppSubscriptionLimit::new(1000, 1000, 1000,))
+        contract.subscribe_app(accounts.django, 1),
+        Err(Error::NoPermission)
     );
 }
 
 #[ink::test]
-fn actualize_subscriptions_works() {
-    let accounts = get_accounts();
+fn subscribe_for_works() {
     let mut contract = make_contract();
+    let accounts = get_accounts();
+    let reseller = accounts.bob;
+    let app = accounts.charlie;
+
+    set_exec_context(reseller, 2);
+    assert_eq!(contract.top_up_for(app), Err(Error::NoSubscription));
+    assert_eq!(contract.subscribe_for(app, 1), Ok(()));
+    assert_eq!(contract.top_up_for(app), Ok(()));
+    // The tier of the app cannot be changed by others
+    assert_eq!(contract.subscribe_for(app, 2), Err(Error::TierMismatch));
+    undo_set_exec_context();
+
+    assert_eq!(contract.tier_id_of(app), 1);
+    assert_eq!(contract.balance_of(app), 4);
+    assert_eq!(contract.balance_of(reseller), 0);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::Deposit(Deposit { from, to, value }) = decode_event(raw_events.last().unwrap()) {
+        assert_eq!(from, Some(reseller));
+        assert_eq!(to, app);
+        assert_eq!(value, 2);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn set_refund_address_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+
+    set_exec_context(app, 2);
+    contract.subscribe(1).unwrap();
+    assert_eq!(
+        contract.set_refund_address(AccountId::default()),
+        Err(Error::InvalidAccount)
+    );
+    assert_eq!(contract.set_refund_address(accounts.django), Ok(()));
+
+    // The new address is not effective immediately
+    assert_eq!(contract.get_refund_address(app), None);
+
+    // So the refund still goes to the app
+    set_balance(contract_id(), 1000);
+    set_balance(app, 0);
+    set_balance(accounts.django, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    assert_eq!(balance_of(app), 2);
+    assert_eq!(balance_of(accounts.django), 0);
+}
+
+#[ink::test]
+fn delayed_address_works() {
+    let accounts = get_accounts();
+    let mut address = DelayedAddress::default();
+    assert_eq!(address.at_time(0), None);
+
+    address.schedule(accounts.bob, 10, 100);
+    assert_eq!(address.at_time(109), None);
+    assert_eq!(address.at_time(110), Some(accounts.bob));
+
+    // A change keeps the previous address until it becomes effective
+    address.schedule(accounts.charlie, 200, 100);
+    assert_eq!(address.at_time(299), Some(accounts.bob));
+    assert_eq!(address.at_time(300), Some(accounts.charlie));
+}
+
+#[ink::test]
+#[should_panic(expected = "Transfer has failed!")]
+fn refund_failed_works() {
+    let mut contract = make_contract();
+    let caller = AccountId::from([0x1; 32]);
+    set_exec_context(caller, 2);
 
-    let alice = accounts.alice;
-    set_exec_context(alice, 2);
     contract.subscribe(1).unwrap();
+
+    assert_eq!(contract.refund(), Ok(())); // contract account doesn't have enough balance to refund. should panic
+}
+
+#[ink::test]
+fn export_import_subscriptions_works() {
+    let mut old_contract = make_contract();
+    let accounts = get_accounts();
+
+    set_exec_context(accounts.bob, 2);
+    old_contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    set_exec_context(accounts.charlie, 8);
+    old_contract.subscribe(3).unwrap();
     undo_set_exec_context();
 
-    let bob = accounts.bob;
-    set_exec_context(bob, 4);
-    contract.subscribe(2).unwrap();
+    // Only the owner can export
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        old_contract.export_subscriptions(0, 10),
+        Err(Error::OnlyOwner)
+    );
     undo_set_exec_context();
 
-    let charlie = accounts.charlie;
-    set_exec_context(charlie, 8);
-    contract.subscribe(3).unwrap();
+    let first_page = old_contract.export_subscriptions(0, 1).unwrap();
+    assert_eq!(first_page.total, 2);
+    assert_eq!(first_page.next_cursor, Some(1));
+    let second_page = old_contract.export_subscriptions(1, 1).unwrap();
+    assert_eq!(second_page.next_cursor, None);
 
-    assert_eq!(contract.actualize_subscriptions(), Err(Error::OnlyOwner));
+    let mut new_contract = make_contract();
+    assert_eq!(
+        new_contract.import_subscriptions(vec![vec![1, 2, 3]]),
+        Err(Error::InvalidRecord)
+    );
+    // The tiers must have room for the imported apps
+    for tier_id in [1, 3].iter() {
+        new_contract
+            .set_tier_max_subscribers(*tier_id, Some(0))
+            .unwrap();
+    }
+    assert_eq!(
+        new_contract.import_subscriptions(second_page.items.clone()),
+        Err(Error::TierFull)
+    );
+    for tier_id in [1, 3].iter() {
+        new_contract
+            .set_tier_max_subscribers(*tier_id, None)
+            .unwrap();
+    }
+
+    assert_eq!(
+        new_contract.import_subscriptions(first_page.items.clone()),
+        Ok(())
+    );
+    assert_eq!(new_contract.import_subscriptions(second_page.items), Ok(()));
+    assert_eq!(new_contract.storage_footprint(accounts.bob).items, 1);
 
+    // Existing subscriptions are not overwritten
+    assert_eq!(
+        new_contract.import_subscriptions(first_page.items),
+        Err(Error::AlreadySubscribed)
+    );
+
+    for app_id in [accounts.bob, accounts.charlie].iter() {
+        assert_eq!(
+            new_contract.get_subscription_details_of(*app_id),
+            old_contract.get_subscription_details_of(*app_id)
+        );
+    }
+
+    // No more imports once finished
+    assert!(!new_contract.is_import_finished());
+    new_contract.finish_import().unwrap();
+    assert!(new_contract.is_import_finished());
+    let page = old_contract.export_subscriptions(0, 10).unwrap();
+    assert_eq!(
+        new_contract.import_subscriptions(page.items),
+        Err(Error::ImportFinished)
+    );
+}
+
+#[ink::test]
+fn grant_access_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let node = String::from("gateway");
+    let read = 1;
+    let write = 2;
+
+    set_exec_context(app_id, 2);
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 100, read, 0),
+        Err(Error::NoSubscription)
+    );
+    contract.subscribe(1).unwrap();
     undo_set_exec_context();
 
-    contract.actualize_subscriptions().unwrap();
+    set_exec_context(app_id, 0);
+    // The grant must be short-lived
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 0, read, 0),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), MS_PER_DAY + 1, read, 0),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 100, read, 0),
+        Ok(())
+    );
+    undo_set_exec_context();
 
-    assert_eq!(contract.get_total_ddc_balance(), 0);
+    assert!(contract.is_access_valid_at_time(app_id, node.clone(), read, 99));
+    assert!(!contract.is_access_valid_at_time(app_id, node.clone(), read | write, 99));
+    assert!(!contract.is_access_valid_at_time(app_id, node.clone(), read, 100));
+    assert!(!contract.is_access_valid_at_time(app_id, "other".to_string(), read, 99));
 
-    let mut subscription = contract.subscriptions.get(&alice).unwrap().clone();
-    let tier = contract.tier_limit_of(alice);
+    set_exec_context(app_id, 0);
+    contract.revoke_access(node.clone()).unwrap();
+    undo_set_exec_context();
+    assert!(!contract.is_access_valid_at_time(app_id, node, read, 99));
+}
 
-    let middle_of_period = PERIOD_MS / 2;
-    let end_of_period = PERIOD_MS;
+#[ink::test]
+fn subscribe_sponsored_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let sponsor = accounts.bob;
+    let app = accounts.charlie;
+
+    // The app is not sponsored yet
+    set_exec_context(app, 0);
+    assert_eq!(contract.subscribe_sponsored(1), Err(Error::NotSponsored));
+    undo_set_exec_context();
 
+    // The sponsor funds its pool and whitelists the app, which accepts
+    set_exec_context(sponsor, 3);
+    contract.sponsor_deposit().unwrap();
+    contract.add_sponsored_app(app).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.sponsor_pool_of(sponsor), 3);
+    assert_eq!(contract.sponsor_of(app), None);
+    set_exec_context(app, 0);
     assert_eq!(
-        Ddc::actualize_subscription_at_time(middle_of_period, &mut subscription, &tier),
-        1
+        contract.accept_sponsor(accounts.django),
+        Err(Error::NotSponsored)
     );
+    assert_eq!(contract.accept_sponsor(sponsor), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(contract.sponsor_of(app), Some(sponsor));
 
+    // Another sponsor cannot take over the app
+    set_exec_context(accounts.django, 0);
     assert_eq!(
-        Ddc::actualize_subscription_at_time(end_of_period, &mut subscription, &tier),
-        1
+        contract.add_sponsored_app(app),
+        Err(Error::AppAlreadySponsored)
     );
+    assert_eq!(contract.remove_sponsored_app(app), Err(Error::NotSponsored));
+    undo_set_exec_context();
 
-    let mut subscription = contract.subscriptions.get(&bob).unwrap().clone();
-    let tier = contract.tier_limit_of(bob);
+    // The app subscribes without paying, the fee is taken from the pool
+    set_exec_context(app, 0);
+    assert_eq!(contract.subscribe_sponsored(1), Ok(()));
+    assert_eq!(contract.balance_of(app), 2);
+    assert_eq!(contract.sponsor_pool_of(sponsor), 1);
+    assert_eq!(
+        contract.subscribe_sponsored(1),
+        Err(Error::InsufficientBalance)
+    );
+
+    // A refund goes back to the pool of the sponsor, up to what it paid
+    undo_set_exec_context();
+    set_exec_context(app, 4);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    set_balance(contract_id(), 1000);
+    set_balance(app, 0);
+    set_exec_context(app, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    assert_eq!(contract.sponsor_pool_of(sponsor), 3);
+    assert_eq!(balance_of(app), 4);
+    undo_set_exec_context();
+
+    // The sponsor withdraws its funds and revokes the app
+    set_balance(contract_id(), 1000);
+    set_exec_context(sponsor, 0);
+    assert_eq!(
+        contract.sponsor_withdraw(4),
+        Err(Error::InsufficientBalance)
+    );
+    assert_eq!(contract.sponsor_withdraw(3), Ok(()));
+    assert_eq!(contract.sponsor_pool_of(sponsor), 0);
+    assert_eq!(contract.remove_sponsored_app(app), Ok(()));
+    undo_set_exec_context();
+
+    set_exec_context(app, 0);
+    assert_eq!(contract.subscribe_sponsored(1), Err(Error::NotSponsored));
+    undo_set_exec_context();
+
+    // A free tier can be sponsored from an empty pool
+    let free_tier_id = contract.add_tier(0, 1000, 1000, 1000).unwrap();
+    set_exec_context(accounts.eve, 0);
+    contract.add_sponsored_app(app).unwrap();
+    undo_set_exec_context();
+    set_exec_context(app, 0);
+    contract.accept_sponsor(accounts.eve).unwrap();
+    assert_eq!(contract.subscribe_sponsored(free_tier_id), Ok(()));
+    assert_eq!(contract.sponsor_pool_of(accounts.eve), 0);
+}
 
+/// Test the records of the funds paid by third parties stay within the balance of the app
+#[ink::test]
+fn app_funds_are_capped_to_the_balance() {
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+    let mut funds = StorageHashMap::new();
+
+    Ddc::add_app_funds(&mut funds, app, accounts.bob, 2, 2);
+    Ddc::add_app_funds(&mut funds, app, accounts.django, 2, 4);
+    // The app consumed 3 since, the oldest funds first
+    Ddc::add_app_funds(&mut funds, app, accounts.bob, 2, 3);
     assert_eq!(
-        Ddc::actualize_subscription_at_time(middle_of_period, &mut subscription, &tier),
-        2
+        funds.get(&app),
+        Some(&vec![(accounts.django, 1), (accounts.bob, 2)])
     );
 
+    // Only the remaining balance goes back to the funders
+    let mut value = 2;
     assert_eq!(
-        Ddc::actualize_subscription_at_time(end_of_period, &mut subscription, &tier),
-        2
+        Ddc::take_app_funds(&mut funds, app, &mut value),
+        vec![(accounts.bob, 2)]
     );
+    assert_eq!(value, 0);
+    assert!(funds.get(&app).is_none());
+}
 
-    let mut subscription = contract.subscriptions.get(&charlie).unwrap().clone();
-    let tier = contract.tier_limit_of(charlie);
+#[ink::test]
+fn get_app_limit_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.alice;
+    let now = 0;
+    let later = now + 45 * MS_PER_DAY;
 
     assert_eq!(
-        Ddc::actualize_subscription_at_time(middle_of_period, &mut subscription, &tier),
-        4
+        contract.get_app_limit_at_time(app_id, 0),
+        Err(Error::NoSubscription)
+    );
+
+    set_exec_context(accounts.alice, 4);
+
+    contract.subscribe(2).unwrap();
+
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(4000, 4000, 4000,))
     );
 
     assert_eq!(
-        Ddc::actualize_subscription_at_time(end_of_period, &mut subscription, &tier),
-        4
+        contract.get_app_limit_at_time(app_id, later),
+        Err(NoFreeTier)
+    );
+
+    contract.add_tier(0, 1000, 1000, 1000).unwrap();
+
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, later),
+        Ok(AppSubscriptionLimit::new(1000, 1000, 1000,))
     );
 }
 
 #[ink::test]
-fn get_subscription_details_of() {
+fn get_app_limit_uses_tier_snapshot_works() {
+    let mut contract = make_contract();
     let accounts = get_accounts();
+    let app_id = accounts.alice;
+
+    set_exec_context(app_id, 4);
+    contract.subscribe(2).unwrap();
+    undo_set_exec_context();
+
+    // Changing the tier limits does not affect the already paid subscription
+    contract.change_tier_limit(2, 100, 100, 100).unwrap();
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(4000, 4000, 4000))
+    );
+
+    // The new limits apply once the app renews its subscription
+    set_exec_context(app_id, 4);
+    contract.subscribe(2).unwrap();
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(100, 100, 100))
+    );
+}
+
+#[ink::test]
+fn low_balance_works() {
     let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.alice;
 
-    let alice = accounts.alice;
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    // Disabled by default
+    contract.actualize_subscriptions().unwrap();
+    let events_before = recorded_events().count();
 
+    set_exec_context(accounts.bob, 0);
     assert_eq!(
-        contract.get_subscription_details_of(alice),
-        Err(Error::NoSubscription)
+        contract.set_low_balance_threshold(40),
+        Err(Error::OnlyOwner)
     );
+    undo_set_exec_context();
 
-    set_exec_context(alice, 2);
+    // 31 days left is above the threshold
+    contract.set_low_balance_threshold(31).unwrap();
+    assert_eq!(contract.get_low_balance_threshold(), 31);
+    contract.actualize_subscriptions().unwrap();
+    assert_eq!(recorded_events().count(), events_before);
+
+    contract.set_low_balance_threshold(40).unwrap();
+    contract.actualize_subscriptions().unwrap();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    assert_eq!(raw_events.len(), events_before + 1);
+    if let Event::LowBalance(LowBalance {
+        app,
+        remaining_days,
+    }) = decode_event(&raw_events[events_before])
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(remaining_days, 31);
+    } else {
+        panic!("Wrong event type");
+    }
+
+    // No warning on a free tier
+    contract.change_tier_fee(1, 0).unwrap();
+    let events_before = recorded_events().count();
+    assert_eq!(contract.actualize_subscriptions(), Ok(()));
+    assert_eq!(recorded_events().count(), events_before);
+}
+
+#[ink::test]
+fn rounding_policy_div_works() {
+    let check = |numerator, floor, ceil, bankers| {
+        assert_eq!(RoundingPolicy::Floor.div(numerator, 4), floor);
+        assert_eq!(RoundingPolicy::Ceil.div(numerator, 4), ceil);
+        assert_eq!(RoundingPolicy::Bankers.div(numerator, 4), bankers);
+    };
+
+    check(8, 2, 2, 2); // Exact
+    check(9, 2, 3, 2); // 2.25
+    check(10, 2, 3, 2); // 2.5, to even
+    check(11, 2, 3, 3); // 2.75
+    check(14, 3, 4, 4); // 3.5, to even
+}
+
+#[ink::test]
+fn rounding_policy_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    set_exec_context(app_id, 2);
     contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    let subscription = contract.subscriptions.get(&app_id).unwrap().clone();
+    let tier = contract.get_tier_limit(1);
 
+    // A quarter of the fee unit is consumed
+    let quarter_ms = PERIOD_MS / 8;
+    let consumed = |rounding| {
+        Ddc::get_consumed_balance_at_time(quarter_ms, &subscription, &tier, rounding, PERIOD_MS)
+    };
+    assert_eq!(consumed(RoundingPolicy::Floor), Ok(0));
+    assert_eq!(consumed(RoundingPolicy::Ceil), Ok(1));
+    assert_eq!(consumed(RoundingPolicy::Bankers), Ok(0));
+
+    set_exec_context(accounts.bob, 0);
     assert_eq!(
-        contract.get_subscription_details_of(alice).unwrap(),
-        AppSubscriptionDetails {
-            subscription: AppSubscription {
-                start_date_ms: 0,
-                tier_id: 1,
+        contract.set_rounding_policy(RoundingPolicy::Ceil),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.get_rounding_policy(), RoundingPolicy::Floor);
+    assert_eq!(contract.set_rounding_policy(RoundingPolicy::Ceil), Ok(()));
+    assert_eq!(contract.get_rounding_policy(), RoundingPolicy::Ceil);
 
-                balance: 2,
-                last_update_ms: 0,
-            },
-            end_date_ms: 2678400000
-        }
+    // Whole periods are not affected
+    assert_eq!(
+        contract
+            .get_subscription_details_of(app_id)
+            .unwrap()
+            .end_date_ms,
+        PERIOD_MS
+    );
+}
+
+#[ink::test]
+fn spending_cap_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.set_spending_cap(Some(4)),
+        Err(Error::NoSubscription)
     );
+    undo_set_exec_context();
+
+    set_exec_context(app_id, 4);
+    contract.subscribe(2).unwrap();
+    assert_eq!(contract.set_spending_cap(Some(4)), Ok(()));
+    assert_eq!(contract.get_spending_cap(app_id), Some(4));
+
+    // Upgrades must respect the cap
+    set_exec_context(app_id, 8);
+    assert_eq!(contract.subscribe(3), Err(Error::SpendingCapExceeded));
+    assert_eq!(contract.change_tier(3), Err(Error::SpendingCapExceeded));
+    assert_eq!(contract.change_tier(1), Ok(()));
+    assert_eq!(contract.change_tier(2), Ok(()));
+
+    // Lowering the cap below the fee suspends the service
+    let events_before = recorded_events().count();
+    assert_eq!(contract.set_spending_cap(Some(3)), Ok(()));
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    assert_eq!(raw_events.len(), events_before + 1);
+    if let Event::CapReached(CapReached {
+        app,
+        spending_cap,
+        tier_fee,
+    }) = decode_event(&raw_events[events_before])
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(spending_cap, 3);
+        assert_eq!(tier_fee, 4);
+    } else {
+        panic!("Wrong event type")
+    }
+    undo_set_exec_context();
+
+    contract.add_tier(0, 10, 10, 10).unwrap();
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(10, 10, 10))
+    );
+
+    // Nothing is charged while suspended
+    let mut subscription = contract.subscriptions.get(&app_id).unwrap().clone();
+    let tier = contract.get_tier_limit(2);
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            PERIOD_MS,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(0)
+    );
+    assert_eq!(subscription.balance, 4);
+
+    set_exec_context(app_id, 0);
+    assert_eq!(contract.set_spending_cap(None), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(4000, 4000, 4000))
+    );
+}
+
+#[ink::test]
+fn actualize_subscriptions_works() {
+    let accounts = get_accounts();
+    let mut contract = make_contract();
+
+    let alice = accounts.alice;
+    set_exec_context(alice, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    let bob = accounts.bob;
+    set_exec_context(bob, 4);
+    contract.subscribe(2).unwrap();
+    undo_set_exec_context();
+
+    let charlie = accounts.charlie;
+    set_exec_context(charlie, 8);
+    contract.subscribe(3).unwrap();
+
+    assert_eq!(contract.actualize_subscriptions(), Err(Error::OnlyOwner));
+
+    undo_set_exec_context();
+
+    contract.actualize_subscriptions().unwrap();
+
+    assert_eq!(contract.get_total_ddc_balance(), 0);
+
+    let mut subscription = contract.subscriptions.get(&alice).unwrap().clone();
+    let tier = contract.tier_limit_of(alice);
+
+    let middle_of_period = PERIOD_MS / 2;
+    let end_of_period = PERIOD_MS;
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            middle_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(1)
+    );
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            end_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(1)
+    );
+
+    let mut subscription = contract.subscriptions.get(&bob).unwrap().clone();
+    let tier = contract.tier_limit_of(bob);
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            middle_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(2)
+    );
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            end_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(2)
+    );
+
+    let mut subscription = contract.subscriptions.get(&charlie).unwrap().clone();
+    let tier = contract.tier_limit_of(charlie);
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            middle_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(4)
+    );
+
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            end_of_period,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(4)
+    );
+}
+
+#[ink::test]
+fn billing_math_is_checked() {
+    let accounts = get_accounts();
+    let mut contract = make_contract();
+
+    // A free tier never ends, instead of dividing by zero
+    let free_tier_id = contract.add_tier(0, 1000, 1000, 1000).unwrap();
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(contract.subscribe(free_tier_id), Ok(()));
+    undo_set_exec_context();
+    let details = contract.get_subscription_details_of(accounts.bob).unwrap();
+    assert_eq!(details.end_date_ms, u64::MAX);
+    assert_eq!(
+        contract.actualize_subscriptions_at_time(10 * PERIOD_MS),
+        Ok(())
+    );
+
+    // A balance too large to be represented as an end date never ends either
+    let mut subscription = details.subscription.clone();
+    subscription.tier_id = 1;
+    subscription.balance = Balance::MAX;
+    assert_eq!(contract.get_end_date_ms(&subscription), u64::MAX);
+
+    // Overflowing charges are reported instead of wrapping around
+    let tier = ServiceTier {
+        tier_fee: Balance::MAX,
+        ..contract.get_tier_limit(1)
+    };
+    subscription.last_update_ms = 0;
+    assert_eq!(
+        Ddc::get_consumed_balance_at_time(
+            2 * PERIOD_MS,
+            &subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Err(Error::ArithmeticError)
+    );
+    assert_eq!(
+        Ddc::actualize_subscription_at_time(
+            2 * PERIOD_MS,
+            &mut subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Err(Error::ArithmeticError)
+    );
+}
+
+#[ink::test]
+fn get_subscription_details_of() {
+    let accounts = get_accounts();
+    let mut contract = make_contract();
+
+    let alice = accounts.alice;
+
+    assert_eq!(
+        contract.get_subscription_details_of(alice),
+        Err(Error::NoSubscription)
+    );
+
+    set_exec_context(alice, 2);
+    contract.subscribe(1).unwrap();
+
+    assert_eq!(
+        contract.get_subscription_details_of(alice).unwrap(),
+        AppSubscriptionDetails {
+            subscription: AppSubscription {
+                start_date_ms: 0,
+                tier_id: 1,
+
+                balance: 2,
+                last_update_ms: 0,
+
+                tier_limit: AppSubscriptionLimit::new(2000, 2000, 2000),
+                spending_cap: None,
+
+                auto_renew: true,
+                renewal_end_ms: 0,
+            },
+            end_date_ms: 2678400000
+        }
+    );
+}
+
+#[ink::test]
+fn commit_period_export_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let period_start_ms = 2 * MS_PER_DAY;
+    let period_end_ms = period_start_ms + PERIOD_MS;
+    let cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string();
+
+    assert_eq!(contract.get_period_export(period_start_ms), None);
+
+    // Only the owner can commit
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.commit_period_export_at_time(period_start_ms, cid.clone(), period_end_ms),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+
+    // The period must be over
+    assert_eq!(
+        contract.commit_period_export_at_time(period_start_ms, cid.clone(), period_end_ms - 1),
+        Err(Error::PeriodNotEnded)
+    );
+    assert_eq!(
+        contract.commit_period_export_at_time(period_start_ms + 1, cid.clone(), period_end_ms),
+        Err(Error::UnexpectedTimestamp)
+    );
+
+    assert_eq!(
+        contract.commit_period_export_at_time(period_start_ms, cid.clone(), period_end_ms),
+        Ok(())
+    );
+    assert_eq!(
+        contract.get_period_export(period_start_ms),
+        Some(cid.clone())
+    );
+
+    // The commitment cannot be changed
+    assert_eq!(
+        contract.commit_period_export_at_time(period_start_ms, "other".to_string(), period_end_ms),
+        Err(Error::ExportAlreadyCommitted)
+    );
+    assert_eq!(contract.get_period_export(period_start_ms), Some(cid));
+}
+
+#[ink::test]
+fn account_overview_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let owner = accounts.alice;
+    let app_id = accounts.charlie;
+
+    contract.add_inspector(owner).unwrap();
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    set_exec_context(accounts.django, 5);
+    contract.delegate_to_inspector(owner).unwrap();
+    undo_set_exec_context();
+
+    let overview = contract.account_overview(owner).unwrap();
+    assert!(overview.is_owner);
+    assert!(overview.is_inspector);
+    assert!(!overview.is_ddn_manager);
+    assert_eq!(overview.subscription, None);
+    assert_eq!(overview.inspector_stake, 5);
+
+    set_exec_context(app_id, 0);
+    let overview = contract.account_overview(app_id).unwrap();
+    undo_set_exec_context();
+    assert!(!overview.is_owner);
+    assert!(!overview.is_inspector);
+    assert_eq!(
+        overview.subscription,
+        contract.get_subscription_details_of(app_id).ok()
+    );
+    assert_eq!(overview.unsettled_consumption, 0);
+    assert_eq!(overview.sponsor, None);
+
+    set_exec_context(accounts.django, 0);
+    let overview = contract.account_overview(accounts.django).unwrap();
+    undo_set_exec_context();
+    assert_eq!(overview.delegated, 5);
+    assert_eq!(overview.payout_nodes, Vec::<String>::new());
+}
+
+#[ink::test]
+fn guardian_claim_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let guardian = accounts.bob;
+    let inactivity_ms = 30 * MS_PER_DAY;
+
+    contract
+        .set_guardian(Some(guardian), inactivity_ms)
+        .unwrap();
+    assert_eq!(contract.get_guardian(), Some(guardian));
+
+    // Admin actions of the owner are tracked
+    advance_block::<DefaultEnvironment>().unwrap();
+    contract.set_low_balance_threshold(3).unwrap();
+    assert_eq!(contract.get_last_owner_activity(), 5);
+
+    // Only the guardian can claim
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.announce_guardian_claim_at_time(5 + inactivity_ms),
+        Err(Error::OnlyGuardian)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(guardian, 0);
+    // Should not announce while the owner is active
+    assert_eq!(
+        contract.announce_guardian_claim_at_time(5 + inactivity_ms - 1),
+        Err(Error::OwnerStillActive)
+    );
+    // Should not claim before announcing
+    assert_eq!(
+        contract.claim_ownership_at_time(5 + inactivity_ms),
+        Err(Error::OwnerStillActive)
+    );
+
+    let announced_ms = 5 + inactivity_ms;
+    assert_eq!(
+        contract.announce_guardian_claim_at_time(announced_ms),
+        Ok(())
+    );
+    assert_eq!(
+        contract.claim_ownership_at_time(announced_ms + GUARDIAN_CLAIM_DELAY_MS - 1),
+        Err(Error::ClaimNotReady)
+    );
+
+    // An action of the owner after the announcement cancels the claim
+    contract.last_owner_activity_ms = announced_ms + 1;
+    assert_eq!(
+        contract.claim_ownership_at_time(announced_ms + GUARDIAN_CLAIM_DELAY_MS),
+        Err(Error::OwnerStillActive)
+    );
+    contract.last_owner_activity_ms = 5;
+
+    assert_eq!(
+        contract.claim_ownership_at_time(announced_ms + GUARDIAN_CLAIM_DELAY_MS),
+        Ok(())
+    );
+    assert_eq!(contract.only_owner(), Ok(()));
+    assert_eq!(contract.get_guardian(), None);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::GuardianClaimAnnounced(GuardianClaimAnnounced {
+        guardian: event_guardian,
+        claimable_ms,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_guardian, guardian);
+        assert_eq!(claimable_ms, announced_ms + GUARDIAN_CLAIM_DELAY_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+    undo_set_exec_context();
+
+    // The previous owner lost its rights
+    assert_eq!(contract.only_owner(), Err(Error::OnlyOwner));
+}
+
+#[ink::test]
+fn payout_eligibility_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let period_start_ms = 1000 * MS_PER_DAY;
+
+    contract.add_inspector(accounts.alice).unwrap();
+    for day in &[0, 3, PERIOD_DAYS + 1] {
+        contract
+            .report_metrics_ddn(p2p_id.clone(), period_start_ms + day * MS_PER_DAY, 1, 2, 3)
+            .unwrap();
+    }
+
+    assert_eq!(
+        contract.set_min_reported_days(PERIOD_DAYS + 1),
+        Err(Error::TooManyDays)
+    );
+    assert_eq!(contract.set_min_reported_days(2), Ok(()));
+    assert_eq!(contract.get_min_reported_days(), 2);
+
+    // The report of the next period does not count
+    let expected = PayoutEligibility {
+        reported_days: vec![period_start_ms, period_start_ms + 3 * MS_PER_DAY],
+        required_days: 2,
+        eligible: true,
+    };
+    assert_eq!(
+        contract.payout_eligibility(p2p_id.clone(), period_start_ms),
+        Ok(expected.clone())
+    );
+
+    contract.set_min_reported_days(3).unwrap();
+    assert_eq!(
+        contract.payout_eligibility(p2p_id.clone(), period_start_ms),
+        Ok(PayoutEligibility {
+            required_days: 3,
+            eligible: false,
+            ..expected
+        })
+    );
+
+    assert_eq!(
+        contract.payout_eligibility(p2p_id, period_start_ms + 1),
+        Err(Error::UnexpectedTimestamp)
+    );
+}
+
+#[ink::test]
+fn attest_usage_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let day_start_ms = 1000 * MS_PER_DAY;
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .report_metrics(app_id, day_start_ms, 1000, 100, 100)
+        .unwrap();
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.attest_usage(day_start_ms, 1010, 100, 100),
+        Err(Error::NoSubscription)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    assert_eq!(
+        contract.attest_usage(day_start_ms + 1, 1010, 100, 100),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(contract.attest_usage(day_start_ms, 1010, 100, 100), Ok(()));
+    undo_set_exec_context();
+
+    // Should not be attested without tolerance
+    assert_eq!(contract.is_day_attested(app_id, day_start_ms), Ok(false));
+
+    assert_eq!(
+        contract.set_attestation_tolerance(BPS + 1),
+        Err(Error::InvalidShare)
+    );
+    contract.set_attestation_tolerance(100).unwrap();
+    assert_eq!(contract.get_attestation_tolerance(), 100);
+    assert_eq!(contract.is_day_attested(app_id, day_start_ms), Ok(true));
+
+    // Days without an attestation or without reports are not attested
+    let next_day_ms = day_start_ms + MS_PER_DAY;
+    assert_eq!(contract.is_day_attested(app_id, next_day_ms), Ok(false));
+    set_exec_context(app_id, 0);
+    contract.attest_usage(next_day_ms, 0, 0, 0).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.is_day_attested(app_id, next_day_ms), Ok(false));
+
+    // An attestation from a previous period is ignored
+    let next_period_ms = day_start_ms + PERIOD_MS;
+    assert_eq!(contract.is_day_attested(app_id, next_period_ms), Ok(false));
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::UsageAttested(UsageAttested {
+        app_id: event_app_id,
+        day_start_ms: event_day_start_ms,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_app_id, app_id);
+        assert_eq!(event_day_start_ms, next_day_ms);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn roles_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let manager = accounts.bob;
+
+    set_exec_context(manager, 0);
+    assert_eq!(contract.add_tier(1, 1, 1, 1), Err(Error::MissingRole));
+    assert_eq!(contract.flip_contract_status(), Err(Error::MissingRole));
+    assert_eq!(
+        contract.grant_role(manager, Role::Pauser),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+
+    contract.grant_role(manager, Role::TierManager).unwrap();
+    contract.grant_role(manager, Role::NodeManager).unwrap();
+    assert!(contract.has_role(manager, Role::TierManager));
+    assert!(!contract.has_role(manager, Role::Pauser));
+
+    set_exec_context(manager, 0);
+    assert_eq!(contract.add_tier(1, 1, 1, 1), Ok(4));
+    assert_eq!(contract.change_tier_fee(4, 2), Ok(()));
+    assert_eq!(
+        contract.add_ddc_node(
+            String::from("test_p2p_id"),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        ),
+        Ok(())
+    );
+    // Roles are distinct
+    assert_eq!(contract.flip_contract_status(), Err(Error::MissingRole));
+    assert_eq!(contract.withdraw(manager, 1), Err(Error::MissingRole));
+    undo_set_exec_context();
+
+    contract.revoke_role(manager, Role::TierManager).unwrap();
+    assert!(!contract.has_role(manager, Role::TierManager));
+
+    set_exec_context(manager, 0);
+    assert_eq!(contract.add_tier(1, 1, 1, 1), Err(Error::MissingRole));
+    undo_set_exec_context();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::RoleRevoked(RoleRevoked { account, role }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(account, manager);
+        assert_eq!(role, Role::TierManager);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn storage_footprint_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let node = String::from("gateway");
+
+    contract.set_storage_deposit(3).unwrap();
+    assert_eq!(contract.get_storage_deposit(), 3);
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    // Renewals do not create items
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.storage_footprint(app_id),
+        StorageFootprint {
+            items: 1,
+            deposit: 0
+        }
+    );
+
+    // A new access grant requires the deposit
+    set_exec_context(app_id, 2);
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 100, 1, 0),
+        Err(Error::InsufficientDeposit)
+    );
+    undo_set_exec_context();
+    set_exec_context(app_id, 3);
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 100, 1, 0),
+        Ok(())
+    );
+    undo_set_exec_context();
+    // Replacing it does not
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.grant_access_at_time(node.clone(), 200, 1, 0),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.storage_footprint(app_id),
+        StorageFootprint {
+            items: 2,
+            deposit: 3
+        }
+    );
+
+    // The deposit is returned on cleanup
+    set_balance(contract_id(), 1000);
+    set_balance(app_id, 0);
+    set_exec_context(app_id, 0);
+    contract.revoke_access(node).unwrap();
+    undo_set_exec_context();
+    assert_eq!(balance_of(app_id), 3);
+    assert_eq!(
+        contract.storage_footprint(app_id),
+        StorageFootprint {
+            items: 1,
+            deposit: 0
+        }
+    );
+
+    // Reports are attributed to the inspector
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .report_metrics(app_id, 1000 * MS_PER_DAY, 1, 1, 1)
+        .unwrap();
+    contract
+        .report_metrics(app_id, 1000 * MS_PER_DAY, 2, 2, 2)
+        .unwrap();
+    assert_eq!(contract.storage_footprint(accounts.alice).items, 1);
+}
+
+/// Test a DDC node is charged to its operator, even when admitted by another account
+#[ink::test]
+fn node_storage_footprint_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let operator = accounts.bob;
+
+    contract.add_ddn_manager(operator).unwrap();
+    contract.set_target_node_count(Some(0)).unwrap();
+    set_exec_context(operator, 0);
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.storage_footprint(operator).items, 0);
+
+    // The owner makes room, which admits the queued node
+    contract.set_target_node_count(None).unwrap();
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+    assert_eq!(contract.storage_footprint(accounts.alice).items, 0);
+    assert_eq!(contract.storage_footprint(operator).items, 1);
+
+    // The item follows the operator, and is released with the node
+    contract
+        .set_ddc_node_operator(p2p_id.clone(), accounts.charlie)
+        .unwrap();
+    assert_eq!(contract.storage_footprint(operator).items, 0);
+    assert_eq!(contract.storage_footprint(accounts.charlie).items, 1);
+    contract.remove_ddc_node(p2p_id).unwrap();
+    assert_eq!(contract.storage_footprint(accounts.charlie).items, 0);
+}
+
+#[ink::test]
+fn error_code_works() {
+    let contract = make_contract();
+
+    // Codes are part of the API and must never change
+    assert_eq!(contract.error_code(Error::OnlyOwner), 0);
+    assert_eq!(contract.error_code(Error::InsufficientBalance), 8);
+    assert_eq!(contract.error_code(Error::MissingRole), 38);
+
+    // Codes are contiguous
+    let mut code = 0;
+    while let Some(error) = contract.decode_error(code) {
+        assert_eq!(contract.error_code(error), code);
+        code += 1;
+    }
+    assert_eq!(code, 80);
+    assert_eq!(contract.error_code(Error::RegistrationClosed), 79);
+    assert_eq!(contract.decode_error(u16::MAX), None);
+}
+
+#[ink::test]
+fn cluster_tiers_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let cluster_id = 7;
+
+    assert_eq!(
+        contract.set_tier_availability(4, cluster_id, true),
+        Err(Error::TidOutOfBound)
+    );
+    contract.set_tier_availability(1, cluster_id, true).unwrap();
+    contract.set_tier_availability(2, cluster_id, true).unwrap();
+    contract
+        .set_tier_availability(2, cluster_id, false)
+        .unwrap();
+    let tier_ids: Vec<u64> = contract
+        .get_tiers_for_cluster(cluster_id)
+        .iter()
+        .map(|tier| tier.tier_id)
+        .collect();
+    assert_eq!(tier_ids, vec![1]);
+    assert!(contract.get_tiers_for_cluster(8).is_empty());
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.assign_app_cluster(app_id, Some(cluster_id)),
+        Err(Error::OnlyDDNManager)
+    );
+    undo_set_exec_context();
+    contract
+        .assign_app_cluster(app_id, Some(cluster_id))
+        .unwrap();
+    assert_eq!(contract.cluster_of_app(app_id), Some(cluster_id));
+
+    // Only the tiers of the cluster can be purchased
+    set_exec_context(app_id, 4);
+    assert_eq!(contract.subscribe(2), Err(Error::TierNotInCluster));
+    assert_eq!(contract.subscribe(1), Ok(()));
+    assert_eq!(contract.change_tier(3), Err(Error::TierNotInCluster));
+    undo_set_exec_context();
+
+    // Unassigned apps are not restricted
+    contract.assign_app_cluster(app_id, None).unwrap();
+    set_exec_context(app_id, 0);
+    assert_eq!(contract.change_tier(3), Ok(()));
+    undo_set_exec_context();
+}
+
+#[ink::test]
+fn deactivate_remove_tier_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    assert_eq!(contract.remove_tier(1), Err(Error::TierActive));
+    assert_eq!(contract.deactivate_tier(4), Err(Error::TidOutOfBound));
+    assert_eq!(contract.deactivate_tier(1), Ok(()));
+
+    // New subscriptions are rejected
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(contract.subscribe(1), Err(Error::TierInactive));
+    undo_set_exec_context();
+
+    // The existing subscription keeps working
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, 0),
+        Ok(AppSubscriptionLimit::new(2000, 2000, 2000))
+    );
+    set_exec_context(app_id, 0);
+    assert_eq!(contract.change_tier(1), Ok(()));
+    assert_eq!(contract.remove_tier(1), Err(Error::MissingRole));
+    undo_set_exec_context();
+    assert_eq!(contract.remove_tier(1), Err(Error::TierInUse));
+
+    // Once the app moved to another tier, the tier can be removed
+    set_exec_context(app_id, 0);
+    contract.change_tier(2).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.remove_tier(1), Ok(()));
+    assert_eq!(contract.tier_deposit(1), 0);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::TierRemoved(TierRemoved { tier_id }) = decode_event(raw_events.last().unwrap()) {
+        assert_eq!(tier_id, 1);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn read_delegation_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let subject = accounts.charlie;
+    let delegate = accounts.bob;
+
+    // Only the subject can read its private queries by default, not even the owner
+    assert_eq!(contract.account_overview(subject), Err(Error::NoReadAccess));
+    assert!(contract.can_read(subject, subject));
+    assert!(!contract.can_read(subject, delegate));
+
+    set_exec_context(subject, 0);
+    contract.grant_read_access(delegate).unwrap();
+    undo_set_exec_context();
+    assert!(contract.can_read(subject, delegate));
+
+    set_exec_context(delegate, 0);
+    assert!(contract.account_overview(subject).is_ok());
+    undo_set_exec_context();
+
+    set_exec_context(subject, 0);
+    contract.revoke_read_access(delegate).unwrap();
+    undo_set_exec_context();
+
+    set_exec_context(delegate, 0);
+    assert_eq!(contract.account_overview(subject), Err(Error::NoReadAccess));
+    undo_set_exec_context();
+}
+
+#[ink::test]
+fn tier_metadata_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+
+    let tier_id = contract
+        .add_named_tier(
+            16,
+            16000,
+            16000,
+            16000,
+            String::from("Pro"),
+            String::from("For busy apps"),
+        )
+        .unwrap();
+    assert_eq!(tier_id, 4);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::TierAdded(TierAdded {
+        name, description, ..
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(name, "Pro");
+        assert_eq!(description, "For busy apps");
+    } else {
+        panic!("Wrong event type")
+    }
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.set_tier_metadata(1, String::from("Free"), String::new()),
+        Err(Error::MissingRole)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.set_tier_metadata(5, String::from("Free"), String::new()),
+        Err(Error::TidOutOfBound)
+    );
+    contract
+        .set_tier_metadata(1, String::from("Starter"), String::from("To try DDC"))
+        .unwrap();
+
+    let tiers = contract.get_all_tiers();
+    let starter = tiers.iter().find(|tier| tier.tier_id == 1).unwrap();
+    assert_eq!(starter.name, "Starter");
+    assert_eq!(starter.description, "To try DDC");
+    let pro = tiers.iter().find(|tier| tier.tier_id == 4).unwrap();
+    assert_eq!(pro.name, "Pro");
+    // Tiers added without metadata have none
+    let tier_2 = tiers.iter().find(|tier| tier.tier_id == 2).unwrap();
+    assert_eq!(tier_2.name, "");
+}
+
+#[ink::test]
+fn set_period_days_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let week_ms = 7 * MS_PER_DAY;
+
+    assert_eq!(contract.set_period_days(0), Err(Error::InvalidPeriod));
+    assert_eq!(
+        contract.set_period_days(PERIOD_DAYS + 1),
+        Err(Error::InvalidPeriod)
+    );
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(contract.set_period_days(7), Err(Error::OnlyOwner));
+    undo_set_exec_context();
+
+    contract.set_min_reported_days(10).unwrap();
+    assert_eq!(contract.set_period_days(7), Ok(()));
+    assert_eq!(contract.get_period_days(), 7);
+    assert_eq!(contract.get_min_reported_days(), 7);
+
+    // The fee of a tier now buys a week
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    let details = contract.get_subscription_details_of(app_id).unwrap();
+    assert_eq!(details.end_date_ms, week_ms);
+
+    // Metrics are returned for the days of the period
+    let p2p_id = String::from("test_p2p_id");
+    assert_eq!(
+        contract
+            .metrics_for_ddn_at_time(p2p_id.clone(), 100 * MS_PER_DAY)
+            .len(),
+        7
+    );
+    assert_eq!(
+        contract
+            .payout_eligibility(p2p_id, 100 * MS_PER_DAY)
+            .unwrap()
+            .required_days,
+        7
+    );
+}
+
+#[ink::test]
+fn report_metrics_batch_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let inspector = accounts.alice;
+    let day_start_ms = 1000 * MS_PER_DAY;
+
+    assert_eq!(
+        contract.report_metrics_batch(vec![]),
+        Err(Error::OnlyInspector)
+    );
+    contract.add_inspector(inspector).unwrap();
+
+    // Nothing is written if any report is invalid
+    assert_eq!(
+        contract.report_metrics_batch(vec![
+            (accounts.bob, day_start_ms, 1, 2, 3),
+            (accounts.charlie, day_start_ms + 1, 1, 2, 3),
+        ]),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(
+        contract
+            .preview_day_aggregate(accounts.bob, day_start_ms)
+            .unwrap()
+            .inspectors_count,
+        0
+    );
+
+    let too_many = vec![(accounts.bob, day_start_ms, 1, 2, 3); MAX_REPORT_BATCH + 1];
+    assert_eq!(
+        contract.report_metrics_batch(too_many),
+        Err(Error::BatchTooLarge)
+    );
+
+    contract
+        .report_metrics(accounts.bob, day_start_ms, 1, 2, 3)
+        .unwrap();
+    assert_eq!(
+        contract.report_metrics_batch(vec![
+            (accounts.bob, day_start_ms, 1, 2, 3),
+            (accounts.charlie, day_start_ms, 4, 5, 6),
+        ]),
+        Ok(())
+    );
+    assert_eq!(
+        contract
+            .preview_day_aggregate(accounts.charlie, day_start_ms)
+            .unwrap()
+            .metrics,
+        MetricValue {
+            start_ms: day_start_ms,
+            storage_bytes: 4,
+            wcu_used: 5,
+            rcu_used: 6,
+        }
+    );
+
+    // One event for the batch, the identical report excluded
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::MetricsBatchReported(MetricsBatchReported {
+        inspector: event_inspector,
+        reports,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_inspector, inspector);
+        assert_eq!(reports, 1);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    let p2p_id = String::from("test_p2p_id");
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    // Nothing is written if a node is unknown
+    assert_eq!(
+        contract.report_metrics_ddn_batch(vec![
+            (p2p_id.clone(), day_start_ms, 1, 2, 3),
+            (String::from("unknown_p2p_id"), day_start_ms, 4, 5, 6),
+        ]),
+        Err(Error::DDNNotFound)
+    );
+    assert!(contract.metrics_ddn.is_empty());
+    assert!(contract.ddn_statuses.is_empty());
+
+    assert_eq!(
+        contract.report_metrics_ddn_batch(vec![
+            (p2p_id.clone(), day_start_ms, 1, 2, 3),
+            (p2p_id.clone(), day_start_ms + MS_PER_DAY, 4, 5, 6),
+        ]),
+        Ok(())
+    );
+    let now_ms = day_start_ms + MS_PER_DAY;
+    let metrics = contract.metrics_for_ddn_at_time(p2p_id.clone(), now_ms);
+    assert_eq!(metrics[metrics.len() - 2].storage_bytes, 1);
+    assert_eq!(metrics[metrics.len() - 1].storage_bytes, 4);
+    assert!(contract.get_ddn_status(p2p_id.clone()).unwrap().is_online);
+
+    // A report behind the last status of the node fails under the Reject policy
+    contract.set_clock_skew(0, SkewPolicy::Reject).unwrap();
+    contract
+        .report_ddn_status_at_time(p2p_id.clone(), true, now_ms + MS_PER_DAY)
+        .unwrap();
+    assert_eq!(
+        contract.report_metrics_ddn_batch(vec![(p2p_id.clone(), day_start_ms, 7, 8, 9)]),
+        Err(Error::UnexpectedTimestamp)
+    );
+    assert_eq!(
+        contract.report_metrics_ddn(p2p_id, day_start_ms, 7, 8, 9),
+        Err(Error::UnexpectedTimestamp)
+    );
+}
+
+#[ink::test]
+fn coverage_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let covered_app = accounts.charlie;
+    let uncovered_app = accounts.django;
+
+    for app_id in &[covered_app, uncovered_app] {
+        set_exec_context(*app_id, 2);
+        contract.subscribe(1).unwrap();
+        undo_set_exec_context();
+    }
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.add_inspector(accounts.bob).unwrap();
+    contract.report_metrics(covered_app, 0, 1, 1, 1).unwrap();
+    set_exec_context(accounts.bob, 0);
+    contract
+        .report_metrics(covered_app, MS_PER_DAY, 1, 1, 1)
+        .unwrap();
+    undo_set_exec_context();
+
+    assert_eq!(contract.coverage(covered_app, 0), Ok(2));
+    assert_eq!(contract.coverage(uncovered_app, 0), Ok(0));
+    assert_eq!(
+        contract.coverage(covered_app, 1),
+        Err(Error::UnexpectedTimestamp)
+    );
+    // Reports of another period do not count
+    assert_eq!(contract.coverage(covered_app, PERIOD_MS), Ok(0));
+
+    let now_ms = 2 * MS_PER_DAY;
+    assert_eq!(contract.get_coverage_quorum(), 1);
+    assert_eq!(
+        contract.low_coverage_apps_at_time(10, now_ms),
+        vec![(uncovered_app, 0)]
+    );
+
+    assert_eq!(contract.set_coverage_quorum(3), Ok(()));
+    assert_eq!(contract.low_coverage_apps_at_time(10, now_ms).len(), 2);
+    assert_eq!(contract.low_coverage_apps_at_time(1, now_ms).len(), 1);
+
+    // Expired subscriptions are not listed
+    assert!(contract
+        .low_coverage_apps_at_time(10, 2 * PERIOD_MS)
+        .is_empty());
+}
+
+#[ink::test]
+fn rescale_tier_limits_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.rescale_tier_limits(15_000),
+        Err(Error::MissingRole)
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.rescale_tier_limits(0), Err(Error::InvalidShare));
+
+    assert_eq!(contract.rescale_tier_limits(15_000), Ok(()));
+    assert_eq!(
+        contract.get_tier_limit(1).limit(),
+        AppSubscriptionLimit::new(3000, 3000, 3000)
+    );
+    assert_eq!(
+        contract.get_tier_limit(3).limit(),
+        AppSubscriptionLimit::new(12000, 12000, 12000)
+    );
+
+    contract.rescale_tier_limits(5_000).unwrap();
+    assert_eq!(
+        contract.get_tier_limit(1).limit(),
+        AppSubscriptionLimit::new(1500, 1500, 1500)
+    );
+
+    // One event per tier
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    let changed = raw_events
+        .iter()
+        .filter(|event| matches!(decode_event(event), Event::TierLimitChanged(_)))
+        .count();
+    assert_eq!(changed, 6);
+}
+
+#[ink::test]
+fn ddc_node_queue_works() {
+    let mut contract = make_contract();
+    let add_node = |contract: &mut Ddc, p2p_id: &str, url: &str| {
+        contract.add_ddc_node(
+            String::from(p2p_id),
+            String::from("p2p_addr"),
+            String::from(url),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+    };
+
+    contract.set_target_node_count(Some(1)).unwrap();
+    assert_eq!(contract.get_target_node_count(), Some(1));
+    add_node(&mut contract, "a", "url").unwrap();
+
+    // Further nodes are queued
+    add_node(&mut contract, "b", "url").unwrap();
+    add_node(&mut contract, "c", "url").unwrap();
+    add_node(&mut contract, "b", "new_url").unwrap();
+    assert!(contract.is_ddc_node(String::from("a")));
+    assert!(!contract.is_ddc_node(String::from("b")));
+    assert_eq!(contract.node_queue_position(String::from("b")), Some(0));
+    assert_eq!(contract.node_queue_position(String::from("c")), Some(1));
+    assert_eq!(contract.node_queue_position(String::from("a")), None);
+    assert_eq!(contract.get_node_queue()[0].url, "new_url");
+
+    // Updating a queued node does not queue it again
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeQueued(NodeQueued { p2p_id, position }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(p2p_id, "c");
+        assert_eq!(position, 1);
+    } else {
+        panic!("Wrong event type")
+    }
+    assert_eq!(contract.get_node_queue().len(), 2);
+
+    // A leaving node makes room for the first queued node
+    contract.remove_ddc_node(String::from("a")).unwrap();
+    assert!(contract.is_ddc_node(String::from("b")));
+    assert_eq!(contract.node_queue_position(String::from("c")), Some(0));
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeAdmitted(NodeAdmitted { p2p_id }) = decode_event(raw_events.last().unwrap()) {
+        assert_eq!(p2p_id, "b");
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // A queued node can leave the queue
+    add_node(&mut contract, "d", "url").unwrap();
+    contract.remove_ddc_node(String::from("d")).unwrap();
+    assert_eq!(contract.node_queue_position(String::from("d")), None);
+
+    // Raising the target admits the queued nodes
+    contract.set_target_node_count(None).unwrap();
+    assert!(contract.is_ddc_node(String::from("c")));
+    assert!(contract.get_node_queue().is_empty());
+
+    // The queue is bounded
+    contract.set_target_node_count(Some(2)).unwrap();
+    for i in 0..MAX_NODE_QUEUE_LEN {
+        add_node(&mut contract, &format!("queued_{}", i), "url").unwrap();
+    }
+    assert_eq!(
+        add_node(&mut contract, "e", "url"),
+        Err(Error::NodeQueueFull)
+    );
+    assert_eq!(contract.get_node_queue().len(), MAX_NODE_QUEUE_LEN as usize);
+}
+
+#[ink::test]
+fn metrics_bucket_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.bob;
+    let day_start_ms = 1000 * MS_PER_DAY;
+
+    assert_eq!(
+        contract.report_metrics_bucket(app_id, 7, day_start_ms, 1, 2, 3),
+        Err(Error::OnlyInspector)
+    );
+    contract.add_inspector(accounts.alice).unwrap();
+
+    assert_eq!(
+        contract.report_metrics_bucket(app_id, 7, day_start_ms + 1, 1, 2, 3),
+        Err(Error::UnexpectedTimestamp)
+    );
+
+    contract
+        .report_metrics_bucket(app_id, 7, day_start_ms, 10, 20, 30)
+        .unwrap();
+    contract
+        .report_metrics_bucket(app_id, 7, day_start_ms + MS_PER_DAY, 1, 2, 3)
+        .unwrap();
+    contract
+        .report_metrics_bucket(app_id, 8, day_start_ms, 100, 200, 300)
+        .unwrap();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::MetricBucketReported(MetricBucketReported {
+        inspector, metrics, ..
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(inspector, accounts.alice);
+        assert_eq!(metrics.storage_bytes, 100);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // Buckets are billed separately, and do not count as app metrics
+    let now_ms = day_start_ms + MS_PER_DAY;
+    assert_eq!(
+        contract.metrics_for_bucket_period(app_id, 7, day_start_ms, now_ms),
+        MetricValue {
+            start_ms: day_start_ms,
+            storage_bytes: 11,
+            wcu_used: 22,
+            rcu_used: 33,
+        }
+    );
+    assert_eq!(
+        contract
+            .metrics_for_bucket_period(app_id, 8, day_start_ms, now_ms)
+            .storage_bytes,
+        100
+    );
+    assert_eq!(
+        contract
+            .metrics_for_period(app_id, day_start_ms, now_ms)
+            .storage_bytes,
+        0
+    );
+
+    // Reports from a previous period are ignored
+    let next_period_ms = day_start_ms + PERIOD_MS;
+    assert_eq!(
+        contract
+            .metrics_for_bucket_period(app_id, 7, day_start_ms, next_period_ms)
+            .storage_bytes,
+        0
+    );
+}
+
+#[ink::test]
+fn gift_subscription_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let gifter = accounts.charlie;
+    let recipient = accounts.django;
+
+    set_exec_context(gifter, 1);
+    assert_eq!(
+        contract.gift_subscription_at_time(recipient, 1, 0),
+        Err(Error::InsufficientDeposit)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(gifter, 2);
+    assert_eq!(contract.gift_subscription_at_time(recipient, 1, 0), Ok(()));
+    assert_eq!(
+        contract.gift_subscription_at_time(recipient, 1, 0),
+        Err(Error::GiftPending)
+    );
+    undo_set_exec_context();
+
+    // A pending gift does not block the gifts of others
+    set_exec_context(accounts.bob, 2);
+    assert_eq!(contract.gift_subscription_at_time(recipient, 1, 0), Ok(()));
+    undo_set_exec_context();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::GiftOffered(GiftOffered {
+        gifter: event_gifter,
+        recipient: event_recipient,
+        value,
+        expires_ms,
+        ..
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_gifter, accounts.bob);
+        assert_eq!(event_recipient, recipient);
+        assert_eq!(value, 2);
+        assert_eq!(expires_ms, GIFT_ACCEPT_DELAY_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // Nothing is created for the recipient until the gift is accepted
+    assert_eq!(contract.balance_of(recipient), 0);
+    assert_eq!(contract.storage_footprint(recipient).items, 0);
+
+    // Only the gifter can reclaim, after the deadline
+    assert_eq!(
+        contract.reclaim_gift_at_time(recipient, GIFT_ACCEPT_DELAY_MS + 1),
+        Err(Error::NoGift)
+    );
+    set_exec_context(gifter, 0);
+    assert_eq!(
+        contract.reclaim_gift_at_time(recipient, GIFT_ACCEPT_DELAY_MS),
+        Err(Error::ClaimNotReady)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(recipient, 0);
+    assert_eq!(
+        contract.accept_gift_at_time(gifter, GIFT_ACCEPT_DELAY_MS + 1),
+        Err(Error::GiftExpired)
+    );
+    assert_eq!(
+        contract.accept_gift_at_time(gifter, GIFT_ACCEPT_DELAY_MS),
+        Ok(())
+    );
+    assert_eq!(contract.accept_gift_at_time(gifter, 0), Err(Error::NoGift));
+    undo_set_exec_context();
+
+    assert_eq!(contract.balance_of(recipient), 2);
+    assert_eq!(contract.get_gift(gifter, recipient), None);
+    assert!(contract.get_gift(accounts.bob, recipient).is_some());
+
+    // An expired gift is refunded to the gifter
+    set_exec_context(gifter, 4);
+    contract
+        .gift_subscription_at_time(accounts.eve, 2, 0)
+        .unwrap();
+    undo_set_exec_context();
+    set_balance(contract_id(), 100);
+    set_balance(gifter, 0);
+    set_exec_context(gifter, 0);
+    assert_eq!(
+        contract.reclaim_gift_at_time(accounts.eve, GIFT_ACCEPT_DELAY_MS + 1),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(balance_of(gifter), 4);
+    assert_eq!(contract.get_gift(gifter, accounts.eve), None);
+    assert_eq!(contract.storage_footprint(gifter).items, 0);
+}
+
+#[ink::test]
+fn ddn_day_summary_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let day_start_ms = 1000 * MS_PER_DAY;
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.add_inspector(accounts.bob).unwrap();
+
+    contract
+        .report_metrics_ddn(p2p_id.clone(), day_start_ms, 10, 20, 30)
+        .unwrap();
+    assert_eq!(
+        contract.get_ddn_day_summary(p2p_id.clone(), day_start_ms),
+        None
+    );
+
+    contract.finalize_metric_period(day_start_ms).unwrap();
+    let summary = MetricValue {
+        start_ms: day_start_ms,
+        storage_bytes: 10,
+        wcu_used: 20,
+        rcu_used: 30,
+    };
+    assert_eq!(
+        contract.get_ddn_day_summary(p2p_id.clone(), day_start_ms),
+        Some(summary.clone())
+    );
+    let now_ms = day_start_ms + MS_PER_DAY;
+    assert_eq!(
+        contract.metrics_for_ddn_at_time(p2p_id.clone(), now_ms)[PERIOD_DAYS as usize - 2],
+        summary
+    );
+
+    // A late report discards the summary until the day is finalized again
+    set_exec_context(accounts.bob, 0);
+    contract
+        .report_metrics_ddn(p2p_id.clone(), day_start_ms, 30, 40, 50)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.get_ddn_day_summary(p2p_id.clone(), day_start_ms),
+        None
+    );
+    assert_eq!(
+        contract.metrics_for_ddn_at_time(p2p_id.clone(), now_ms)[PERIOD_DAYS as usize - 2],
+        summary
+    );
+    set_exec_context(accounts.bob, 0);
+    contract.finalize_metric_period(day_start_ms).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.get_ddn_day_summary(p2p_id.clone(), day_start_ms),
+        Some(summary)
+    );
+
+    // Summaries of a previous period are ignored
+    assert_eq!(
+        contract.get_ddn_day_summary(p2p_id, day_start_ms + PERIOD_MS),
+        None
+    );
+}
+
+#[ink::test]
+fn health_check_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let day_start_ms = 1000 * MS_PER_DAY;
+
+    set_balance(contract_id(), 1000);
+    contract.subscribe(1).unwrap();
+    let report = contract.health_check();
+    assert_eq!(report.subscription_balances, 500);
+    assert!(report.balances_covered);
+    assert!(report.revenue_consistent);
+    assert_eq!(report.orphaned_metrics, 0);
+
+    // The balances must stay above the minimum balance
+    set_balance(contract_id(), 500);
+    assert!(!contract.health_check().balances_covered);
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .report_metrics(accounts.alice, day_start_ms, 1, 2, 3)
+        .unwrap();
+    contract
+        .report_metrics(accounts.bob, day_start_ms, 1, 2, 3)
+        .unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    contract
+        .report_metrics_ddn(p2p_id.clone(), day_start_ms, 1, 2, 3)
+        .unwrap();
+    assert_eq!(contract.health_check().orphaned_metrics, 1);
+
+    contract.remove_ddc_node(p2p_id.clone()).unwrap();
+    assert_eq!(contract.health_check().orphaned_metrics, 2);
+
+    // The metrics are no longer orphaned once the node and the app are back
+    contract
+        .add_ddc_node(
+            p2p_id,
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    assert_eq!(contract.health_check().orphaned_metrics, 1);
+    set_exec_context(accounts.bob, 500);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.health_check().orphaned_metrics, 0);
+
+    // Until the subscription moves to another account
+    set_exec_context(accounts.bob, 0);
+    contract.transfer_subscription(accounts.eve).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.health_check().orphaned_metrics, 1);
+}
+
+#[ink::test]
+fn register_ddc_node_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let operator = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+    let p2p_addr = String::from("test_p2p_addr");
+    let url = String::from("test_url");
+
+    // Self-registration is closed until the owner sets a minimum stake
+    set_exec_context(operator, 10);
+    assert_eq!(
+        contract.register_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Err(Error::RegistrationClosed)
+    );
+    undo_set_exec_context();
+
+    contract.set_min_node_stake(10).unwrap();
+    assert_eq!(contract.get_min_node_stake(), 10);
+
+    set_exec_context(operator, 9);
+    assert_eq!(
+        contract.register_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Err(Error::InsufficientDeposit)
+    );
+    undo_set_exec_context();
+    set_exec_context(operator, 10);
+    assert_eq!(
+        contract.register_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+    assert_eq!(
+        contract.node_stake_of(p2p_id.clone()),
+        Some(NodeStake {
+            operator,
+            value: 10,
+            unlock_ms: None,
+        })
+    );
+
+    // Another account cannot take over the node
+    set_exec_context(accounts.django, 10);
+    assert_eq!(
+        contract.register_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Err(Error::NodeAlreadyRegistered)
+    );
+    assert_eq!(
+        contract.deregister_ddc_node(p2p_id.clone()),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+
+    // A node slashed below the minimum stake is removed until it is topped up
+    contract.slash_node_stake(p2p_id.clone(), 4).unwrap();
+    assert_eq!(contract.node_stake_of(p2p_id.clone()).unwrap().value, 6);
+    assert!(!contract.is_ddc_node(p2p_id.clone()));
+    set_exec_context(operator, 4);
+    assert_eq!(
+        contract.register_ddc_node(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+    contract.set_min_node_stake(6).unwrap();
+    contract.slash_node_stake(p2p_id.clone(), 4).unwrap();
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+
+    // The stake stays locked while the node is registered, then during the cooldown
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.withdraw_node_stake_at_time(p2p_id.clone(), u64::MAX),
+        Err(Error::StakeLocked)
+    );
+    assert_eq!(contract.deregister_ddc_node(p2p_id.clone()), Ok(()));
+    assert_eq!(
+        contract.withdraw_node_stake_at_time(p2p_id.clone(), 0),
+        Err(Error::StakeLocked)
+    );
+    undo_set_exec_context();
+    assert!(!contract.is_ddc_node(p2p_id.clone()));
+
+    set_balance(contract_id(), 100);
+    set_balance(operator, 0);
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.withdraw_node_stake_at_time(p2p_id.clone(), NODE_UNSTAKE_COOLDOWN_MS),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(balance_of(operator), 6);
+    assert_eq!(contract.node_stake_of(p2p_id), None);
+}
+
+#[ink::test]
+fn unregister_node_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let operator = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.set_min_node_stake(10).unwrap();
+    let cluster_id = contract.create_cluster(String::from("eu")).unwrap();
+    set_exec_context(operator, 10);
+    contract
+        .register_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+        )
+        .unwrap();
+    undo_set_exec_context();
+
+    // Only the operator can unregister the node
+    set_exec_context(accounts.django, 0);
+    assert_eq!(
+        contract.unregister_node(p2p_id.clone()),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(operator, 0);
+    assert_eq!(contract.unregister_node(p2p_id.clone()), Ok(()));
+    assert_eq!(
+        contract.unregister_node(p2p_id.clone()),
+        Err(Error::NodeLeaving)
+    );
+    undo_set_exec_context();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeUnregistered(NodeUnregistered {
+        p2p_id: event_p2p_id,
+        unlock_ms,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_p2p_id, p2p_id);
+        assert_eq!(unlock_ms, NODE_UNSTAKE_COOLDOWN_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // A leaving node stays listed, but is not assigned anymore
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+    assert!(contract.is_node_leaving(p2p_id.clone()));
+    assert_eq!(
+        contract.assign_node_to_cluster(p2p_id.clone(), cluster_id),
+        Err(Error::NodeLeaving)
+    );
+
+    // The stake is returned and the node removed after the cooldown
+    set_balance(contract_id(), 100);
+    set_balance(operator, 0);
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.withdraw_node_stake_at_time(p2p_id.clone(), NODE_UNSTAKE_COOLDOWN_MS - 1),
+        Err(Error::StakeLocked)
+    );
+    assert_eq!(
+        contract.withdraw_node_stake_at_time(p2p_id.clone(), NODE_UNSTAKE_COOLDOWN_MS),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(balance_of(operator), 10);
+    assert!(!contract.is_ddc_node(p2p_id.clone()));
+    assert!(!contract.is_node_leaving(p2p_id.clone()));
+    assert_eq!(contract.node_stake_of(p2p_id), None);
+}
+
+#[ink::test]
+fn report_window_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.bob;
+    let today_ms = 1003 * MS_PER_DAY;
+    let now_ms = today_ms + 12345;
+
+    contract.add_inspector(accounts.alice).unwrap();
+    assert_eq!(
+        contract.set_report_window(Some(0)),
+        Err(Error::InvalidPeriod)
+    );
+    contract.set_report_window(Some(3)).unwrap();
+    assert_eq!(contract.get_report_window(), Some(3));
+
+    // Today and the two previous days
+    assert_eq!(
+        contract.report_metrics_at_time((app_id, today_ms, 1, 2, 3), now_ms),
+        Ok(())
+    );
+    assert_eq!(
+        contract.report_metrics_at_time((app_id, today_ms - 2 * MS_PER_DAY, 1, 2, 3), now_ms),
+        Ok(())
+    );
+    let late_day_ms = today_ms - 3 * MS_PER_DAY;
+    assert_eq!(
+        contract.report_metrics_at_time((app_id, late_day_ms, 1, 2, 3), now_ms),
+        Err(Error::LateReport)
+    );
+
+    // The owner can still correct the late day
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.correct_metrics(accounts.alice, app_id, late_day_ms, 4, 5, 6),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.correct_metrics(accounts.alice, app_id, late_day_ms, 4, 5, 6),
+        Ok(())
+    );
+    assert_eq!(
+        contract
+            .preview_day_aggregate(app_id, late_day_ms)
+            .unwrap()
+            .metrics
+            .storage_bytes,
+        4
+    );
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::MetricsCorrected(MetricsCorrected { inspector, .. }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(inspector, accounts.alice);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // No window
+    contract.set_report_window(None).unwrap();
+    assert_eq!(
+        contract.report_metrics_at_time((app_id, late_day_ms, 1, 2, 3), now_ms),
+        Ok(())
+    );
+}
+
+#[ink::test]
+fn node_join_request_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let applicant = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+    let p2p_addr = String::from("test_p2p_addr");
+    let url = String::from("test_url");
+
+    set_exec_context(applicant, 0);
+    assert_eq!(
+        contract.request_join(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Ok(())
+    );
+    assert_eq!(contract.approve_node(p2p_id.clone()), Err(Error::OnlyOwner));
+    undo_set_exec_context();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeJoinRequested(NodeJoinRequested {
+        p2p_id: event_p2p_id,
+        applicant: event_applicant,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(event_p2p_id, p2p_id);
+        assert_eq!(event_applicant, applicant);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // The node is pending, not added
+    assert!(!contract.is_ddc_node(p2p_id.clone()));
+    assert_eq!(contract.get_join_requests().len(), 1);
+    set_exec_context(accounts.django, 0);
+    assert_eq!(
+        contract.request_join(p2p_id.clone(), p2p_addr.clone(), url.clone()),
+        Err(Error::NodeAlreadyRegistered)
+    );
+    undo_set_exec_context();
+
+    assert_eq!(contract.approve_node(p2p_id.clone()), Ok(()));
+    assert!(contract.is_ddc_node(p2p_id.clone()));
+    assert_eq!(contract.get_join_requests(), vec![]);
+    assert_eq!(
+        contract.approve_node(p2p_id.clone()),
+        Err(Error::DDNNotFound)
+    );
+
+    // Rejected requests are discarded
+    let other_p2p_id = String::from("other_p2p_id");
+    set_exec_context(applicant, 0);
+    contract
+        .request_join(other_p2p_id.clone(), p2p_addr, url)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.reject_node(other_p2p_id.clone()), Ok(()));
+    assert!(!contract.is_ddc_node(other_p2p_id));
+    assert_eq!(contract.get_join_requests(), vec![]);
+    assert_eq!(contract.storage_footprint(applicant).items, 0);
+}
+
+#[ink::test]
+fn update_ddc_node_by_operator_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let operator = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            DDC_NODE_PERMISSION_TRUSTED,
+        )
+        .unwrap();
+    contract.set_node_update_cooldown(0).unwrap();
+
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.update_ddc_node_url(p2p_id.clone(), String::from("new_url")),
+        Err(Error::NoPermission)
+    );
+    assert_eq!(
+        contract.set_ddc_node_operator(p2p_id.clone(), operator),
+        Err(Error::OnlyDDNManager)
+    );
+    undo_set_exec_context();
+    contract
+        .set_ddc_node_operator(p2p_id.clone(), operator)
+        .unwrap();
+
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.update_ddc_node_url(p2p_id.clone(), String::from("new_url")),
+        Ok(())
+    );
+    assert_eq!(
+        contract.update_ddc_node_addr(p2p_id.clone(), String::from("new_p2p_addr")),
+        Ok(())
+    );
+    assert_eq!(
+        contract.update_ddc_node_url(String::from("unknown"), String::from("new_url")),
+        Err(Error::DDNNotFound)
+    );
+    undo_set_exec_context();
+
+    // The owner can update it too, the node keeps its operator
+    assert_eq!(
+        contract.update_ddc_node_url(p2p_id.clone(), String::from("owner_url")),
+        Ok(())
+    );
+    assert_eq!(
+        contract.get_all_ddc_nodes(),
+        vec![DDCNode {
+            p2p_id,
+            p2p_addr: String::from("new_p2p_addr"),
+            url: String::from("owner_url"),
+            permissions: DDC_NODE_PERMISSION_TRUSTED,
+            namespace: 0,
+            operator,
+            storage_capacity_bytes: 0,
+            region: String::new(),
+            tier_support: vec![],
+        }]
+    );
+}
+
+#[ink::test]
+fn node_contact_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let operator = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+    let contact = String::from("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi");
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    contract
+        .set_ddc_node_operator(p2p_id.clone(), operator)
+        .unwrap();
+
+    set_exec_context(accounts.django, 0);
+    assert_eq!(
+        contract.set_node_contact(p2p_id.clone(), contact.clone()),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+
+    set_exec_context(operator, 0);
+    assert_eq!(
+        contract.set_node_contact(p2p_id.clone(), "x".repeat(MAX_NODE_CONTACT_LEN + 1)),
+        Err(Error::ContactTooLong)
+    );
+    assert_eq!(
+        contract.set_node_contact(p2p_id.clone(), contact.clone()),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.get_node_contact(p2p_id.clone()), Some(contact));
+
+    set_exec_context(operator, 0);
+    assert_eq!(contract.clear_node_contact(p2p_id.clone()), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(contract.get_node_contact(p2p_id.clone()), None);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeContactChanged(NodeContactChanged { contact, .. }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(contact, None);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // The contact is removed with the node
+    contract
+        .set_node_contact(p2p_id.clone(), String::from("contact"))
+        .unwrap();
+    contract.remove_ddc_node(p2p_id.clone()).unwrap();
+    assert_eq!(contract.get_node_contact(p2p_id), None);
+}
+
+#[ink::test]
+fn ddc_node_placement_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    contract.set_node_update_cooldown(0).unwrap();
+
+    assert_eq!(
+        contract.set_ddc_node_placement(p2p_id.clone(), 1000, String::from("eu"), vec![1, 4]),
+        Err(Error::TidOutOfBound)
+    );
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.set_ddc_node_placement(p2p_id.clone(), 1000, String::from("eu"), vec![1, 3]),
+        Err(Error::NoPermission)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.set_ddc_node_placement(p2p_id.clone(), 1000, String::from("eu"), vec![1, 3]),
+        Ok(())
+    );
+
+    // The placement is kept when the addresses change
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("new_p2p_addr"),
+            String::from("new_url"),
+            0,
+        )
+        .unwrap();
+    let node = contract.get_all_ddc_nodes().pop().unwrap();
+    assert_eq!(node.url, String::from("new_url"));
+    assert_eq!(node.storage_capacity_bytes, 1000);
+    assert_eq!(node.region, String::from("eu"));
+    assert_eq!(node.tier_support, vec![1, 3]);
+}
+
+#[ink::test]
+fn cluster_management_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.create_cluster(String::from("eu")),
+        Err(Error::OnlyDDNManager)
+    );
+    undo_set_exec_context();
+
+    assert_eq!(contract.create_cluster(String::from("eu")), Ok(1));
+    assert_eq!(contract.create_cluster(String::from("us")), Ok(2));
+    assert_eq!(
+        contract.set_cluster_replication_factor(1, 0),
+        Err(Error::InvalidReplicationFactor)
+    );
+    assert_eq!(
+        contract.set_cluster_replication_factor(3, 2),
+        Err(Error::ClusterNotFound)
+    );
+    contract.set_cluster_replication_factor(1, 3).unwrap();
+    assert_eq!(
+        contract.get_cluster(1),
+        Some(Cluster {
+            cluster_id: 1,
+            name: String::from("eu"),
+            replication_factor: 3,
+        })
+    );
+    assert_eq!(contract.get_clusters().len(), 2);
+
+    assert_eq!(
+        contract.assign_node_to_cluster(p2p_id.clone(), 1),
+        Err(Error::DDNNotFound)
+    );
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    assert_eq!(
+        contract.assign_node_to_cluster(p2p_id.clone(), 3),
+        Err(Error::ClusterNotFound)
+    );
+    assert_eq!(contract.assign_node_to_cluster(p2p_id.clone(), 1), Ok(()));
+    assert_eq!(contract.get_cluster_nodes(1).len(), 1);
+    assert_eq!(contract.get_cluster_nodes(2), vec![]);
+
+    // Nodes move between clusters, and leave them when removed
+    contract.assign_node_to_cluster(p2p_id.clone(), 2).unwrap();
+    assert_eq!(contract.get_cluster_nodes(1), vec![]);
+    assert_eq!(contract.get_cluster_nodes(2)[0].p2p_id, p2p_id);
+    contract.remove_ddc_node(p2p_id).unwrap();
+    assert_eq!(contract.get_cluster_nodes(2), vec![]);
+}
+
+#[ink::test]
+fn distribute_node_rewards_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let stable_node = String::from("stable_node");
+    let flaky_node = String::from("flaky_node");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    for p2p_id in vec![stable_node.clone(), flaky_node.clone()] {
+        contract
+            .add_ddc_node(
+                p2p_id.clone(),
+                String::from("test_p2p_addr"),
+                String::from("test_url"),
+                0,
+            )
+            .unwrap();
+        contract.report_ddn_status(p2p_id, true).unwrap();
+    }
+    contract
+        .set_ddc_node_operator(flaky_node.clone(), accounts.charlie)
+        .unwrap();
+
+    // The flaky node is down for one block
+    contract
+        .report_ddn_status(flaky_node.clone(), false)
+        .unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+    contract
+        .report_ddn_status(flaky_node.clone(), true)
+        .unwrap();
+
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(0, PERIOD_MS - 1),
+        Err(Error::PeriodNotEnded)
+    );
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(0, PERIOD_MS),
+        Err(Error::ZeroBalance)
+    );
+
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(0, PERIOD_MS),
+        Ok(())
+    );
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(0, PERIOD_MS),
+        Err(Error::PeriodAlreadyRewarded)
+    );
+    assert_eq!(contract.node_rewards_of(accounts.alice), 500);
+    assert_eq!(contract.node_rewards_of(accounts.charlie), 499);
+    // The rounding remainder is left to the payment sink
+    assert_eq!(contract.get_unforwarded_revenue(), 1);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeRewardsDistributed(NodeRewardsDistributed { value, .. }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(value, 999);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    set_balance(contract_id(), 10000);
+    set_balance(accounts.charlie, 0);
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(contract.claim_node_rewards(), Ok(()));
+    assert_eq!(contract.claim_node_rewards(), Err(Error::ZeroBalance));
+    undo_set_exec_context();
+    assert_eq!(balance_of(accounts.charlie), 499);
+}
+
+/// Test the compliance of a cluster with its SLA is recorded when a period is rewarded
+#[ink::test]
+fn cluster_sla_report_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let stable_node = String::from("stable_node");
+    let flaky_node = String::from("flaky_node");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    let cluster_id = contract.create_cluster(String::from("eu")).unwrap();
+    for p2p_id in vec![stable_node.clone(), flaky_node.clone()] {
+        contract
+            .add_ddc_node(
+                p2p_id.clone(),
+                String::from("test_p2p_addr"),
+                String::from("test_url"),
+                0,
+            )
+            .unwrap();
+        contract.report_ddn_status(p2p_id.clone(), true).unwrap();
+        contract.assign_node_to_cluster(p2p_id, cluster_id).unwrap();
+    }
+
+    // Only the owner sets the SLA targets
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.set_cluster_sla(cluster_id, 4, 2),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.set_cluster_sla(cluster_id + 1, 4, 2),
+        Err(Error::ClusterNotFound)
+    );
+    assert_eq!(
+        contract.set_cluster_sla(cluster_id, 4, 0),
+        Err(Error::InvalidReplicationFactor)
+    );
+    assert_eq!(contract.set_cluster_sla(cluster_id, 4, 2), Ok(()));
+    assert_eq!(
+        contract.get_cluster_sla(cluster_id),
+        Some(ClusterSla {
+            max_downtime_ms: 4,
+            min_replication: 2,
+        })
+    );
+
+    // The flaky node is down for one block, longer than the target
+    contract
+        .report_ddn_status(flaky_node.clone(), false)
+        .unwrap();
+    advance_block::<DefaultEnvironment>().unwrap();
+    contract.report_ddn_status(flaky_node, true).unwrap();
+
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    assert_eq!(contract.cluster_sla_report(cluster_id, 0), None);
+    contract
+        .distribute_node_rewards_at_time(0, PERIOD_MS)
+        .unwrap();
+
+    assert_eq!(
+        contract.cluster_sla_report(cluster_id, 0),
+        Some(ClusterSlaReport {
+            nodes: 2,
+            nodes_within_sla: 1,
+            max_downtime_ms: 5,
+            compliant: false,
+        })
+    );
+}
+
+#[ink::test]
+fn payout_statement_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let operator = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.set_min_node_stake(10).unwrap();
+    set_exec_context(operator, 10);
+    contract
+        .register_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+        )
+        .unwrap();
+    undo_set_exec_context();
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .report_metrics_ddn(p2p_id.clone(), 0, 100, 0, 0)
+        .unwrap();
+
+    // The slash is withheld from the reward
+    contract.slash_node_stake(p2p_id.clone(), 10).unwrap();
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    assert_eq!(contract.payout_statement(p2p_id.clone(), 0), None);
+    contract
+        .distribute_node_rewards_at_time(0, PERIOD_MS)
+        .unwrap();
+
+    assert_eq!(
+        contract.payout_statement(p2p_id.clone(), 0),
+        Some(PayoutStatement {
+            usage_share: USAGE_SHARE_UNIT,
+            sla_bps: BPS,
+            gross: 1000,
+            penalties: 10,
+            net: 990,
+        })
+    );
+    assert_eq!(contract.node_rewards_of(operator), 990);
+    assert_eq!(contract.payout_statement(p2p_id, PERIOD_MS), None);
+}
+
+#[ink::test]
+fn node_rewards_are_per_period() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    contract.report_ddn_status(p2p_id, true).unwrap();
+
+    // An app consumes 1000 per period, for two periods
+    contract.change_tier_fee(3, 1000).unwrap();
+    set_exec_context(accounts.charlie, 2000);
+    contract.subscribe(3).unwrap();
+    undo_set_exec_context();
+
+    // Only the revenue of the period is distributed
+    contract
+        .distribute_node_rewards_at_time(0, 2 * PERIOD_MS)
+        .unwrap();
+    assert_eq!(contract.get_total_ddc_balance(), 2000);
+    assert_eq!(contract.node_rewards_of(accounts.alice), 1000);
+    assert_eq!(contract.get_next_reward_period(), Some(PERIOD_MS));
+
+    // The revenue of the next period is kept for the nodes
+    contract.set_payment_sink(Some(accounts.frank)).unwrap();
+    assert_eq!(contract.get_unforwarded_revenue(), 1000);
+    assert_eq!(contract.settle_revenue(), Err(Error::ZeroBalance));
+
+    // The periods are rewarded in order
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(2 * PERIOD_MS, 3 * PERIOD_MS),
+        Err(Error::PeriodNotNext)
+    );
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(0, 3 * PERIOD_MS),
+        Err(Error::PeriodAlreadyRewarded)
+    );
+    assert_eq!(
+        contract.distribute_node_rewards_at_time(PERIOD_MS, 3 * PERIOD_MS),
+        Ok(())
+    );
+    assert_eq!(contract.node_rewards_of(accounts.alice), 2000);
+    assert_eq!(contract.get_unforwarded_revenue(), 0);
+}
+
+#[ink::test]
+fn distribute_node_rewards_by_usage_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let storage_node = String::from("storage_node");
+    let busy_node = String::from("busy_node");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    for p2p_id in vec![storage_node.clone(), busy_node.clone()] {
+        contract
+            .add_ddc_node(
+                p2p_id,
+                String::from("test_p2p_addr"),
+                String::from("test_url"),
+                0,
+            )
+            .unwrap();
+    }
+    contract
+        .set_ddc_node_operator(busy_node.clone(), accounts.charlie)
+        .unwrap();
+    contract
+        .report_metrics_ddn(storage_node.clone(), 0, 300, 0, 0)
+        .unwrap();
+    contract
+        .report_metrics_ddn(busy_node.clone(), 0, 100, 10, 0)
+        .unwrap();
+
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.set_reward_weighting(RewardWeighting::Usage),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    contract
+        .set_reward_weighting(RewardWeighting::Usage)
+        .unwrap();
+    assert_eq!(contract.get_reward_weighting(), RewardWeighting::Usage);
+
+    // Shares: 3/4 of the storage, and 1/4 of the storage plus all the WCU
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    contract
+        .distribute_node_rewards_at_time(0, PERIOD_MS)
+        .unwrap();
+    assert_eq!(contract.node_rewards_of(accounts.alice), 375);
+    assert_eq!(contract.node_rewards_of(accounts.charlie), 625);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::NodeRewardAccrued(NodeRewardAccrued {
+        p2p_id,
+        account,
+        value,
+    }) = decode_event(&raw_events[raw_events.len() - 2])
+    {
+        assert!(p2p_id == busy_node || p2p_id == storage_node);
+        assert_eq!(contract.node_rewards_of(account), value);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn cancel_subscription_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.cancel_subscription_at_time(0),
+        Err(Error::NoSubscription)
+    );
+    undo_set_exec_context();
+
+    // Two periods paid
+    set_exec_context(app_id, 4);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.cancel_subscription_at_time(10 * MS_PER_DAY),
+        Ok(())
+    );
+    undo_set_exec_context();
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionCancelled(SubscriptionCancelled { app, end_ms }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(end_ms, PERIOD_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // The subscription ends with the current period, and is not charged after that
+    let details = contract.get_subscription_details_of(app_id).unwrap();
+    assert_eq!(details.end_date_ms, PERIOD_MS);
+    assert!(!details.subscription.auto_renew);
+    let tier = contract.service_tiers.get(&1).unwrap().clone();
+    assert_eq!(
+        Ddc::get_consumed_balance_at_time(
+            2 * PERIOD_MS,
+            &details.subscription,
+            &tier,
+            RoundingPolicy::Floor,
+            PERIOD_MS
+        ),
+        Ok(2)
+    );
+
+    // Paying again resumes it
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    let details = contract.get_subscription_details_of(app_id).unwrap();
+    assert!(details.subscription.auto_renew);
+    assert_eq!(details.end_date_ms, 3 * PERIOD_MS);
+}
+
+#[ink::test]
+fn grace_period_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let grace_period_ms = 3 * MS_PER_DAY;
+
+    contract.add_tier(0, 1000, 1000, 1000).unwrap();
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.set_grace_period(grace_period_ms),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    contract.set_grace_period(grace_period_ms).unwrap();
+    assert_eq!(contract.get_grace_period(), grace_period_ms);
+
+    // One period paid
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+
+    let paid_limit = Ok(AppSubscriptionLimit::new(2000, 2000, 2000));
+    let free_limit = Ok(AppSubscriptionLimit::new(1000, 1000, 1000));
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, PERIOD_MS + grace_period_ms),
+        paid_limit
+    );
+    assert_eq!(
+        contract.get_app_limit_at_time(app_id, PERIOD_MS + grace_period_ms + 1),
+        free_limit
+    );
+
+    // The app is warned during the grace period
+    contract
+        .actualize_subscriptions_at_time(PERIOD_MS + MS_PER_DAY)
+        .unwrap();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionInGrace(SubscriptionInGrace { app, grace_end_ms }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(grace_end_ms, PERIOD_MS + grace_period_ms);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // Cancelled subscriptions have no grace period
+    set_exec_context(accounts.django, 2);
+    contract.subscribe(1).unwrap();
+    contract.cancel_subscription_at_time(0).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.get_app_limit_at_time(accounts.django, PERIOD_MS + 1),
+        free_limit
+    );
+}
+
+#[ink::test]
+fn transfer_subscription_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+
+    set_exec_context(app, 2);
+    assert_eq!(
+        contract.transfer_subscription(accounts.django),
+        Err(Error::NoSubscription)
+    );
+    contract.subscribe(1).unwrap();
+    assert_eq!(
+        contract.transfer_subscription(app),
+        Err(Error::InvalidAccount)
+    );
+    set_exec_context(accounts.eve, 4);
+    contract.subscribe(2).unwrap();
+    set_exec_context(app, 0);
+    assert_eq!(
+        contract.transfer_subscription(accounts.eve),
+        Err(Error::AlreadySubscribed)
+    );
+
+    let subscription = contract.subscriptions.get(&app).unwrap().clone();
+    assert_eq!(contract.transfer_subscription(accounts.django), Ok(()));
+    undo_set_exec_context();
+
+    assert_eq!(contract.subscriptions.get(&app), None);
+    assert_eq!(
+        contract.subscriptions.get(&accounts.django),
+        Some(&subscription)
+    );
+    assert_eq!(contract.balance_of(accounts.django), 2);
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionTransferred(SubscriptionTransferred { from, to }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(from, app);
+        assert_eq!(to, accounts.django);
+    } else {
+        panic!("Wrong event type")
+    }
+}
+
+#[ink::test]
+fn transfer_subscription_moves_refund_address() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+
+    set_exec_context(app, 2);
+    contract.subscribe(1).unwrap();
+    undo_set_exec_context();
+    // An address already in effect
+    let mut refund_address = DelayedAddress::default();
+    refund_address.schedule(accounts.eve, 0, 0);
+    contract.refund_addresses.insert(app, refund_address);
+
+    set_exec_context(app, 0);
+    contract.transfer_subscription(accounts.django).unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.get_refund_address(app), None);
+    assert_eq!(
+        contract.get_refund_address(accounts.django),
+        Some(accounts.eve)
+    );
+
+    // The new key refunds to the address chosen by the app
+    set_balance(contract_id(), 1000);
+    set_balance(accounts.django, 0);
+    set_balance(accounts.eve, 0);
+    set_exec_context(accounts.django, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(balance_of(accounts.django), 0);
+    assert_eq!(balance_of(accounts.eve), 2);
+}
+
+#[ink::test]
+fn clock_skew_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    contract
+        .report_ddn_status_at_time(p2p_id.clone(), true, 100)
+        .unwrap();
+
+    // Strictly monotonic by default
+    assert_eq!(contract.get_clock_skew(), (0, SkewPolicy::Reject));
+    assert_eq!(
+        contract.report_ddn_status_at_time(p2p_id.clone(), true, 90),
+        Err(Error::UnexpectedTimestamp)
+    );
+
+    // A small skew is clamped
+    contract.set_clock_skew(20, SkewPolicy::Reject).unwrap();
+    assert_eq!(
+        contract.report_ddn_status_at_time(p2p_id.clone(), false, 90),
+        Ok(())
+    );
+    assert_eq!(
+        contract
+            .get_ddn_status(p2p_id.clone())
+            .unwrap()
+            .last_timestamp,
+        100
+    );
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SkewDetected(SkewDetected { reporter, skew_ms }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(reporter, accounts.alice);
+        assert_eq!(skew_ms, 10);
+    } else {
+        panic!("Wrong event type")
+    }
+    assert_eq!(
+        contract.report_ddn_status_at_time(p2p_id.clone(), true, 50),
+        Err(Error::UnexpectedTimestamp)
+    );
+
+    // Or any skew
+    contract.set_clock_skew(20, SkewPolicy::Clamp).unwrap();
+    assert_eq!(
+        contract.report_ddn_status_at_time(p2p_id.clone(), true, 50),
+        Ok(())
+    );
+    let status = contract.get_ddn_status(p2p_id).unwrap();
+    assert_eq!(status.last_timestamp, 100);
+    assert_eq!(status.total_downtime, 0);
+}
+
+#[ink::test]
+fn archive_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    set_exec_context(app, 2);
+    contract.subscribe(1).unwrap();
+    contract.attest_usage(0, 1, 2, 3).unwrap();
+    contract.set_refund_address(accounts.eve).unwrap();
+    undo_set_exec_context();
+    contract.report_metrics(app, 0, 1, 2, 3).unwrap();
+    contract.report_metrics_bucket(app, 7, 0, 1, 2, 3).unwrap();
+    set_exec_context(app, 0);
+    contract
+        .dispute_metric_at_time(0, Hash::from([1; 32]), 0)
+        .unwrap();
+    undo_set_exec_context();
+
+    // The app is frozen
+    assert_eq!(
+        contract.archive_app_at_time(accounts.bob, 0),
+        Err(Error::NoSubscription)
+    );
+    assert_eq!(contract.archive_app_at_time(app, 0), Ok(()));
+    assert_eq!(
+        contract.archive_app_at_time(app, 0),
+        Err(Error::AppArchived)
+    );
+    assert_eq!(contract.get_app_archived_ms(app), Some(0));
+    assert_eq!(
+        contract.report_metrics(app, 0, 1, 2, 3),
+        Err(Error::AppArchived)
+    );
+    set_exec_context(app, 2);
+    assert_eq!(contract.subscribe(1), Err(Error::AppArchived));
+    assert_eq!(contract.refund(), Err(Error::AppArchived));
+    undo_set_exec_context();
+
+    // And its balance is not charged anymore
+    contract.actualize_subscriptions_at_time(PERIOD_MS).unwrap();
+    assert_eq!(contract.subscriptions.get(&app).unwrap().balance, 2);
+
+    // The node too
+    assert_eq!(contract.archive_ddc_node_at_time(p2p_id.clone(), 0), Ok(()));
+    assert_eq!(
+        contract.archive_ddc_node_at_time(p2p_id.clone(), 0),
+        Err(Error::NodeArchived)
+    );
+    assert_eq!(contract.get_ddc_node_archived_ms(p2p_id.clone()), Some(0));
+    assert_eq!(
+        contract.report_ddn_status(p2p_id.clone(), true),
+        Err(Error::NodeArchived)
+    );
+    assert_eq!(
+        contract.report_metrics_ddn(p2p_id.clone(), 0, 1, 2, 3),
+        Err(Error::NodeArchived)
+    );
+
+    // Records are pruned after the retention period
+    set_balance(contract_id(), 1000);
+    set_balance(app, 0);
+    assert_eq!(
+        contract.prune_archived_app_at_time(accounts.bob, ARCHIVE_RETENTION_MS),
+        Err(Error::NotArchived)
+    );
+    assert_eq!(
+        contract.prune_archived_app_at_time(app, ARCHIVE_RETENTION_MS - 1),
+        Err(Error::RetentionNotEnded)
+    );
+    // Not before its disputes are closed
+    assert_eq!(
+        contract.prune_archived_app_at_time(app, ARCHIVE_RETENTION_MS),
+        Err(Error::DisputeExists)
+    );
+    contract.grant_role(accounts.django, Role::Arbiter).unwrap();
+    set_exec_context(accounts.django, 0);
+    contract
+        .resolve_dispute_at_time(app, 0, None, ARCHIVE_RETENTION_MS)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.prune_archived_app_at_time(app, ARCHIVE_RETENTION_MS),
+        Ok(())
+    );
+    assert_eq!(balance_of(app), 2);
+    assert_eq!(contract.balance_of(app), 0);
+    assert_eq!(contract.get_app_archived_ms(app), None);
+    assert!(contract.metrics.is_empty());
+    assert!(contract.metrics_bucket.is_empty());
+    assert!(contract.app_attestations.is_empty());
+    assert!(!contract.refund_addresses.contains_key(&app));
+
+    assert_eq!(
+        contract.prune_archived_ddc_node_at_time(p2p_id.clone(), ARCHIVE_RETENTION_MS - 1),
+        Err(Error::RetentionNotEnded)
+    );
+    assert_eq!(
+        contract.prune_archived_ddc_node_at_time(p2p_id.clone(), ARCHIVE_RETENTION_MS),
+        Ok(())
+    );
+    assert!(!contract.is_ddc_node(p2p_id.clone()));
+    assert_eq!(contract.get_ddc_node_archived_ms(p2p_id), None);
+}
+
+#[ink::test]
+fn ops_stats_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    assert_eq!(contract.ops_stats(0), OpsStats::default());
+
+    contract.report_ddn_status(p2p_id.clone(), true).unwrap();
+    // A metrics report of a node also updates its status
+    contract
+        .report_metrics_ddn(p2p_id.clone(), 0, 10, 20, 30)
+        .unwrap();
+    contract.report_metrics(accounts.bob, 0, 1, 2, 3).unwrap();
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    contract
+        .distribute_node_rewards_at_time(0, PERIOD_MS)
+        .unwrap();
+
+    assert_eq!(
+        contract.ops_stats(0),
+        OpsStats {
+            reports: 2,
+            status_updates: 2,
+            settlements: 1,
+        }
+    );
+    assert_eq!(contract.ops_stats(PERIOD_MS), OpsStats::default());
+}
+
+#[ink::test]
+fn day_status_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let p2p_id = String::from("test_p2p_id");
+    let app_id = accounts.charlie;
+    let day_start_ms = 0;
+
+    contract
+        .add_ddc_node(
+            p2p_id.clone(),
+            String::from("test_p2p_addr"),
+            String::from("test_url"),
+            0,
+        )
+        .unwrap();
+    contract.report_ddn_status(p2p_id.clone(), true).unwrap();
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.add_inspector(accounts.bob).unwrap();
+    contract.set_coverage_quorum(2).unwrap();
+
+    assert_eq!(contract.day_status(1), Err(Error::UnexpectedTimestamp));
+    assert_eq!(contract.day_status(day_start_ms), Ok(DayStatus::Open));
+
+    contract
+        .report_metrics(app_id, day_start_ms, 10, 20, 30)
+        .unwrap();
+    assert_eq!(contract.day_status(day_start_ms), Ok(DayStatus::Open));
+
+    set_exec_context(accounts.bob, 0);
+    contract
+        .report_metrics(app_id, day_start_ms, 10, 20, 30)
+        .unwrap();
+    assert_eq!(
+        contract.day_status(day_start_ms),
+        Ok(DayStatus::QuorumReached)
+    );
+    undo_set_exec_context();
+
+    // The day is disputed until the dispute is resolved
+    set_exec_context(app_id, 0);
+    contract
+        .dispute_metric_at_time(day_start_ms, Hash::from([1; 32]), MS_PER_DAY)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(contract.day_status(day_start_ms), Ok(DayStatus::Disputed));
+    contract.grant_role(accounts.django, Role::Arbiter).unwrap();
+    set_exec_context(accounts.django, 0);
+    contract
+        .resolve_dispute_at_time(app_id, day_start_ms, None, MS_PER_DAY)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.day_status(day_start_ms),
+        Ok(DayStatus::QuorumReached)
+    );
+
+    set_exec_context(accounts.bob, 0);
+
+    contract.finalize_metric_period(day_start_ms).unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.day_status(day_start_ms),
+        Ok(DayStatus::QuorumReached)
+    );
+    contract.finalize_metric_period(day_start_ms).unwrap();
+    assert_eq!(contract.day_status(day_start_ms), Ok(DayStatus::Finalized));
+
+    contract.total_ddc_balance = 1000;
+    contract.daily_revenues.insert(0, 1000);
+    contract
+        .distribute_node_rewards_at_time(day_start_ms, PERIOD_MS)
+        .unwrap();
+    assert_eq!(contract.day_status(day_start_ms), Ok(DayStatus::Settled));
+    assert_eq!(contract.day_status(PERIOD_MS), Ok(DayStatus::Open));
+}
+
+#[ink::test]
+fn dispute_metric_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.charlie;
+    let day_ms = MS_PER_DAY;
+    let reason_hash = Hash::from([1; 32]);
+
+    // Nothing to dispute yet
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.dispute_metric_at_time(day_ms, reason_hash, day_ms),
+        Err(Error::NoMetrics)
+    );
+    undo_set_exec_context();
+
+    contract.add_inspector(accounts.alice).unwrap();
+    contract.add_inspector(accounts.bob).unwrap();
+    contract.report_metrics(app_id, day_ms, 10, 10, 10).unwrap();
+    set_exec_context(accounts.bob, 0);
+    contract.report_metrics(app_id, day_ms, 12, 12, 12).unwrap();
+    undo_set_exec_context();
+
+    set_exec_context(app_id, 0);
+    assert_eq!(
+        contract.dispute_metric_at_time(day_ms, reason_hash, day_ms + DISPUTE_WINDOW_MS),
+        Err(Error::DisputeWindowEnded)
+    );
+    assert_eq!(
+        contract.dispute_metric_at_time(day_ms, reason_hash, 2 * day_ms),
+        Ok(())
+    );
+    assert_eq!(
+        contract.dispute_metric_at_time(day_ms, reason_hash, 2 * day_ms),
+        Err(Error::DisputeExists)
+    );
+    assert_eq!(
+        contract.get_dispute(app_id, day_ms),
+        Some(Dispute {
+            reason_hash,
+            opened_ms: 2 * day_ms,
+        })
+    );
+
+    // Only arbiters resolve disputes
+    let corrected = MetricValue {
+        start_ms: 0,
+        storage_bytes: 5,
+        wcu_used: 6,
+        rcu_used: 7,
+    };
+    assert_eq!(
+        contract.resolve_dispute_at_time(app_id, day_ms, Some(corrected.clone()), 3 * day_ms),
+        Err(Error::MissingRole)
+    );
+    undo_set_exec_context();
+    contract.grant_role(accounts.django, Role::Arbiter).unwrap();
+    set_exec_context(accounts.django, 0);
+
+    // The correction is bounded by the window
+    let late_ms = 2 * day_ms + DISPUTE_WINDOW_MS;
+    assert_eq!(
+        contract.resolve_dispute_at_time(app_id, day_ms, Some(corrected.clone()), late_ms),
+        Err(Error::DisputeWindowEnded)
+    );
+    assert_eq!(
+        contract.resolve_dispute_at_time(app_id, day_ms, Some(corrected), 3 * day_ms),
+        Ok(())
+    );
+    undo_set_exec_context();
+
+    // The correction replaces the median, and the reports are kept
+    let expected = MetricValue {
+        start_ms: day_ms,
+        storage_bytes: 5,
+        wcu_used: 6,
+        rcu_used: 7,
+    };
+    assert_eq!(
+        contract.preview_day_aggregate(app_id, day_ms),
+        Ok(DayAggregate {
+            inspectors_count: 2,
+            metrics: expected.clone(),
+        })
+    );
+    assert_eq!(
+        contract
+            .metrics_for_day(accounts.alice, app_id, 1)
+            .map(|metrics| metrics.storage_bytes),
+        Some(10)
+    );
+    assert_eq!(contract.get_dispute(app_id, day_ms), None);
+    assert_eq!(
+        contract.resolve_dispute_at_time(app_id, day_ms, None, 3 * day_ms),
+        Err(Error::NoDispute)
+    );
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::MetricCorrected(MetricCorrected {
+        app,
+        day_start_ms,
+        metrics,
+    }) = decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(day_start_ms, day_ms);
+        assert_eq!(metrics, expected);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // Without correction, a dispute can be closed after the window
+    set_exec_context(app_id, 0);
+    contract
+        .dispute_metric_at_time(day_ms, reason_hash, 2 * day_ms)
+        .unwrap();
+    undo_set_exec_context();
+    assert_eq!(
+        contract.resolve_dispute_at_time(app_id, day_ms, None, late_ms),
+        Ok(())
+    );
+}
+
+#[ink::test]
+fn reporter_reputation_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.eve;
+    let inspectors = [accounts.alice, accounts.bob, accounts.charlie];
+    for inspector in inspectors.iter() {
+        contract.add_inspector(*inspector).unwrap();
+    }
+
+    // Charlie reports twice the usage of the others, then the same
+    let report = |contract: &mut Ddc, day: u64, charlie_wcu: u64| {
+        for inspector in inspectors.iter() {
+            let wcu_used = if *inspector == accounts.charlie {
+                charlie_wcu
+            } else {
+                10
+            };
+            set_exec_context(*inspector, 0);
+            contract
+                .report_metrics(app_id, day * MS_PER_DAY, 10, wcu_used, 10)
+                .unwrap();
+            undo_set_exec_context();
+        }
+    };
+    report(&mut contract, 1, 20);
+    report(&mut contract, 2, 10);
+
+    assert_eq!(contract.reporter_reputation(accounts.charlie), 10_000);
+    assert_eq!(
+        contract.finalize_day_at_time(2 * MS_PER_DAY, 2 * MS_PER_DAY),
+        Err(Error::DayNotEnded)
+    );
+    assert_eq!(
+        contract.finalize_day_at_time(MS_PER_DAY, 3 * MS_PER_DAY),
+        Ok(())
+    );
+    assert!(contract.is_day_finalized(MS_PER_DAY));
+    assert_eq!(
+        contract.finalize_day_at_time(MS_PER_DAY, 3 * MS_PER_DAY),
+        Err(Error::DayAlreadyFinalized)
+    );
+
+    assert_eq!(contract.reporter_reputation(accounts.alice), 10_000);
+    assert_eq!(contract.reporter_reputation(accounts.charlie), 0);
+    assert_eq!(
+        contract.reporter_stats_of(accounts.charlie),
+        ReporterStats {
+            reports: 1,
+            deviation_bps: 10_000,
+        }
+    );
+
+    // The reputation is the average over the finalized days
+    contract
+        .finalize_day_at_time(2 * MS_PER_DAY, 3 * MS_PER_DAY)
+        .unwrap();
+    assert_eq!(contract.reporter_reputation(accounts.charlie), 5_000);
+
+    // The reports of a day are overwritten in the next period
+    assert_eq!(
+        contract.finalize_day_at_time(3 * MS_PER_DAY, 3 * MS_PER_DAY + PERIOD_MS),
+        Err(Error::StalePeriod)
+    );
+
+    // Without a report window, only the owner finalizes the days
+    report(&mut contract, 3, 10);
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.finalize_day_at_time(3 * MS_PER_DAY, 4 * MS_PER_DAY),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+
+    // With a report window, anyone finalizes the days after their window
+    contract.set_report_window(Some(2)).unwrap();
+    set_exec_context(accounts.bob, 0);
+    assert_eq!(
+        contract.finalize_day_at_time(3 * MS_PER_DAY, 4 * MS_PER_DAY),
+        Err(Error::DayNotEnded)
+    );
+    assert_eq!(
+        contract.finalize_day_at_time(3 * MS_PER_DAY, 5 * MS_PER_DAY),
+        Ok(())
+    );
+    undo_set_exec_context();
+    assert_eq!(
+        contract.reporter_stats_of(accounts.alice),
+        ReporterStats {
+            reports: 3,
+            deviation_bps: 0,
+        }
+    );
+}
+
+#[ink::test]
+fn inspector_suspension_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app_id = accounts.eve;
+    for inspector in [accounts.alice, accounts.bob, accounts.charlie].iter() {
+        contract.add_inspector(*inspector).unwrap();
+    }
+    assert_eq!(
+        contract.set_suspension_threshold(Some(10_001)),
+        Err(Error::InvalidShare)
+    );
+    assert_eq!(contract.set_suspension_threshold(Some(5_000)), Ok(()));
+    assert_eq!(contract.get_suspension_threshold(), Some(5_000));
+
+    // Charlie reports twice the usage of the others
+    for (inspector, wcu_used) in [
+        (accounts.alice, 10),
+        (accounts.bob, 10),
+        (accounts.charlie, 20),
+    ]
+    .iter()
+    {
+        set_exec_context(*inspector, 0);
+        contract
+            .report_metrics(app_id, MS_PER_DAY, 10, *wcu_used, 10)
+            .unwrap();
+        undo_set_exec_context();
+    }
+    contract
+        .finalize_day_at_time(MS_PER_DAY, 2 * MS_PER_DAY)
+        .unwrap();
+    assert!(contract.is_inspector_suspended(accounts.charlie));
+    assert!(!contract.is_inspector_suspended(accounts.alice));
+
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::InspectorSuspended(InspectorSuspended {
+        inspector,
+        reputation,
+    }) = decode_event(&raw_events[raw_events.len() - 2])
+    {
+        assert_eq!(inspector, accounts.charlie);
+        assert_eq!(reputation, 0);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // The reports of a suspended inspector are ignored by the median
+    for (inspector, wcu_used) in [(accounts.alice, 10), (accounts.charlie, 0)].iter() {
+        set_exec_context(*inspector, 0);
+        contract
+            .report_metrics(app_id, 2 * MS_PER_DAY, 10, *wcu_used, 10)
+            .unwrap();
+        undo_set_exec_context();
+    }
+    let aggregate = contract.app_day_aggregate(app_id, 2);
+    assert_eq!(aggregate.inspectors_count, 1);
+    assert_eq!(aggregate.metrics.wcu_used, 10);
+
+    // Until reinstated by the owner, with a new reputation
+    set_exec_context(accounts.charlie, 0);
+    assert_eq!(
+        contract.reinstate_inspector(accounts.charlie),
+        Err(Error::OnlyOwner)
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.reinstate_inspector(accounts.charlie), Ok(()));
+    assert!(!contract.is_inspector_suspended(accounts.charlie));
+    assert_eq!(contract.reporter_reputation(accounts.charlie), 10_000);
+    assert_eq!(
+        contract.reinstate_inspector(accounts.charlie),
+        Err(Error::NotSuspended)
+    );
+    assert_eq!(contract.app_day_aggregate(app_id, 2).metrics.wcu_used, 0);
+}
+
+#[ink::test]
+fn free_trial_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+    let trial_ms = 7 * MS_PER_DAY;
+
+    set_exec_context(app, 0);
+    assert_eq!(
+        contract.start_trial_at_time(0),
+        Err(Error::TrialUnavailable)
+    );
+    undo_set_exec_context();
+    assert_eq!(contract.set_trial(7, 4), Err(Error::TidOutOfBound));
+    assert_eq!(contract.set_trial(7, 2), Ok(()));
+    assert_eq!(contract.get_trial(), (7, 2));
+
+    set_exec_context(app, 0);
+    assert_eq!(contract.start_trial_at_time(0), Ok(()));
+    assert_eq!(contract.start_trial_at_time(0), Err(Error::TrialUsed));
+    undo_set_exec_context();
+    assert!(contract.is_trial_used(app));
+    assert_eq!(contract.get_trial_end_ms(app), Some(trial_ms));
+
+    // The app gets the limits of the trial tier until the end of the trial
+    assert_eq!(
+        contract.get_app_limit_at_time(app, trial_ms - 1),
+        Ok(AppSubscriptionLimit::new(4000, 4000, 4000))
+    );
+    assert_eq!(
+        contract.get_app_limit_at_time(app, trial_ms),
+        Err(Error::NoSubscription)
+    );
+
+    // Subscribers cannot use the trial
+    set_exec_context(accounts.django, 2);
+    contract.subscribe(1).unwrap();
+    assert_eq!(
+        contract.start_trial_at_time(0),
+        Err(Error::AlreadySubscribed)
+    );
+    undo_set_exec_context();
+}
+
+#[ink::test]
+fn subscribe_with_promo_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+    let code = b"SPRING".to_vec();
+    let mut output = <Blake2x256 as HashOutput>::Type::default();
+    ink_env::hash_bytes::<Blake2x256>(&code, &mut output);
+    let code_hash = Hash::from(output);
+
+    assert_eq!(
+        contract.create_promo(code_hash, 10_001, 1, 1000),
+        Err(Error::InvalidShare)
+    );
+    assert_eq!(contract.create_promo(code_hash, 5_000, 1, 1000), Ok(()));
+    assert_eq!(
+        contract.create_promo(code_hash, 5_000, 1, 1000),
+        Err(Error::PromoExists)
+    );
+
+    // Half of the fee of the tier 3 is paid
+    set_exec_context(app, 4);
+    assert_eq!(
+        contract.subscribe_with_promo(3, b"WINTER".to_vec()),
+        Err(Error::InvalidPromo)
+    );
+    assert_eq!(contract.subscribe_with_promo(3, code.clone()), Ok(()));
+    assert_eq!(contract.balance_of(app), 8);
+    assert_eq!(contract.get_promo(code_hash).unwrap().remaining_uses, 0);
+
+    // No use left
+    set_exec_context(accounts.django, 4);
+    assert_eq!(
+        contract.subscribe_with_promo(3, code.clone()),
+        Err(Error::InvalidPromo)
+    );
+
+    // The discount is not refundable
+    set_exec_context(app, 0);
+    set_balance(contract_id(), 1000);
+    set_balance(app, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(balance_of(app), 4);
+    assert_eq!(contract.get_unforwarded_revenue(), 0);
+}
+
+#[ink::test]
+fn subscribe_with_referrer_works() {
+    let mut contract = make_contract();
+    let accounts = get_accounts();
+    let app = accounts.charlie;
+    let referrer = accounts.bob;
+
+    assert_eq!(
+        contract.set_referral_reward(10_001),
+        Err(Error::InvalidShare)
+    );
+    assert_eq!(contract.set_referral_reward(1_000), Ok(()));
+
+    set_exec_context(app, 100);
+    assert_eq!(
+        contract.subscribe_with_referrer(1, app),
+        Err(Error::InvalidAccount)
+    );
+    assert_eq!(contract.subscribe_with_referrer(1, referrer), Ok(()));
+    // Only new subscribers are rewarded
+    assert_eq!(
+        contract.subscribe_with_referrer(1, referrer),
+        Err(Error::AlreadySubscribed)
+    );
+    assert_eq!(
+        contract.referral_stats_of(referrer),
+        ReferralStats {
+            referrals: 1,
+            rewards: 10,
+            unclaimed: 10,
+        }
+    );
+
+    set_balance(contract_id(), 1000);
+    set_balance(referrer, 0);
+    set_exec_context(referrer, 0);
+    assert_eq!(contract.claim_referral_rewards(), Ok(()));
+    assert_eq!(contract.claim_referral_rewards(), Err(Error::ZeroBalance));
+    assert_eq!(balance_of(referrer), 10);
+    assert_eq!(contract.referral_stats_of(referrer).unclaimed, 0);
+
+    // The reward is not refundable to the app
+    set_balance(app, 0);
+    set_exec_context(app, 0);
+    assert_eq!(contract.refund(), Ok(()));
+    undo_set_exec_context();
+    assert_eq!(balance_of(app), 90);
+}
+
+#[ink::test]
+fn subscription_lifecycle_events_work() {
+    let mut contract = make_contract();
+    let app_id = get_accounts().charlie;
+
+    set_exec_context(app_id, 2);
+    contract.subscribe(1).unwrap();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionCreated(SubscriptionCreated {
+        app,
+        tier_id,
+        end_date_ms,
+    }) = decode_event(&raw_events[raw_events.len() - 2])
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(tier_id, 1);
+        assert_eq!(end_date_ms, PERIOD_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    contract.subscribe(1).unwrap();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionExtended(SubscriptionExtended { app, end_date_ms }) =
+        decode_event(&raw_events[raw_events.len() - 2])
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(end_date_ms, 2 * PERIOD_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // An implicit tier change
+    set_exec_context(app_id, 4);
+    contract.subscribe(2).unwrap();
+    undo_set_exec_context();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionTierChanged(SubscriptionTierChanged {
+        old_tier_id,
+        new_tier_id,
+        end_date_ms,
+        ..
+    }) = decode_event(&raw_events[raw_events.len() - 3])
+    {
+        assert_eq!(old_tier_id, 1);
+        assert_eq!(new_tier_id, 2);
+        assert_eq!(end_date_ms, 2 * PERIOD_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+
+    // The expiry is reported once
+    contract
+        .actualize_subscriptions_at_time(3 * PERIOD_MS)
+        .unwrap();
+    let raw_events = recorded_events().collect::<Vec<_>>();
+    if let Event::SubscriptionExpired(SubscriptionExpired { app, end_ms }) =
+        decode_event(raw_events.last().unwrap())
+    {
+        assert_eq!(app, app_id);
+        assert_eq!(end_ms, 2 * PERIOD_MS);
+    } else {
+        panic!("Wrong event type")
+    }
+    let events_before = recorded_events().count();
+    contract
+        .actualize_subscriptions_at_time(4 * PERIOD_MS)
+        .unwrap();
+    assert_eq!(recorded_events().count(), events_before);
+}
+
+// ---- Property tests ----
+
+const PROPERTY_CASES: usize = 500;
+
+/// Small deterministic xorshift generator, so failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `[0, bound)`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[ink::test]
+fn get_current_period_days_properties() {
+    let mut rng = Rng(0x5eed_0001);
+
+    for _ in 0..PROPERTY_CASES {
+        let subscription_start_ms = rng.below(1000 * MS_PER_DAY);
+        let now_ms = subscription_start_ms + rng.below(10 * PERIOD_MS);
+
+        let (period_start_days, now_days) =
+            get_current_period_days(subscription_start_ms, now_ms, PERIOD_DAYS);
+        let start_days = subscription_start_ms / MS_PER_DAY;
+
+        assert_eq!(now_days, now_ms / MS_PER_DAY);
+        // The current period started on an anniversary of the subscription...
+        assert!(period_start_days >= start_days);
+        assert_eq!((period_start_days - start_days) % PERIOD_DAYS, 0);
+        // ...and includes today, which is one of its PERIOD_DAYS days.
+        assert!(period_start_days <= now_days);
+        assert!(now_days - period_start_days < PERIOD_DAYS);
+    }
+}
+
+#[ink::test]
+fn get_median_properties() {
+    let mut rng = Rng(0x5eed_0002);
+
+    for _ in 0..PROPERTY_CASES {
+        let length = rng.below(12) as usize;
+        let values: Vec<u64> = (0..length).map(|_| rng.below(20)).collect();
+
+        let median = get_median(values.clone());
+        if length == 0 {
+            assert_eq!(median, None);
+            continue;
+        }
+        let median = median.unwrap();
+
+        // The median is one of the values, with the lower half before it.
+        assert!(values.contains(&median));
+        let below = values.iter().filter(|v| **v < median).count();
+        let above = values.iter().filter(|v| **v > median).count();
+        assert!(below <= (length - 1) / 2);
+        assert!(above <= length / 2);
+
+        // The order of the reports does not matter.
+        let mut shuffled = values.clone();
+        for i in (1..length).rev() {
+            shuffled.swap(i, rng.below(i as u64 + 1) as usize);
+        }
+        assert_eq!(get_median(shuffled), Some(median));
+    }
+}
+
+#[ink::test]
+fn end_date_properties() {
+    let mut rng = Rng(0x5eed_0003);
+    let contract = make_contract();
+
+    for _ in 0..PROPERTY_CASES {
+        let tier_id = 1 + rng.below(3);
+        let tier = contract.service_tiers.get(&tier_id).unwrap().clone();
+        let periods = rng.below(24) as u128;
+        let last_update_ms = rng.below(1000 * MS_PER_DAY);
+
+        let subscription = AppSubscription {
+            start_date_ms: last_update_ms,
+            tier_id,
+            last_update_ms,
+            balance: periods * tier.tier_fee,
+            tier_limit: tier.limit(),
+            spending_cap: None,
+            auto_renew: true,
+            renewal_end_ms: 0,
+        };
+
+        // Each paid fee buys exactly one period...
+        let end_date_ms = contract.get_end_date_ms(&subscription);
+        assert_eq!(end_date_ms, last_update_ms + periods as u64 * PERIOD_MS);
+
+        // ...and the whole balance is consumed at the end date.
+        assert_eq!(
+            Ddc::get_consumed_balance_at_time(
+                end_date_ms,
+                &subscription,
+                &tier,
+                RoundingPolicy::Floor,
+                PERIOD_MS
+            ),
+            Ok(subscription.balance)
+        );
+
+        let more = AppSubscription {
+            balance: subscription.balance + 1,
+            ..subscription.clone()
+        };
+        assert!(contract.get_end_date_ms(&more) >= end_date_ms);
+    }
+}
+
+#[ink::test]
+fn day_aggregate_properties() {
+    let mut rng = Rng(0x5eed_0004);
+    let accounts = get_accounts();
+    let inspectors = [
+        accounts.alice,
+        accounts.bob,
+        accounts.charlie,
+        accounts.django,
+        accounts.eve,
+        accounts.frank,
+    ];
+    let app_id = AccountId::from([0x42; 32]);
+
+    for _ in 0..PROPERTY_CASES / 10 {
+        let mut contract = make_contract();
+        let day_start_ms = rng.below(PERIOD_DAYS) * MS_PER_DAY;
+
+        let mut storage_bytes = Vec::new();
+        for inspector in inspectors.iter() {
+            if rng.below(2) == 0 {
+                continue;
+            }
+            contract.add_inspector(*inspector).unwrap();
+
+            let value = rng.below(1000);
+            set_exec_context(*inspector, 0);
+            contract
+                .report_metrics(app_id, day_start_ms, value, value, value)
+                .unwrap();
+            undo_set_exec_context();
+            storage_bytes.push(value);
+        }
+
+        let aggregate = contract
+            .preview_day_aggregate(app_id, day_start_ms)
+            .unwrap();
+        assert_eq!(aggregate.inspectors_count, storage_bytes.len() as u32);
+        assert_eq!(
+            aggregate.metrics.storage_bytes,
+            get_median(storage_bytes).unwrap_or(0)
+        );
+        assert_eq!(aggregate.metrics.start_ms, day_start_ms);
+    }
 }
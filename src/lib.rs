@@ -5,6 +5,11 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod ddc {
+    use core::cmp::Ordering;
+    use ink_env::{
+        call::{build_call, ExecutionInput, Selector},
+        hash::{Blake2x256, HashOutput},
+    };
     use ink_prelude::string::String;
     use ink_prelude::vec::Vec;
     use ink_storage::{
@@ -20,33 +25,246 @@ mod ddc {
         // -- Admin --
         /// Owner of Contract.
         owner: Lazy<AccountId>,
+        /// Admin roles delegated by the owner.
+        roles: StorageHashMap<(AccountId, Role), ()>,
         pause: bool,
 
         // -- Tiers --
         service_tiers: StorageHashMap<u64, ServiceTier>,
+        /// Future fee change of each tier.
+        scheduled_fees: StorageHashMap<u64, ScheduledFee>,
+        /// Time of the applied fee changes of each tier and the fee before them, oldest first.
+        fee_history: StorageHashMap<u64, Vec<(u64, Balance)>>,
+        /// Number of apps counted towards the capacity of each tier.
+        tier_subscribers: StorageHashMap<u64, u64>,
+        /// Mapping from app to the tier it is counted in, until its subscription ends.
+        subscriber_tiers: StorageHashMap<AccountId, u64>,
 
         // -- App Subscriptions --
         /// Mapping from owner to number of owned coins.
         subscriptions: StorageHashMap<AccountId, AppSubscription>,
+        /// Whether `subscribe` must not change the tier of an active subscription.
+        reject_implicit_tier_change: bool,
+        /// Remaining prepaid days under which an app is warned during actualization.
+        low_balance_threshold_days: u64,
+        /// Time during which an app whose prepaid balance is consumed keeps its paid limits.
+        grace_period_ms: u64,
+        /// Whether subscriptions can no longer be imported from a previous deployment.
+        import_finished: bool,
+
+        /// Mapping from app to the account receiving its refunds.
+        refund_addresses: StorageHashMap<AccountId, DelayedAddress>,
+        /// Mapping from app to the payer account it approved to manage it.
+        app_payers: StorageHashMap<AccountId, AccountId>,
+        /// Funds paid by payers into the balance of each app, oldest first.
+        payer_funds: StorageHashMap<AccountId, Vec<(AccountId, Balance)>>,
+
+        // -- Sponsors --
+        sponsor_pools: StorageHashMap<AccountId, Balance>,
+        /// Mapping from app to its sponsor.
+        sponsored_apps: StorageHashMap<AccountId, AccountId>,
+        /// Sponsorships offered by sponsors and not yet accepted by the apps.
+        sponsor_offers: StorageHashMap<(AccountId, AccountId), ()>,
+        /// Funds paid by sponsors into the balance of each app, oldest first.
+        sponsored_funds: StorageHashMap<AccountId, Vec<(AccountId, Balance)>>,
+        /// Pending gifts, by gifter and recipient.
+        gifts: StorageHashMap<(AccountId, AccountId), Gift>,
+        /// Length of the free trial in days, 0 if there is none, and its tier.
+        trial_days: u64,
+        trial_tier_id: u64,
+        /// End of the trial of each account which used it.
+        trials: StorageHashMap<AccountId, u64>,
+        /// Promotional codes by hash of the code.
+        promos: StorageHashMap<Hash, Promo>,
+        /// Part of the balance of each app which was not received or was paid out already,
+        /// and is not refundable: promotional discounts and referral rewards.
+        non_refundable: StorageHashMap<AccountId, Balance>,
+        /// Share of the first payment of a referred app rewarded to the referrer, in basis points.
+        referral_bps: u32,
+        referral_stats: StorageHashMap<AccountId, ReferralStats>,
+
+        /// Access granted by an app to a DDN node or gateway.
+        access_grants: StorageHashMap<(AccountId, String), AccessGrant>,
+        /// Accounts allowed to read the private queries about an account, by account.
+        read_delegates: StorageHashMap<(AccountId, AccountId), ()>,
+
+        // -- Storage footprint --
+        storage_footprints: StorageHashMap<AccountId, StorageFootprint>,
+        /// Refundable deposit required for each item created by users and cleaned up by them.
+        storage_deposit_per_item: Balance,
 
         // -- Admin: Inspectors --
         inspectors: StorageHashMap<AccountId, ()>,
         current_period_ms: StorageHashMap<AccountId, u64>,
 
+        // -- Inspector rebates --
+        inspector_reward_pool: Balance,
+        inspector_rebate_per_report: Balance,
+        inspector_rebates: StorageHashMap<AccountId, Balance>,
+        /// Last day credited for the reports of each inspector on each app or node, by day of
+        /// period.
+        rebated_app_reports: StorageHashMap<(AccountId, AccountId, u64), u64>,
+        rebated_node_reports: StorageHashMap<(AccountId, String, u64), u64>,
+
+        // -- Inspector delegations --
+        inspector_delegations: StorageHashMap<(AccountId, AccountId), Balance>,
+        inspector_stakes: StorageHashMap<AccountId, Balance>,
+        /// Stake of an inspector at the start of the days it changed, by day of period.
+        inspector_stake_history: StorageHashMap<(AccountId, u64), (u64, Balance)>,
+        /// Undelegated tokens and the time they can be withdrawn, by delegator and inspector.
+        inspector_unbondings: StorageHashMap<(AccountId, AccountId), (Balance, u64)>,
+        stake_per_weight: Balance,
+
         // -- DDC Node managers --
         ddn_managers: StorageHashMap<AccountId, ()>,
+        /// Accounts administrating the tiers and nodes of a namespace (a logical DDC network).
+        namespace_admins: StorageHashMap<(u8, AccountId), ()>,
+
+        // -- Clusters --
+        /// Tiers offered in each cluster.
+        cluster_tiers: StorageHashMap<(u32, u64), ()>,
+        /// Mapping from app to the cluster serving it.
+        app_clusters: StorageHashMap<AccountId, u32>,
+        clusters: StorageHashMap<u32, Cluster>,
+        /// Mapping from DDC node to its cluster.
+        node_clusters: StorageHashMap<String, u32>,
+        /// SLA targets of each cluster.
+        cluster_slas: StorageHashMap<u32, ClusterSla>,
+        /// Compliance of the clusters with their SLA, by cluster and rewarded period start.
+        cluster_sla_reports: StorageHashMap<(u32, u64), ClusterSlaReport>,
 
         // -- DDC Nodes --
         ddc_nodes: StorageHashMap<String, DDCNode>,
 
+        /// Mapping from DDC node to the account receiving its rewards.
+        node_payout_addresses: StorageHashMap<String, DelayedAddress>,
+        /// Capacities declared for each DDC node, oldest first.
+        node_capacities: StorageHashMap<String, Vec<NodeCapacity>>,
+        /// Where to notify the operator of each DDC node, e.g. a CID.
+        node_contacts: StorageHashMap<String, String>,
+        /// Time of the last update of the record of each DDC node, kept after its removal.
+        ddc_node_updated_ms: StorageHashMap<String, u64>,
+        node_update_cooldown_ms: u64,
+        /// Maximum number of DDC nodes, unlimited if None. Further nodes wait in the queue.
+        target_node_count: Option<u32>,
+        /// Nodes waiting to be admitted, first in first out, by index in the queue. The
+        /// indexes of the nodes which left the queue are skipped.
+        ddc_node_queue: StorageHashMap<u32, String>,
+        /// Index of the next node to admit, and the index after the last queued node.
+        node_queue_head: u32,
+        node_queue_tail: u32,
+        /// Records of the queued nodes, with their index in the queue.
+        queued_nodes: StorageHashMap<String, (u32, DDCNode)>,
+        /// Deposit of the self-registered DDC nodes, slashable by the owner.
+        node_stakes: StorageHashMap<String, NodeStake>,
+        min_node_stake: Balance,
+        /// Nodes which requested to join, waiting for the approval of the owner.
+        join_requests: StorageHashMap<String, JoinRequest>,
+
         // -- Statuses of DDC Nodes--
         ddn_statuses: StorageHashMap<DDNStatusKey, DDNStatus>,
 
         // -- Metrics Reporting --
         pub metrics: StorageHashMap<MetricKey, MetricValue>,
         pub metrics_ddn: StorageHashMap<MetricKeyDDN, MetricValue>,
+        /// Number of metrics entries stored for each app and each DDC node.
+        app_metric_entries: StorageHashMap<AccountId, u32>,
+        node_metric_entries: StorageHashMap<String, u32>,
+        /// Metrics entries of apps without subscription and of DDC nodes no longer known.
+        orphaned_metrics: u32,
+        /// Medians of the DDN metrics of the finalized days, by node and day of period.
+        ddn_day_summaries: StorageHashMap<(String, u64), MetricValue>,
+        pub metrics_bucket: StorageHashMap<MetricKeyBucket, MetricValue>,
+        /// Buckets reported for each app, to prune their metrics with the app.
+        reported_buckets: StorageHashMap<AccountId, Vec<BucketId>>,
+        no_report_policy: NoReportPolicy,
+        /// Number of days, up to today, in which inspectors can report apps. Unlimited if None.
+        report_window_days: Option<u64>,
+        rounding_policy: RoundingPolicy,
+        /// Number of distinct inspectors expected to report each app in a period.
+        coverage_quorum: u32,
+        /// Length of the billing period, the tier fees being per period.
+        period_days: u64,
+        /// Minimum number of reported days in a period for a node to be eligible for payouts.
+        min_reported_days: u64,
+        /// Usage figures attested by the apps themselves, by app and day of period.
+        app_attestations: StorageHashMap<(AccountId, u64), MetricValue>,
+        attestation_tolerance_bps: u32,
+
+        /// Content hash (e.g. IPFS CID) of the usage export of each period, by period start.
+        period_exports: StorageHashMap<u64, String>,
+        /// Apps with unbillable usage in the period of each committed export.
+        period_unbillable_apps: StorageHashMap<u64, Vec<AccountId>>,
 
         pub total_ddc_balance: Balance,
+
+        /// Contract receiving the treasury share of the revenue, if any.
+        payment_sink: Option<AccountId>,
+        /// Part of `total_ddc_balance` already forwarded to a payment sink, burned,
+        /// or distributed to the nodes.
+        revenue_forwarded: Balance,
+        /// Share of the settled revenue burned, in basis points.
+        burn_bps: u32,
+        burn_address: AccountId,
+        total_burned: Balance,
+        /// Share of the refunds retained by the contract, in basis points.
+        refund_fee_bps: u32,
+
+        /// Rewards of the node operators, not claimed yet.
+        node_rewards: StorageHashMap<AccountId, Balance>,
+        /// Revenue earned each day and not distributed to the nodes yet, by day.
+        daily_revenues: StorageHashMap<u64, Balance>,
+        /// Start of the next period whose revenue is to be distributed to the nodes,
+        /// None before the first distribution.
+        next_reward_period_ms: Option<u64>,
+        /// Downtime of the nodes reported by each inspector, by inspector, node and day
+        /// of period, with the day.
+        ddn_day_downtimes: StorageHashMap<(AccountId, String, u64), (u64, u64)>,
+        /// Slashes of the node stakes to withhold from the next rewards, by node.
+        node_penalties: StorageHashMap<String, Balance>,
+        /// How the rewards were computed, by node and period start.
+        payout_statements: StorageHashMap<(String, u64), PayoutStatement>,
+        reward_weighting: RewardWeighting,
+
+        /// Clock skew between the reporters and the chain accepted by the time checks.
+        clock_skew_tolerance_ms: u64,
+        skew_policy: SkewPolicy,
+
+        /// Archived apps and nodes, with the time of archival.
+        archived_apps: StorageHashMap<AccountId, u64>,
+        archived_nodes: StorageHashMap<String, u64>,
+
+        /// Open disputes of metrics, by app and day start.
+        disputes: StorageHashMap<(AccountId, u64), Dispute>,
+        /// Number of open disputes by day, and by app.
+        day_disputes: StorageHashMap<u64, u32>,
+        app_disputes: StorageHashMap<AccountId, u32>,
+        /// Metrics corrected by the arbiters, by app and day of period. The reports are kept.
+        corrected_metrics: StorageHashMap<(AccountId, u64), MetricValue>,
+        /// Amount that can be withdrawn while disputes are open, and the amount withdrawn since.
+        dispute_withdraw_allowance: Balance,
+        withdrawn_in_dispute: Balance,
+
+        /// Deviation of the reports of each inspector from the median, and the days counted.
+        reporter_stats: StorageHashMap<AccountId, ReporterStats>,
+        /// Apps reported on each day, by day of period.
+        reported_apps: StorageHashMap<u64, (u64, Vec<AccountId>)>,
+        finalized_days: StorageHashMap<u64, ()>,
+        /// Reputation under which inspectors are suspended, None to never suspend.
+        suspension_threshold: Option<u64>,
+        suspended_inspectors: StorageHashMap<AccountId, ()>,
+
+        /// Operations processed in each period, by period start.
+        ops_stats: StorageHashMap<u64, OpsStats>,
+
+        /// Rolling hash of all the state changes.
+        state_fingerprint: Hash,
+
+        // -- Guardian --
+        guardian: Option<AccountId>,
+        guardian_inactivity_ms: u64,
+        last_owner_activity_ms: u64,
+        guardian_claim_ms: Option<u64>,
     }
 
     impl Ddc {
@@ -57,21 +275,149 @@ mod ddc {
 
             Self {
                 owner: Lazy::new(caller),
+                roles: StorageHashMap::new(),
                 service_tiers: StorageHashMap::new(),
+                scheduled_fees: StorageHashMap::new(),
+                fee_history: StorageHashMap::new(),
+                tier_subscribers: StorageHashMap::new(),
+                subscriber_tiers: StorageHashMap::new(),
                 subscriptions: StorageHashMap::new(),
+                reject_implicit_tier_change: false,
+                low_balance_threshold_days: 0,
+                grace_period_ms: 0,
+                import_finished: false,
+                refund_addresses: StorageHashMap::new(),
+                app_payers: StorageHashMap::new(),
+                payer_funds: StorageHashMap::new(),
+                sponsor_pools: StorageHashMap::new(),
+                sponsored_apps: StorageHashMap::new(),
+                sponsor_offers: StorageHashMap::new(),
+                sponsored_funds: StorageHashMap::new(),
+                gifts: StorageHashMap::new(),
+                trial_days: 0,
+                trial_tier_id: 0,
+                trials: StorageHashMap::new(),
+                promos: StorageHashMap::new(),
+                non_refundable: StorageHashMap::new(),
+                referral_bps: 0,
+                referral_stats: StorageHashMap::new(),
+                access_grants: StorageHashMap::new(),
+                read_delegates: StorageHashMap::new(),
+                storage_footprints: StorageHashMap::new(),
+                storage_deposit_per_item: 0,
                 inspectors: StorageHashMap::new(),
                 ddn_managers: StorageHashMap::new(),
+                namespace_admins: StorageHashMap::new(),
+                cluster_tiers: StorageHashMap::new(),
+                app_clusters: StorageHashMap::new(),
+                clusters: StorageHashMap::new(),
+                node_clusters: StorageHashMap::new(),
+                cluster_slas: StorageHashMap::new(),
+                cluster_sla_reports: StorageHashMap::new(),
                 current_period_ms: StorageHashMap::new(),
+                inspector_reward_pool: 0,
+                inspector_rebate_per_report: 0,
+                inspector_rebates: StorageHashMap::new(),
+                rebated_app_reports: StorageHashMap::new(),
+                rebated_node_reports: StorageHashMap::new(),
+                inspector_delegations: StorageHashMap::new(),
+                inspector_stakes: StorageHashMap::new(),
+                inspector_stake_history: StorageHashMap::new(),
+                inspector_unbondings: StorageHashMap::new(),
+                stake_per_weight: 0,
                 ddc_nodes: StorageHashMap::new(),
+                node_payout_addresses: StorageHashMap::new(),
+                node_capacities: StorageHashMap::new(),
+                node_contacts: StorageHashMap::new(),
+                ddc_node_updated_ms: StorageHashMap::new(),
+                node_update_cooldown_ms: NODE_UPDATE_COOLDOWN_MS,
+                target_node_count: None,
+                ddc_node_queue: StorageHashMap::new(),
+                node_queue_head: 0,
+                node_queue_tail: 0,
+                queued_nodes: StorageHashMap::new(),
+                node_stakes: StorageHashMap::new(),
+                min_node_stake: 0,
+                join_requests: StorageHashMap::new(),
                 ddn_statuses: StorageHashMap::new(),
                 metrics: StorageHashMap::new(),
                 metrics_ddn: StorageHashMap::new(),
+                app_metric_entries: StorageHashMap::new(),
+                node_metric_entries: StorageHashMap::new(),
+                orphaned_metrics: 0,
+                ddn_day_summaries: StorageHashMap::new(),
+                metrics_bucket: StorageHashMap::new(),
+                reported_buckets: StorageHashMap::new(),
+                no_report_policy: NoReportPolicy::TreatAsZero,
+                report_window_days: None,
+                rounding_policy: RoundingPolicy::Floor,
+                coverage_quorum: 1,
+                period_days: PERIOD_DAYS,
+                min_reported_days: 0,
+                app_attestations: StorageHashMap::new(),
+                attestation_tolerance_bps: 0,
+                period_exports: StorageHashMap::new(),
+                period_unbillable_apps: StorageHashMap::new(),
                 pause: false,
                 total_ddc_balance: 0,
+                payment_sink: None,
+                revenue_forwarded: 0,
+                burn_bps: 0,
+                refund_fee_bps: 0,
+                burn_address: AccountId::default(),
+                total_burned: 0,
+                node_rewards: StorageHashMap::new(),
+                daily_revenues: StorageHashMap::new(),
+                next_reward_period_ms: None,
+                ddn_day_downtimes: StorageHashMap::new(),
+                node_penalties: StorageHashMap::new(),
+                payout_statements: StorageHashMap::new(),
+                reward_weighting: RewardWeighting::Uptime,
+                clock_skew_tolerance_ms: 0,
+                skew_policy: SkewPolicy::Reject,
+                archived_apps: StorageHashMap::new(),
+                archived_nodes: StorageHashMap::new(),
+                disputes: StorageHashMap::new(),
+                day_disputes: StorageHashMap::new(),
+                app_disputes: StorageHashMap::new(),
+                corrected_metrics: StorageHashMap::new(),
+                dispute_withdraw_allowance: 0,
+                withdrawn_in_dispute: 0,
+                reporter_stats: StorageHashMap::new(),
+                reported_apps: StorageHashMap::new(),
+                finalized_days: StorageHashMap::new(),
+                suspension_threshold: None,
+                suspended_inspectors: StorageHashMap::new(),
+                ops_stats: StorageHashMap::new(),
+                state_fingerprint: Hash::default(),
+                guardian: None,
+                guardian_inactivity_ms: 0,
+                last_owner_activity_ms: Self::env().block_timestamp(),
+                guardian_claim_ms: None,
             }
         }
     }
 
+    // ---- API compatibility ----
+
+    /// Version of the message API of this contract, increased with each release changing it.
+    ///
+    /// The messages of the original API pin their selectors explicitly, so they remain callable
+    /// at the same selectors whatever happens to their Rust names. New functionality is exposed
+    /// under new selectors and never reuses a pinned one.
+    ///
+    /// Version 2 breaks clients of version 1: `ServiceTier` and `AppSubscription` have new
+    /// fields in their SCALE encoding, and unauthorized callers of the admin messages get
+    /// `MissingRole` instead of `OnlyOwner`. See the release notes for the other changes.
+    pub const CONTRACT_API_VERSION: u32 = 2;
+
+    impl Ddc {
+        #[ink(message)]
+        pub fn contract_api_version(&self) -> u32 {
+            CONTRACT_API_VERSION
+        }
+    }
+
     // ---- Admin ----
     impl Ddc {
         /// Check if account is the owner of this contract
@@ -86,14 +432,200 @@ mod ddc {
         }
 
         /// Transfer the contract admin to the accoung provided
-        #[ink(message)]
+        #[ink(message, selector = "0x107e33ea")]
         pub fn transfer_ownership(&mut self, to: AccountId) -> Result<()> {
             self.only_active()?;
             self.only_owner()?;
 
             *self.owner = to;
+            self.record_state_change(&("transfer_ownership", to));
+            Ok(())
+        }
+    }
+
+    // ---- Admin: Roles ----
+
+    /// Admin operations which the owner can delegate to other accounts
+    #[derive(
+        Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+        feature = "std",
+        derive(Debug, scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum Role {
+        /// Add tiers and change their fees and limits.
+        TierManager,
+        /// Add and remove DDC nodes, like a DDN manager.
+        NodeManager,
+        /// Withdraw funds from the contract.
+        Treasurer,
+        /// Pause and unpause the contract.
+        Pauser,
+        /// Resolve the disputes of apps about their metrics.
+        Arbiter,
+    }
+
+    #[ink(event)]
+    pub struct RoleGranted {
+        #[ink(topic)]
+        account: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        #[ink(topic)]
+        account: AccountId,
+        role: Role,
+    }
+
+    impl Ddc {
+        /// Check if the caller is the owner or has the given role
+        fn only_role(&self, role: Role) -> Result<()> {
+            let caller = self.env().caller();
+
+            if *self.owner == caller || self.has_role(caller, role) {
+                Ok(())
+            } else {
+                Err(Error::MissingRole)
+            }
+        }
+
+        #[ink(message)]
+        pub fn has_role(&self, account: AccountId, role: Role) -> bool {
+            self.roles.contains_key(&(account, role))
+        }
+
+        /// As owner, allow an account to perform the operations of a role
+        #[ink(message)]
+        pub fn grant_role(&mut self, account: AccountId, role: Role) -> Result<()> {
+            self.only_owner()?;
+
+            self.roles.insert((account, role), ());
+            self.record_state_change(&("grant_role", account, role));
+            Self::env().emit_event(RoleGranted { account, role });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn revoke_role(&mut self, account: AccountId, role: Role) -> Result<()> {
+            self.only_owner()?;
+
+            self.roles.take(&(account, role));
+            self.record_state_change(&("revoke_role", account, role));
+            Self::env().emit_event(RoleRevoked { account, role });
+
+            Ok(())
+        }
+    }
+
+    // ---- Admin: Guardian ----
+
+    /// Time between the announcement of a guardian claim and the transfer of the ownership.
+    const GUARDIAN_CLAIM_DELAY_MS: u64 = 7 * MS_PER_DAY;
+
+    #[ink(event)]
+    pub struct GuardianClaimAnnounced {
+        #[ink(topic)]
+        guardian: AccountId,
+        claimable_ms: u64,
+    }
+
+    impl Ddc {
+        /// As owner, set the account which can claim the ownership after the owner has been
+        /// inactive for `inactivity_ms`, or None to disable it
+        #[ink(message)]
+        pub fn set_guardian(
+            &mut self,
+            guardian: Option<AccountId>,
+            inactivity_ms: u64,
+        ) -> Result<()> {
+            self.only_owner()?;
+
+            self.guardian = guardian;
+            self.guardian_inactivity_ms = inactivity_ms;
+            self.guardian_claim_ms = None;
+            self.record_state_change(&("set_guardian", guardian, inactivity_ms));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_guardian(&self) -> Option<AccountId> {
+            self.guardian
+        }
+
+        /// Return the time of the last admin action of the owner
+        #[ink(message)]
+        pub fn get_last_owner_activity(&self) -> u64 {
+            self.last_owner_activity_ms
+        }
+
+        /// As guardian, announce the intent to claim the ownership of an inactive owner
+        #[ink(message)]
+        pub fn announce_guardian_claim(&mut self) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.announce_guardian_claim_at_time(now_ms)
+        }
+
+        pub fn announce_guardian_claim_at_time(&mut self, now_ms: u64) -> Result<()> {
+            let guardian = self.only_guardian()?;
+
+            if now_ms < self.last_owner_activity_ms + self.guardian_inactivity_ms {
+                return Err(Error::OwnerStillActive);
+            }
+
+            self.guardian_claim_ms = Some(now_ms);
+            self.record_state_change(&("announce_guardian_claim", guardian));
+            Self::env().emit_event(GuardianClaimAnnounced {
+                guardian,
+                claimable_ms: now_ms + GUARDIAN_CLAIM_DELAY_MS,
+            });
+
+            Ok(())
+        }
+
+        /// As guardian, take the ownership once GUARDIAN_CLAIM_DELAY_MS have passed since the
+        /// announcement. Any admin action of the owner in between cancels the claim.
+        #[ink(message)]
+        pub fn claim_ownership(&mut self) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.claim_ownership_at_time(now_ms)
+        }
+
+        pub fn claim_ownership_at_time(&mut self, now_ms: u64) -> Result<()> {
+            let guardian = self.only_guardian()?;
+
+            let announced_ms = match self.guardian_claim_ms {
+                Some(announced_ms) if announced_ms > self.last_owner_activity_ms => announced_ms,
+                _ => return Err(Error::OwnerStillActive),
+            };
+            if now_ms < announced_ms + GUARDIAN_CLAIM_DELAY_MS {
+                return Err(Error::ClaimNotReady);
+            }
+
+            *self.owner = guardian;
+            self.guardian = None;
+            self.guardian_claim_ms = None;
+            self.last_owner_activity_ms = now_ms;
+            self.record_state_change(&("claim_ownership", guardian));
+
             Ok(())
         }
+
+        fn only_guardian(&self) -> Result<AccountId> {
+            let caller = self.env().caller();
+
+            if self.guardian == Some(caller) {
+                Ok(caller)
+            } else {
+                Err(Error::OnlyGuardian)
+            }
+        }
     }
 
     // ---- Admin: Funds ----
@@ -101,16 +633,17 @@ mod ddc {
         // This seems to be the endowment you give to the contract upon initializing it
         // Official recommendation is 1000
         /// Return the total balance held in this contract
-        #[ink(message)]
+        #[ink(message, selector = "0x1c18c110")]
         pub fn balance_of_contract(&self) -> Balance {
             self.env().balance()
         }
 
         /// As owner, withdraw tokens to the given account. The destination account can be the same
         /// as the contract owner. Some balance must be left in the contract as subsistence deposit.
-        #[ink(message)]
+        /// While metric disputes are open, only the dispute allowance can be withdrawn.
+        #[ink(message, selector = "0x410fcc9d")]
         pub fn withdraw(&mut self, destination: AccountId, amount: Balance) -> Result<()> {
-            self.only_owner()?;
+            self.only_role(Role::Treasurer)?;
 
             if destination == AccountId::default() {
                 return Err(Error::InvalidAccount);
@@ -123,16 +656,187 @@ mod ddc {
                 return Err(Error::InsufficientBalance);
             }
 
+            if !self.disputes.is_empty() {
+                let withdrawn = self.withdrawn_in_dispute.saturating_add(amount);
+                if withdrawn > self.dispute_withdraw_allowance {
+                    return Err(Error::WithdrawLocked);
+                }
+                self.withdrawn_in_dispute = withdrawn;
+            }
+
             match self.env().transfer(destination, amount) {
                 Err(_e) => Err(Error::TransferFailed),
                 Ok(_v) => Ok(()),
             }
         }
+
+        /// As owner, set the amount that can be withdrawn in total while metric disputes are open.
+        #[ink(message)]
+        pub fn set_dispute_withdraw_allowance(&mut self, allowance: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            self.dispute_withdraw_allowance = allowance;
+            self.record_state_change(&("set_dispute_withdraw_allowance", allowance));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_dispute_withdraw_allowance(&self) -> Balance {
+            self.dispute_withdraw_allowance
+        }
+    }
+
+    // ---- Admin: Payment sink ----
+
+    /// Selector of `receive_revenue`, the payable message a payment sink contract must implement.
+    const RECEIVE_REVENUE_SELECTOR: [u8; 4] = [0xab, 0xc7, 0x4f, 0x71];
+
+    const BPS: u32 = 10_000;
+
+    #[ink(event)]
+    pub struct RevenueForwarded {
+        #[ink(topic)]
+        sink: AccountId,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Burned {
+        value: Balance,
+        total_burned: Balance,
     }
 
-    // ---- Admin: Pausable ----
     impl Ddc {
+        /// As owner, set or unset the contract receiving the treasury share of the revenue.
+        /// The sink contract decides how to distribute it (DAO, team, burn, ...).
+        #[ink(message)]
+        pub fn set_payment_sink(&mut self, sink: Option<AccountId>) -> Result<()> {
+            self.only_owner()?;
+
+            if sink == Some(AccountId::default()) {
+                return Err(Error::InvalidAccount);
+            }
+
+            self.payment_sink = sink;
+            self.record_state_change(&("set_payment_sink", sink));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_payment_sink(&self) -> Option<AccountId> {
+            self.payment_sink
+        }
+
+        /// As owner, burn a share of the revenue at each settlement, in basis points, by sending
+        /// it to an unspendable burn address. The rest goes to the payment sink.
+        #[ink(message)]
+        pub fn set_revenue_burn(&mut self, burn_bps: u32, burn_address: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            if burn_bps > BPS {
+                return Err(Error::InvalidShare);
+            }
+            if burn_bps > 0 && burn_address == AccountId::default() {
+                return Err(Error::InvalidAccount);
+            }
+
+            self.burn_bps = burn_bps;
+            self.burn_address = burn_address;
+            self.record_state_change(&("set_revenue_burn", burn_bps, burn_address));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_revenue_burn(&self) -> (u32, AccountId) {
+            (self.burn_bps, self.burn_address)
+        }
+
+        /// Return the cumulated amount burned by settlements
+        #[ink(message)]
+        pub fn total_burned(&self) -> Balance {
+            self.total_burned
+        }
+
+        /// Split revenue into the burned share and the share forwarded to the payment sink
+        pub fn split_revenue(&self, value: Balance) -> Result<(Balance, Balance)> {
+            let burned = value
+                .checked_mul(self.burn_bps as Balance)
+                .ok_or(Error::ArithmeticError)?
+                / BPS as Balance;
+
+            Ok((burned, value - burned))
+        }
+
+        /// Return the settled revenue not forwarded to a payment sink yet
+        #[ink(message)]
+        pub fn get_unforwarded_revenue(&self) -> Balance {
+            self.total_ddc_balance
+                .saturating_sub(self.revenue_forwarded)
+        }
+
+        /// As owner, settle the subscriptions and forward the treasury share of the revenue
+        /// to the payment sink, by calling its `receive_revenue` message. The configured burn
+        /// share is sent to the burn address.
         #[ink(message)]
+        pub fn settle_revenue(&mut self) -> Result<()> {
+            self.only_owner()?;
+            let sink = self.payment_sink.ok_or(Error::NoPaymentSink)?;
+
+            self.actualize_subscriptions()?;
+
+            // The revenue of the periods not rewarded yet is kept for the nodes
+            let value = self
+                .get_unforwarded_revenue()
+                .saturating_sub(self.reserved_node_revenue());
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+            if self.env().balance() <= value {
+                return Err(Error::InsufficientBalance);
+            }
+
+            let (burned, forwarded) = self.split_revenue(value)?;
+
+            if forwarded > 0 {
+                build_call::<Environment>()
+                    .callee(sink)
+                    .gas_limit(0)
+                    .transferred_value(forwarded)
+                    .exec_input(ExecutionInput::new(Selector::new(RECEIVE_REVENUE_SELECTOR)))
+                    .returns::<()>()
+                    .fire()
+                    .map_err(|_| Error::TransferFailed)?;
+                Self::env().emit_event(RevenueForwarded {
+                    sink,
+                    value: forwarded,
+                });
+            }
+
+            if burned > 0 {
+                if self.env().transfer(self.burn_address, burned).is_err() {
+                    panic!("Transfer has failed!");
+                }
+                self.total_burned += burned;
+                Self::env().emit_event(Burned {
+                    value: burned,
+                    total_burned: self.total_burned,
+                });
+            }
+
+            self.revenue_forwarded += value;
+            self.ops_stats_mut().settlements += 1;
+            self.record_state_change(&("settle_revenue", sink, value));
+
+            Ok(())
+        }
+    }
+
+    // ---- Admin: Pausable ----
+    impl Ddc {
+        #[ink(message, selector = "0x3702620c")]
         pub fn paused_or_not(&self) -> bool {
             self.pause
         }
@@ -150,15 +854,38 @@ mod ddc {
         /// flip the status of contract, pause it if it is live
         /// unpause it if it is paused before
         /// only contract owner can call this function
-        #[ink(message)]
+        #[ink(message, selector = "0xf41d073f")]
         pub fn flip_contract_status(&mut self) -> Result<()> {
-            self.only_owner()?;
+            self.only_role(Role::Pauser)?;
 
             self.pause = !self.pause;
+            self.record_state_change(&("flip_contract_status", self.pause));
             Ok(())
         }
     }
 
+    // ---- State fingerprint ----
+    impl Ddc {
+        /// Return a rolling hash of all the state changes made to this contract.
+        ///
+        /// Indexers mirroring the contract state can fold the same changes to detect divergence.
+        #[ink(message)]
+        pub fn state_fingerprint(&self) -> Hash {
+            self.state_fingerprint
+        }
+
+        /// Fold a state change, identified by the message and the keys written, into the fingerprint
+        fn record_state_change<T: Encode>(&mut self, change: &T) {
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            ink_env::hash_encoded::<Blake2x256, _>(&(self.state_fingerprint, change), &mut output);
+            self.state_fingerprint = Hash::from(output);
+
+            if self.env().caller() == *self.owner {
+                self.last_owner_activity_ms = Self::env().block_timestamp();
+            }
+        }
+    }
+
     // ---- Admin: Tiers ----
 
     #[derive(scale::Encode, Clone, scale::Decode, SpreadLayout, PackedLayout)]
@@ -178,6 +905,15 @@ mod ddc {
         storage_bytes: u64,
         wcu_per_minute: u64,
         rcu_per_minute: u64,
+        /// Maximum number of active subscriptions to this tier, unlimited if None.
+        max_subscribers: Option<u64>,
+        /// DDC network of the tier, 0 for the default network.
+        namespace: u8,
+        /// Whether the tier can be subscribed to. Existing subscriptions keep working until expiry.
+        active: bool,
+        /// Display name and description of the tier, for front-ends.
+        name: String,
+        description: String,
     }
 
     impl ServiceTier {
@@ -194,8 +930,57 @@ mod ddc {
                 storage_bytes,
                 wcu_per_minute,
                 rcu_per_minute,
+                max_subscribers: None,
+                namespace: 0,
+                active: true,
+                name: String::new(),
+                description: String::new(),
             }
         }
+
+        /// Return the limits granted by this tier
+        pub fn limit(&self) -> AppSubscriptionLimit {
+            AppSubscriptionLimit::new(self.storage_bytes, self.wcu_per_minute, self.rcu_per_minute)
+        }
+    }
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct ScheduledFee {
+        tier_id: u64,
+        new_fee: Balance,
+        effective_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct TierFeeScheduled {
+        #[ink(topic)]
+        tier_id: u64,
+        new_fee: Balance,
+        effective_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct TierLimitChanged {
+        #[ink(topic)]
+        tier_id: u64,
+        storage_bytes: u64,
+        wcu_per_minute: u64,
+        rcu_per_minute: u64,
+    }
+
+    #[ink(event)]
+    pub struct TierDeactivated {
+        #[ink(topic)]
+        tier_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct TierRemoved {
+        #[ink(topic)]
+        tier_id: u64,
     }
 
     #[ink(event)]
@@ -205,6 +990,16 @@ mod ddc {
         storage_bytes: u64,
         wcu_per_minute: u64,
         rcu_per_minute: u64,
+        name: String,
+        description: String,
+    }
+
+    #[ink(event)]
+    pub struct TierMetadataChanged {
+        #[ink(topic)]
+        tier_id: u64,
+        name: String,
+        description: String,
     }
 
     impl Ddc {
@@ -220,7 +1015,7 @@ mod ddc {
             max + 1
         }
 
-        #[ink(message)]
+        #[ink(message, selector = "0xe1ca6609")]
         pub fn add_tier(
             &mut self,
             tier_fee: Balance,
@@ -228,42 +1023,352 @@ mod ddc {
             wcu_per_minute: u64,
             rcu_per_minute: u64,
         ) -> Result<u64> {
-            self.only_owner()?;
+            self.only_role(Role::TierManager)?;
 
-            let tier_id = self.calculate_new_tier_id();
-            let tier = ServiceTier {
-                tier_id,
+            self.insert_tier(
+                0,
                 tier_fee,
                 storage_bytes,
                 wcu_per_minute,
                 rcu_per_minute,
-            };
-            self.service_tiers.insert(tier_id, tier);
-            Self::env().emit_event(TierAdded {
-                tier_id,
-                tier_fee,
-                storage_bytes,
+                String::new(),
+                String::new(),
+            )
+        }
+
+        /// Add a tier with its display name and description, see `add_tier`
+        #[ink(message)]
+        pub fn add_named_tier(
+            &mut self,
+            tier_fee: Balance,
+            storage_bytes: u64,
+            wcu_per_minute: u64,
+            rcu_per_minute: u64,
+            name: String,
+            description: String,
+        ) -> Result<u64> {
+            self.only_role(Role::TierManager)?;
+
+            self.insert_tier(
+                0,
+                tier_fee,
+                storage_bytes,
+                wcu_per_minute,
+                rcu_per_minute,
+                name,
+                description,
+            )
+        }
+
+        /// As tier manager, change the display name and description of a tier
+        #[ink(message)]
+        pub fn set_tier_metadata(
+            &mut self,
+            tier_id: u64,
+            name: String,
+            description: String,
+        ) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_role(Role::TierManager)?;
+
+            let mut tier = self.service_tiers.get_mut(&tier_id).unwrap();
+            tier.name = name.clone();
+            tier.description = description.clone();
+            self.record_state_change(&("set_tier_metadata", tier_id));
+            Self::env().emit_event(TierMetadataChanged {
+                tier_id,
+                name,
+                description,
+            });
+
+            Ok(())
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn insert_tier(
+            &mut self,
+            namespace: u8,
+            tier_fee: Balance,
+            storage_bytes: u64,
+            wcu_per_minute: u64,
+            rcu_per_minute: u64,
+            name: String,
+            description: String,
+        ) -> Result<u64> {
+            let tier_id = self.calculate_new_tier_id();
+            let tier = ServiceTier {
+                tier_id,
+                tier_fee,
+                storage_bytes,
+                wcu_per_minute,
+                rcu_per_minute,
+                max_subscribers: None,
+                namespace,
+                active: true,
+                name: name.clone(),
+                description: description.clone(),
+            };
+            self.service_tiers.insert(tier_id, tier);
+            Self::env().emit_event(TierAdded {
+                tier_id,
+                tier_fee,
+                storage_bytes,
                 wcu_per_minute,
                 rcu_per_minute,
+                name,
+                description,
             });
+            self.record_state_change(&("add_tier", tier_id));
 
             Ok(tier_id)
         }
 
         /// return the fee required
-        #[ink(message)]
+        #[ink(message, selector = "0xd9e1c06a")]
         pub fn tier_deposit(&self, tier_id: u64) -> Balance {
             if self.tid_in_bound(tier_id).is_err() {
                 return 0 as Balance;
             }
 
-            let v = self.service_tiers.get(&tier_id).unwrap();
-            return v.tier_fee as Balance;
+            let now_ms = Self::env().block_timestamp();
+            self.tier_fee_at_time(tier_id, now_ms)
+        }
+
+        /// Return the fee of a tier effective at the given time, including scheduled changes
+        pub fn tier_fee_at_time(&self, tier_id: u64, now_ms: u64) -> Balance {
+            match self.scheduled_fees.get(&tier_id) {
+                Some(scheduled) if scheduled.effective_ms <= now_ms => scheduled.new_fee,
+                _ => self
+                    .service_tiers
+                    .get(&tier_id)
+                    .map_or(0, |tier| tier.tier_fee),
+            }
+        }
+
+        /// As owner, schedule a change of the fee of a tier at a future date.
+        /// This replaces any change already scheduled for this tier.
+        #[ink(message)]
+        pub fn schedule_tier_fee(
+            &mut self,
+            tier_id: u64,
+            new_fee: Balance,
+            effective_ms: u64,
+        ) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_active()?;
+            self.only_role(Role::TierManager)?;
+
+            let now_ms = Self::env().block_timestamp();
+            if effective_ms <= now_ms {
+                return Err(Error::UnexpectedTimestamp);
+            }
+            self.apply_scheduled_fee(tier_id, now_ms)?;
+
+            let scheduled = ScheduledFee {
+                tier_id,
+                new_fee,
+                effective_ms,
+            };
+            self.scheduled_fees.insert(tier_id, scheduled.clone());
+            Self::env().emit_event(TierFeeScheduled {
+                tier_id,
+                new_fee,
+                effective_ms,
+            });
+            self.record_state_change(&("schedule_tier_fee", scheduled));
+
+            Ok(())
         }
 
+        /// Return the fee changes scheduled and not effective yet
         #[ink(message)]
+        pub fn get_scheduled_fees(&self) -> Vec<ScheduledFee> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.scheduled_fees
+                .values()
+                .filter(|scheduled| scheduled.effective_ms > now_ms)
+                .cloned()
+                .collect()
+        }
+
+        /// Apply the fee change scheduled for a tier if it became effective. The previous fee
+        /// is kept for the subscriptions of the tier, which are settled at it until the change
+        /// when they are next touched, see `apply_scheduled_fee_of`.
+        fn apply_scheduled_fee(&mut self, tier_id: u64, now_ms: u64) -> Result<()> {
+            let scheduled = match self.scheduled_fees.get(&tier_id) {
+                Some(scheduled) if scheduled.effective_ms <= now_ms => scheduled.clone(),
+                _ => return Ok(()),
+            };
+
+            let tier = self.service_tiers.get_mut(&tier_id).unwrap();
+            let mut fee_history = self.fee_history.take(&tier_id).unwrap_or_default();
+            fee_history.push((scheduled.effective_ms, tier.tier_fee));
+            self.fee_history.insert(tier_id, fee_history);
+            tier.tier_fee = scheduled.new_fee;
+            self.scheduled_fees.take(&tier_id);
+
+            Ok(())
+        }
+
+        /// Apply the fee change scheduled for the tier of an app, then settle its subscription
+        /// at the previous fees until the last change
+        fn apply_scheduled_fee_of(&mut self, app: AccountId, now_ms: u64) -> Result<()> {
+            let (tier_id, last_update_ms) = match self.subscriptions.get(&app) {
+                Some(subscription) => (subscription.tier_id, subscription.last_update_ms),
+                None => return Ok(()),
+            };
+            self.apply_scheduled_fee(tier_id, now_ms)?;
+            let is_settled = self
+                .fee_history
+                .get(&tier_id)
+                .and_then(|fee_history| fee_history.last())
+                .map_or(true, |(change_ms, _)| *change_ms <= last_update_ms);
+            if is_settled {
+                return Ok(());
+            }
+
+            let period_ms = self.period_ms();
+            let subscription = self.subscriptions.get_mut(&app).unwrap();
+            let tier = self.service_tiers.get(&tier_id).unwrap();
+            self.total_ddc_balance += Self::settle_fee_changes(
+                subscription,
+                tier,
+                self.fee_history.get(&tier_id),
+                self.rounding_policy,
+                period_ms,
+                &mut self.daily_revenues,
+            )?;
+            if subscription.is_over_cap(tier) {
+                Self::env().emit_event(CapReached {
+                    app,
+                    spending_cap: subscription.spending_cap.unwrap_or_default(),
+                    tier_fee: tier.tier_fee,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Charge a subscription at the fees of its tier before each change since its last
+        /// update, until the last change
+        fn settle_fee_changes(
+            subscription: &mut AppSubscription,
+            tier: &ServiceTier,
+            fee_history: Option<&Vec<(u64, Balance)>>,
+            rounding: RoundingPolicy,
+            period_ms: u64,
+            revenues: &mut StorageHashMap<u64, Balance>,
+        ) -> Result<Balance> {
+            let mut charged = 0;
+            for (change_ms, previous_fee) in fee_history.into_iter().flatten() {
+                if *change_ms <= subscription.last_update_ms {
+                    continue;
+                }
+                let previous_tier = ServiceTier {
+                    tier_fee: *previous_fee,
+                    ..tier.clone()
+                };
+                charged += Self::actualize_subscription_at_time(
+                    *change_ms,
+                    subscription,
+                    &previous_tier,
+                    rounding,
+                    period_ms,
+                    revenues,
+                )?;
+            }
+
+            Ok(charged)
+        }
+
+        /// Apply the fee changes scheduled for all the tiers, see `apply_scheduled_fee`
+        fn apply_scheduled_fees(&mut self, now_ms: u64) -> Result<()> {
+            let tier_ids: Vec<u64> = self.scheduled_fees.keys().cloned().collect();
+            for tier_id in tier_ids {
+                self.apply_scheduled_fee(tier_id, now_ms)?;
+            }
+
+            Ok(())
+        }
+
+        /// A tier with the fee effective now, as queries see it before the fee change is
+        /// applied
+        fn effective_tier(&self, tier: &ServiceTier, now_ms: u64) -> ServiceTier {
+            ServiceTier {
+                tier_fee: self.tier_fee_at_time(tier.tier_id, now_ms),
+                ..tier.clone()
+            }
+        }
+
+        /// A subscription and its tier as they will be once the fee change of the tier
+        /// effective now is applied, for the queries
+        fn effective_subscription(
+            &self,
+            subscription: &AppSubscription,
+            now_ms: u64,
+        ) -> (AppSubscription, ServiceTier) {
+            let mut subscription = subscription.clone();
+            let tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+
+            // The applied changes not settled yet, then the change effective now
+            let mut fee_changes: Vec<(u64, Balance)> = self
+                .fee_history
+                .get(&tier.tier_id)
+                .map_or(Vec::new(), |fee_history| fee_history.clone());
+            if let Some(scheduled) = self.scheduled_fees.get(&tier.tier_id) {
+                if scheduled.effective_ms <= now_ms {
+                    fee_changes.push((scheduled.effective_ms, tier.tier_fee));
+                }
+            }
+            for (change_ms, previous_fee) in fee_changes {
+                if change_ms <= subscription.last_update_ms {
+                    continue;
+                }
+                let previous_tier = ServiceTier {
+                    tier_fee: previous_fee,
+                    ..tier.clone()
+                };
+                if !subscription.is_over_cap(&previous_tier) {
+                    let consumed = Self::get_consumed_balance_at_time(
+                        change_ms,
+                        &subscription,
+                        &previous_tier,
+                        self.rounding_policy,
+                        self.period_ms(),
+                    )
+                    .unwrap_or(subscription.balance);
+                    subscription.balance = subscription.balance.saturating_sub(consumed);
+                }
+                subscription.last_update_ms = change_ms;
+            }
+
+            (subscription, self.effective_tier(tier, now_ms))
+        }
+
+        #[ink(message, selector = "0x89d28b25")]
         pub fn get_all_tiers(&self) -> Vec<ServiceTier> {
-            self.service_tiers.values().cloned().collect()
+            let now_ms = Self::env().block_timestamp();
+
+            self.service_tiers
+                .values()
+                .map(|tier| self.effective_tier(tier, now_ms))
+                .collect()
+        }
+
+        /// Return a page of the tiers, starting at the cursor
+        #[ink(message)]
+        pub fn get_tiers_page(&self, cursor: u32, limit: u32) -> Page<ServiceTier> {
+            let now_ms = Self::env().block_timestamp();
+
+            paginate(
+                self.service_tiers
+                    .values()
+                    .map(|tier| self.effective_tier(tier, now_ms)),
+                self.service_tiers.len(),
+                cursor,
+                limit,
+            )
         }
 
         /// check if tid is within 1, 2 ,3
@@ -278,24 +1383,29 @@ mod ddc {
 
         /// change the tier fee given the tier id and new fee
         /// Must be the contract admin to call this function
-        #[ink(message)]
+        ///
+        /// This cancels the change scheduled for this tier, if any.
+        #[ink(message, selector = "0x308e4a4c")]
         pub fn change_tier_fee(&mut self, tier_id: u64, new_fee: Balance) -> Result<()> {
             self.tid_in_bound(tier_id)?;
             self.only_active()?;
-            self.only_owner()?;
+            self.only_role(Role::TierManager)?;
 
+            self.apply_scheduled_fee(tier_id, Self::env().block_timestamp())?;
             self.diff_deposit(tier_id, new_fee)?;
+            self.scheduled_fees.take(&tier_id);
 
             let mut tier = self.service_tiers.get_mut(&tier_id).unwrap();
 
             tier.tier_fee = new_fee;
+            self.record_state_change(&("change_tier_fee", tier_id));
 
             Ok(())
         }
 
         /// Change tier limit given tier id and a new limit
         /// Must be contract admin to call this function
-        #[ink(message)]
+        #[ink(message, selector = "0xe42d276d")]
         pub fn change_tier_limit(
             &mut self,
             tier_id: u64,
@@ -305,12 +1415,181 @@ mod ddc {
         ) -> Result<()> {
             self.tid_in_bound(tier_id)?;
             self.only_active()?;
-            self.only_owner()?;
+            self.only_role(Role::TierManager)?;
 
             let mut tier = self.service_tiers.get_mut(&tier_id).unwrap();
             tier.storage_bytes = new_storage_bytes_limit;
             tier.wcu_per_minute = new_wcu_limit;
             tier.rcu_per_minute = new_rcu_limit;
+            self.record_state_change(&("change_tier_limit", tier_id));
+
+            Ok(())
+        }
+
+        /// Scale the limits of all the tiers by `factor_bps` / 10000, e.g. when the capacity of
+        /// the network changes. Like `change_tier_limit`, subscriptions get the new limits
+        /// when they renew.
+        #[ink(message)]
+        pub fn rescale_tier_limits(&mut self, factor_bps: u32) -> Result<()> {
+            self.only_active()?;
+            self.only_role(Role::TierManager)?;
+
+            if factor_bps == 0 {
+                return Err(Error::InvalidShare);
+            }
+
+            let scale = |limit: u64| {
+                let scaled = limit as u128 * factor_bps as u128 / BPS as u128;
+                scaled.min(u64::MAX as u128) as u64
+            };
+            for tier in self.service_tiers.values_mut() {
+                tier.storage_bytes = scale(tier.storage_bytes);
+                tier.wcu_per_minute = scale(tier.wcu_per_minute);
+                tier.rcu_per_minute = scale(tier.rcu_per_minute);
+
+                Self::env().emit_event(TierLimitChanged {
+                    tier_id: tier.tier_id,
+                    storage_bytes: tier.storage_bytes,
+                    wcu_per_minute: tier.wcu_per_minute,
+                    rcu_per_minute: tier.rcu_per_minute,
+                });
+            }
+            self.record_state_change(&("rescale_tier_limits", factor_bps));
+
+            Ok(())
+        }
+
+        /// Limit the number of active subscriptions to a tier, or remove the limit with None
+        /// Must be contract admin to call this function
+        #[ink(message)]
+        pub fn set_tier_max_subscribers(
+            &mut self,
+            tier_id: u64,
+            max_subscribers: Option<u64>,
+        ) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_active()?;
+            self.only_role(Role::TierManager)?;
+
+            let mut tier = self.service_tiers.get_mut(&tier_id).unwrap();
+            tier.max_subscribers = max_subscribers;
+            self.record_state_change(&("set_tier_max_subscribers", tier_id));
+
+            Ok(())
+        }
+
+        /// As tier manager, stop offering a tier. Existing subscriptions keep working until expiry.
+        #[ink(message)]
+        pub fn deactivate_tier(&mut self, tier_id: u64) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_role(Role::TierManager)?;
+
+            let mut tier = self.service_tiers.get_mut(&tier_id).unwrap();
+            tier.active = false;
+            self.record_state_change(&("deactivate_tier", tier_id));
+            Self::env().emit_event(TierDeactivated { tier_id });
+
+            Ok(())
+        }
+
+        /// As tier manager, remove an inactive tier once no subscription refers to it anymore,
+        /// including expired ones
+        #[ink(message)]
+        pub fn remove_tier(&mut self, tier_id: u64) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_role(Role::TierManager)?;
+
+            if self.service_tiers.get(&tier_id).unwrap().active {
+                return Err(Error::TierActive);
+            }
+            let in_use = self
+                .subscriptions
+                .values()
+                .any(|subscription| subscription.tier_id == tier_id);
+            if in_use {
+                return Err(Error::TierInUse);
+            }
+
+            self.service_tiers.take(&tier_id);
+            self.scheduled_fees.take(&tier_id);
+            self.fee_history.take(&tier_id);
+            self.record_state_change(&("remove_tier", tier_id));
+            Self::env().emit_event(TierRemoved { tier_id });
+
+            Ok(())
+        }
+
+        /// Check that the tier is offered to new subscriptions
+        fn enforce_tier_active(&self, tier_id: u64) -> Result<()> {
+            match self.service_tiers.get(&tier_id) {
+                None => Err(Error::TidOutOfBound),
+                Some(tier) if !tier.active => Err(Error::TierInactive),
+                Some(_) => Ok(()),
+            }
+        }
+
+        /// Return the number of subscriptions counted towards the capacity of a tier. Expired
+        /// subscriptions are counted until `release_expired_subscription` is called.
+        #[ink(message)]
+        pub fn tier_subscriber_count(&self, tier_id: u64) -> u64 {
+            self.tier_subscribers.get(&tier_id).cloned().unwrap_or(0)
+        }
+
+        /// Check that the app can be counted towards the capacity of the tier
+        fn enforce_tier_capacity(&self, app: AccountId, tier_id: u64) -> Result<()> {
+            let max_subscribers = match self.service_tiers.get(&tier_id) {
+                None => return Err(Error::TidOutOfBound),
+                Some(tier) => tier.max_subscribers,
+            };
+            if self.subscriber_tiers.get(&app) == Some(&tier_id) {
+                return Ok(());
+            }
+
+            match max_subscribers {
+                Some(max) if self.tier_subscriber_count(tier_id) >= max => Err(Error::TierFull),
+                _ => Ok(()),
+            }
+        }
+
+        /// Count the app towards the capacity of a tier, or of none
+        fn count_subscriber(&mut self, app: AccountId, tier_id: Option<u64>) {
+            if let Some(old_tier_id) = self.subscriber_tiers.take(&app) {
+                if let Some(count) = self.tier_subscribers.get_mut(&old_tier_id) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+            if let Some(tier_id) = tier_id {
+                self.subscriber_tiers.insert(app, tier_id);
+                *self.tier_subscribers.entry(tier_id).or_insert(0) += 1;
+            }
+        }
+
+        /// Stop counting an expired subscription towards the capacity of its tier. Anyone can
+        /// call it, e.g. to make room in a full tier.
+        #[ink(message)]
+        pub fn release_expired_subscription(&mut self, app: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.release_expired_subscription_at_time(app, now_ms)
+        }
+
+        pub fn release_expired_subscription_at_time(
+            &mut self,
+            app: AccountId,
+            now_ms: u64,
+        ) -> Result<()> {
+            if !self.subscriber_tiers.contains_key(&app) {
+                return Err(Error::NoSubscription);
+            }
+            let is_active = self.subscriptions.get(&app).map_or(false, |subscription| {
+                self.get_end_date_ms(subscription) >= now_ms
+            });
+            if is_active {
+                return Err(Error::SubscriptionActive);
+            }
+
+            self.count_subscriber(app, None);
+            self.record_state_change(&("release_expired_subscription", app));
 
             Ok(())
         }
@@ -331,7 +1610,10 @@ mod ddc {
         fn get_tier_limit(&self, tier_id: u64) -> ServiceTier {
             self.tid_in_bound(tier_id).unwrap();
 
-            self.service_tiers.get(&tier_id).unwrap().clone()
+            self.effective_tier(
+                self.service_tiers.get(&tier_id).unwrap(),
+                Self::env().block_timestamp(),
+            )
         }
     }
 
@@ -342,45 +1624,199 @@ mod ddc {
     pub struct Deposit {
         #[ink(topic)]
         from: Option<AccountId>,
+        /// App whose subscription is credited.
+        #[ink(topic)]
+        to: AccountId,
         #[ink(topic)]
         value: Balance,
     }
 
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct AppSubscription {
-        start_date_ms: u64,
-        tier_id: u64,
+    /// event emit when the tier of an app costs more than its spending cap, suspending its service
+    #[ink(event)]
+    pub struct CapReached {
+        #[ink(topic)]
+        app: AccountId,
+        spending_cap: Balance,
+        tier_fee: Balance,
+    }
 
-        balance: Balance,
-        last_update_ms: u64, // initially creation time
+    /// event emit when an app cancels its subscription, which stops at the end of the period
+    #[ink(event)]
+    pub struct SubscriptionCancelled {
+        #[ink(topic)]
+        app: AccountId,
+        end_ms: u64,
     }
 
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct AppSubscriptionDetails {
-        subscription: AppSubscription,
+    /// event emit when an app subscribes, or subscribes again after its subscription ended
+    #[ink(event)]
+    pub struct SubscriptionCreated {
+        #[ink(topic)]
+        app: AccountId,
+        tier_id: u64,
         end_date_ms: u64,
     }
 
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct AppSubscriptionLimit {
-        storage_bytes: u64,
-        wcu_per_minute: u64,
-        rcu_per_minute: u64,
+    /// event emit when an app tops up its active subscription
+    #[ink(event)]
+    pub struct SubscriptionExtended {
+        #[ink(topic)]
+        app: AccountId,
+        end_date_ms: u64,
     }
 
-    impl AppSubscriptionLimit {
-        pub fn new(
-            storage_bytes: u64,
-            wcu_per_minute: u64,
+    /// event emit when the prepaid time of an app ends, found when actualizing the subscriptions.
+    /// The app may still be in its grace period.
+    #[ink(event)]
+    pub struct SubscriptionExpired {
+        #[ink(topic)]
+        app: AccountId,
+        end_ms: u64,
+    }
+
+    /// event emit when an app moves its subscription to another tier
+    #[ink(event)]
+    pub struct SubscriptionTierChanged {
+        #[ink(topic)]
+        app: AccountId,
+        old_tier_id: u64,
+        new_tier_id: u64,
+        /// End of the prepaid time, the remaining balance being converted at the new fee.
+        end_date_ms: u64,
+    }
+
+    /// event emit when an app is refunded, minus the early termination fee
+    #[ink(event)]
+    pub struct Refunded {
+        #[ink(topic)]
+        app: AccountId,
+        value: Balance,
+        fee: Balance,
+    }
+
+    /// event emit when an app moves its subscription to another account
+    #[ink(event)]
+    pub struct SubscriptionTransferred {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+    }
+
+    /// event emit when the prepaid balance of an app is consumed but it keeps its paid limits
+    /// until the end of the grace period
+    #[ink(event)]
+    pub struct SubscriptionInGrace {
+        #[ink(topic)]
+        app: AccountId,
+        grace_end_ms: u64,
+    }
+
+    /// event emit when the prepaid time of an app falls below the low balance threshold
+    #[ink(event)]
+    pub struct LowBalance {
+        #[ink(topic)]
+        app: AccountId,
+        remaining_days: u64,
+    }
+
+    /// How to round the integer divisions of the prepaid time and consumption math
+    #[derive(Copy, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(Debug, scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum RoundingPolicy {
+        /// Round down.
+        Floor,
+        /// Round up.
+        Ceil,
+        /// Round to the nearest, and halves to the even neighbour.
+        Bankers,
+    }
+
+    impl RoundingPolicy {
+        pub fn div(self, numerator: u128, denominator: u128) -> u128 {
+            let quotient = numerator / denominator;
+            let remainder = numerator % denominator;
+
+            match self {
+                RoundingPolicy::Floor => quotient,
+                RoundingPolicy::Ceil => quotient + (remainder > 0) as u128,
+                RoundingPolicy::Bankers => match (remainder * 2).cmp(&denominator) {
+                    Ordering::Less => quotient,
+                    Ordering::Greater => quotient + 1,
+                    Ordering::Equal => quotient + quotient % 2,
+                },
+            }
+        }
+    }
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct AppSubscription {
+        start_date_ms: u64,
+        tier_id: u64,
+
+        balance: Balance,
+        last_update_ms: u64, // initially creation time
+
+        /// Tier limits in effect when the app last paid. Later changes of the tier limits
+        /// only apply to the app on its next payment.
+        tier_limit: AppSubscriptionLimit,
+
+        /// Maximum amount the app accepts to spend per period, set by the app.
+        spending_cap: Option<Balance>,
+
+        /// Whether the subscription continues after the current period, see `cancel_subscription`.
+        auto_renew: bool,
+        /// Without auto renewal, the end of the period in which the subscription was cancelled.
+        /// Nothing is charged after that.
+        renewal_end_ms: u64,
+    }
+
+    impl AppSubscription {
+        /// Whether the tier costs more per period than the app accepts to spend.
+        /// The service of the app is then suspended instead of overspending.
+        fn is_over_cap(&self, tier: &ServiceTier) -> bool {
+            self.spending_cap.map_or(false, |cap| tier.tier_fee > cap)
+        }
+
+        /// A cancelled subscription is not charged after the end of its period
+        fn charged_until_ms(&self, now_ms: u64) -> u64 {
+            if self.auto_renew {
+                now_ms
+            } else {
+                now_ms.min(self.renewal_end_ms)
+            }
+        }
+    }
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct AppSubscriptionDetails {
+        subscription: AppSubscription,
+        end_date_ms: u64,
+    }
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct AppSubscriptionLimit {
+        storage_bytes: u64,
+        wcu_per_minute: u64,
+        rcu_per_minute: u64,
+    }
+
+    impl AppSubscriptionLimit {
+        pub fn new(
+            storage_bytes: u64,
+            wcu_per_minute: u64,
             rcu_per_minute: u64,
         ) -> AppSubscriptionLimit {
             AppSubscriptionLimit {
@@ -394,7 +1830,7 @@ mod ddc {
     impl Ddc {
         /// Returns the account balance for the specified `account`.
         /// Returns `0` if the account is non-existent.
-        #[ink(message)]
+        #[ink(message, selector = "0x0f755a56")]
         pub fn balance_of(&self, owner: AccountId) -> Balance {
             let subscription_opt = self.subscriptions.get(&owner);
 
@@ -408,19 +1844,19 @@ mod ddc {
 
         // TODO: Add tests in case if subscription is empty
         /// Return the tier id corresponding to the account
-        #[ink(message)]
+        #[ink(message, selector = "0x1d525bff")]
         pub fn tier_id_of(&self, acct: AccountId) -> u64 {
             self.get_tier_id(&acct)
         }
 
         /// Return the tier limit corresponding the account
-        #[ink(message)]
+        #[ink(message, selector = "0xe0116f7d")]
         pub fn tier_limit_of(&self, acct: AccountId) -> ServiceTier {
             let tier_id = self.get_tier_id(&acct);
             self.get_tier_limit(tier_id)
         }
 
-        #[ink(message)]
+        #[ink(message, selector = "0x06c74398")]
         pub fn get_subscription_details_of(
             &self,
             acct: AccountId,
@@ -436,6 +1872,33 @@ mod ddc {
             })
         }
 
+        /// Return a page of the subscriptions with their details, starting at the cursor.
+        /// The order is stable as long as no subscription is removed.
+        #[ink(message)]
+        pub fn get_subscriptions_page(
+            &self,
+            cursor: u32,
+            limit: u32,
+        ) -> Page<(AccountId, AppSubscriptionDetails)> {
+            paginate(
+                self.subscriptions.iter().map(|(app, subscription)| {
+                    let details = AppSubscriptionDetails {
+                        subscription: subscription.clone(),
+                        end_date_ms: self.get_end_date_ms(subscription),
+                    };
+                    (*app, details)
+                }),
+                self.subscriptions.len(),
+                cursor,
+                limit,
+            )
+        }
+
+        #[ink(message)]
+        pub fn subscription_count(&self) -> u32 {
+            self.subscriptions.len()
+        }
+
         /// Return tier id given an account
         fn get_tier_id(&self, owner: &AccountId) -> u64 {
             let subscription = self.subscriptions.get(owner).unwrap();
@@ -443,31 +1906,91 @@ mod ddc {
         }
 
         fn get_end_date_ms(&self, subscription: &AppSubscription) -> u64 {
-            let tier_id = subscription.tier_id;
-            let tier = self.service_tiers.get(&tier_id).unwrap();
+            let (subscription, tier) =
+                self.effective_subscription(subscription, Self::env().block_timestamp());
+
+            Self::end_date_ms(&subscription, &tier, self.rounding_policy, self.period_ms())
+        }
+
+        /// End of the service of an app, including the grace period
+        fn get_service_end_ms(&self, subscription: &AppSubscription) -> u64 {
+            let end_date_ms = self.get_end_date_ms(subscription);
+
+            // A cancelled subscription has no grace period
+            if subscription.auto_renew {
+                end_date_ms.saturating_add(self.grace_period_ms)
+            } else {
+                end_date_ms
+            }
+        }
+
+        fn end_date_ms(
+            subscription: &AppSubscription,
+            tier: &ServiceTier,
+            rounding: RoundingPolicy,
+            period_ms: u64,
+        ) -> u64 {
             let price = tier.tier_fee; // get tier fee
-            let prepaid_time_ms = subscription.balance * PERIOD_MS as u128 / price;
 
-            subscription.last_update_ms + prepaid_time_ms as u64
+            // A free tier, or a balance lasting longer than representable, never ends
+            let end_date_ms = if price == 0 {
+                u64::MAX
+            } else {
+                let prepaid_time_ms = subscription
+                    .balance
+                    .checked_mul(period_ms as u128)
+                    .map_or(u128::MAX, |prepaid| rounding.div(prepaid, price));
+                subscription
+                    .last_update_ms
+                    .saturating_add(prepaid_time_ms.min(u64::MAX as u128) as u64)
+            };
+
+            if subscription.auto_renew {
+                end_date_ms
+            } else {
+                end_date_ms.min(subscription.renewal_end_ms)
+            }
         }
 
         fn get_consumed_balance_at_time(
             now_ms: u64,
             subscription: &AppSubscription,
             subscription_tier: &ServiceTier,
-        ) -> Balance {
-            let duration_consumed = now_ms - subscription.last_update_ms;
-
-            duration_consumed as u128 * subscription_tier.tier_fee as u128 / PERIOD_MS as u128
+            rounding: RoundingPolicy,
+            period_ms: u64,
+        ) -> Result<Balance> {
+            let duration_consumed = subscription
+                .charged_until_ms(now_ms)
+                .saturating_sub(subscription.last_update_ms);
+
+            let charged = (duration_consumed as u128)
+                .checked_mul(subscription_tier.tier_fee)
+                .ok_or(Error::ArithmeticError)?;
+
+            Ok(rounding.div(charged, period_ms as u128))
         }
 
         fn actualize_subscription_at_time(
             now_ms: u64,
             subscription: &mut AppSubscription,
             subscription_tier: &ServiceTier,
-        ) -> Balance {
-            let consumed =
-                Self::get_consumed_balance_at_time(now_ms, subscription, subscription_tier);
+            rounding: RoundingPolicy,
+            period_ms: u64,
+            revenues: &mut StorageHashMap<u64, Balance>,
+        ) -> Result<Balance> {
+            // The service of apps over their spending cap is suspended and not charged
+            if subscription.is_over_cap(subscription_tier) {
+                subscription.last_update_ms = now_ms;
+                return Ok(0);
+            }
+
+            let consumed = Self::get_consumed_balance_at_time(
+                now_ms,
+                subscription,
+                subscription_tier,
+                rounding,
+                period_ms,
+            )?;
             let actually_consumed;
 
             if consumed > subscription.balance {
@@ -477,55 +2000,260 @@ mod ddc {
                 subscription.balance -= consumed;
                 actually_consumed = consumed;
             }
+            book_revenue(
+                revenues,
+                subscription.last_update_ms,
+                subscription.charged_until_ms(now_ms),
+                actually_consumed,
+            );
             subscription.last_update_ms = now_ms;
 
-            actually_consumed
+            Ok(actually_consumed)
         }
 
-        #[must_use]
         fn actualize_subscription(
             subscription: &mut AppSubscription,
             subscription_tier: &ServiceTier,
-        ) -> Balance {
+            rounding: RoundingPolicy,
+            period_ms: u64,
+            revenues: &mut StorageHashMap<u64, Balance>,
+        ) -> Result<Balance> {
             let now_ms = Self::env().block_timestamp();
 
-            Self::actualize_subscription_at_time(now_ms, subscription, subscription_tier)
+            Self::actualize_subscription_at_time(
+                now_ms,
+                subscription,
+                subscription_tier,
+                rounding,
+                period_ms,
+                revenues,
+            )
         }
 
-        #[ink(message)]
+        #[ink(message, selector = "0x79c1212b")]
         pub fn actualize_subscriptions(&mut self) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.actualize_subscriptions_at_time(now_ms)
+        }
+
+        pub fn actualize_subscriptions_at_time(&mut self, now_ms: u64) -> Result<()> {
             self.only_owner()?;
+            self.apply_scheduled_fees(now_ms)?;
+            let period_ms = self.period_ms();
+            let unbillable_apps: Vec<AccountId> = self
+                .subscriptions
+                .iter()
+                .filter(|(app, subscription)| {
+                    !self.is_period_billable(**app, subscription.start_date_ms, now_ms)
+                })
+                .map(|(app, _)| *app)
+                .collect();
 
-            for (_, subscription) in self.subscriptions.iter_mut() {
+            for (app, subscription) in self.subscriptions.iter_mut() {
+                // Archived apps are frozen
+                if self.archived_apps.contains_key(app) {
+                    continue;
+                }
                 let subscription_tier = match self.service_tiers.get(&subscription.tier_id) {
                     None => return Err(Error::TidOutOfBound),
                     Some(v) => v,
                 };
+                self.total_ddc_balance += Self::settle_fee_changes(
+                    subscription,
+                    subscription_tier,
+                    self.fee_history.get(&subscription.tier_id),
+                    self.rounding_policy,
+                    period_ms,
+                    &mut self.daily_revenues,
+                )?;
+
+                if subscription_tier.tier_fee > 0 && !subscription.is_over_cap(subscription_tier) {
+                    let end_date_ms = Self::end_date_ms(
+                        subscription,
+                        subscription_tier,
+                        self.rounding_policy,
+                        period_ms,
+                    );
+
+                    // Once, as the next actualization starts after the end
+                    if subscription.last_update_ms < end_date_ms && end_date_ms <= now_ms {
+                        Self::env().emit_event(SubscriptionExpired {
+                            app: *app,
+                            end_ms: end_date_ms,
+                        });
+                    }
+                }
+
+                // Warn the apps which consumed their balance but keep their limits for now
+                if self.grace_period_ms > 0
+                    && subscription.auto_renew
+                    && subscription_tier.tier_fee > 0
+                {
+                    let end_date_ms = Self::end_date_ms(
+                        subscription,
+                        subscription_tier,
+                        self.rounding_policy,
+                        period_ms,
+                    );
+                    let grace_end_ms = end_date_ms.saturating_add(self.grace_period_ms);
+
+                    if end_date_ms < now_ms && now_ms <= grace_end_ms {
+                        Self::env().emit_event(SubscriptionInGrace {
+                            app: *app,
+                            grace_end_ms,
+                        });
+                    }
+                }
+
+                // The usage in a period marked unbillable is not charged
+                if unbillable_apps.contains(app) {
+                    subscription.last_update_ms = now_ms;
+                    continue;
+                }
+
+                self.total_ddc_balance += Self::actualize_subscription_at_time(
+                    now_ms,
+                    subscription,
+                    subscription_tier,
+                    self.rounding_policy,
+                    period_ms,
+                    &mut self.daily_revenues,
+                )?;
+
+                // Warn the paying apps about to drop to the free tier
+                if self.low_balance_threshold_days > 0
+                    && subscription_tier.tier_fee > 0
+                    && subscription.balance > 0
+                {
+                    let remaining_ms = subscription
+                        .balance
+                        .checked_mul(period_ms as u128)
+                        .map_or(u128::MAX, |prepaid| prepaid / subscription_tier.tier_fee);
+                    let remaining_days = (remaining_ms / MS_PER_DAY as u128) as u64;
+
+                    if remaining_days < self.low_balance_threshold_days {
+                        Self::env().emit_event(LowBalance {
+                            app: *app,
+                            remaining_days,
+                        });
+                    }
+                }
+            }
+            self.record_state_change(&"actualize_subscriptions");
+
+            Ok(())
+        }
+
+        /// As owner, set the number of remaining prepaid days under which actualization emits
+        /// `LowBalance` for an app. Zero disables the warnings.
+        #[ink(message)]
+        pub fn set_low_balance_threshold(&mut self, days: u64) -> Result<()> {
+            self.only_owner()?;
+
+            self.low_balance_threshold_days = days;
+            self.record_state_change(&("set_low_balance_threshold", days));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_low_balance_threshold(&self) -> u64 {
+            self.low_balance_threshold_days
+        }
+
+        /// As owner, set the time during which an app keeps its paid limits after its prepaid
+        /// balance is consumed, so that it can top up without service degradation
+        #[ink(message)]
+        pub fn set_grace_period(&mut self, grace_period_ms: u64) -> Result<()> {
+            self.only_owner()?;
+
+            self.grace_period_ms = grace_period_ms;
+            self.record_state_change(&("set_grace_period", grace_period_ms));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_grace_period(&self) -> u64 {
+            self.grace_period_ms
+        }
+
+        /// As owner, set how the prepaid time, consumption and refund calculations are rounded
+        #[ink(message)]
+        pub fn set_rounding_policy(&mut self, policy: RoundingPolicy) -> Result<()> {
+            self.only_owner()?;
+
+            self.rounding_policy = policy;
+            self.record_state_change(&("set_rounding_policy", policy));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_rounding_policy(&self) -> RoundingPolicy {
+            self.rounding_policy
+        }
 
-                self.total_ddc_balance +=
-                    Self::actualize_subscription(subscription, subscription_tier);
+        /// As owner, change the length of the billing period, up to PERIOD_DAYS.
+        /// Tier fees are per period, so this changes the daily price: the subscriptions are
+        /// settled at the previous price first.
+        #[ink(message)]
+        pub fn set_period_days(&mut self, days: u64) -> Result<()> {
+            self.only_owner()?;
+
+            if days == 0 || days > PERIOD_DAYS {
+                return Err(Error::InvalidPeriod);
             }
 
+            self.actualize_subscriptions()?;
+            self.period_days = days;
+            self.min_reported_days = self.min_reported_days.min(days);
+            self.record_state_change(&("set_period_days", days));
+
             Ok(())
         }
 
+        #[ink(message)]
+        pub fn get_period_days(&self) -> u64 {
+            self.period_days
+        }
+
+        fn period_ms(&self) -> u64 {
+            self.period_days * MS_PER_DAY
+        }
+
         pub fn get_total_ddc_balance(&self) -> Balance {
             self.total_ddc_balance
         }
 
-        fn set_tier(&mut self, subscription: &mut AppSubscription, new_tier_id: u64) -> Result<()> {
+        fn set_tier(
+            &mut self,
+            app: AccountId,
+            subscription: &mut AppSubscription,
+            new_tier_id: u64,
+        ) -> Result<()> {
+            self.enforce_tier_capacity(app, new_tier_id)?;
+            let period_ms = self.period_ms();
+
             let subscription_tier = match self.service_tiers.get(&subscription.tier_id) {
                 None => return Err(Error::TidOutOfBound),
                 Some(v) => v,
             };
-            self.total_ddc_balance += Self::actualize_subscription(subscription, subscription_tier);
+            self.total_ddc_balance += Self::actualize_subscription(
+                subscription,
+                subscription_tier,
+                self.rounding_policy,
+                period_ms,
+                &mut self.daily_revenues,
+            )?;
 
             subscription.tier_id = new_tier_id;
 
             Ok(())
         }
 
-        #[ink(message)]
+        #[ink(message, selector = "0xcb5b5fc9")]
         pub fn get_app_limit(&self, app: AccountId) -> Result<AppSubscriptionLimit> {
             let now_ms = Self::env().block_timestamp() as u64;
 
@@ -539,7 +2267,7 @@ mod ddc {
         ) -> Result<AppSubscriptionLimit> {
             let subscription_opt = self.subscriptions.get(&app);
             if subscription_opt.is_none() {
-                return Err(Error::NoSubscription);
+                return self.get_trial_limit_at_time(app, now_ms);
             }
             let subscription = subscription_opt.unwrap();
 
@@ -547,15 +2275,12 @@ mod ddc {
                 return Ok(AppSubscriptionLimit::new(0, 0, 0));
             }
 
-            let current_tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+            let tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+            let tier = self.effective_tier(tier, now_ms);
 
             // actual
-            if self.get_end_date_ms(subscription) >= now_ms {
-                Ok(AppSubscriptionLimit::new(
-                    current_tier.storage_bytes,
-                    current_tier.wcu_per_minute,
-                    current_tier.rcu_per_minute,
-                ))
+            if self.get_service_end_ms(subscription) >= now_ms && !subscription.is_over_cap(&tier) {
+                Ok(subscription.tier_limit.clone())
             } else {
                 // expired
                 let free_tier = self.get_free_tier()?;
@@ -582,742 +2307,6432 @@ mod ddc {
         /// Receive payment from the participating DDC node
         /// Store payment into users balance map
         /// Initialize user metrics map
-        #[ink(message, payable)]
+        #[ink(message, payable, selector = "0xfb968a8f")]
         pub fn subscribe(&mut self, tier_id: u64) -> Result<()> {
-            self.tid_in_bound(tier_id)?;
-            self.only_active()?;
             let payer = self.env().caller();
             let value = self.env().transferred_balance();
+
+            self.subscribe_with_value(payer, payer, tier_id, value)
+        }
+
+        /// Pay the subscription of another app, e.g. as a reseller. Only the app itself can
+        /// change the tier of its subscription.
+        #[ink(message, payable)]
+        pub fn subscribe_for(&mut self, app: AccountId, tier_id: u64) -> Result<()> {
+            let payer = self.env().caller();
+            let value = self.env().transferred_balance();
+            if app == AccountId::default() {
+                return Err(Error::InvalidAccount);
+            }
+            let current_tier_id = self
+                .subscriptions
+                .get(&app)
+                .map(|subscription| subscription.tier_id);
+            if payer != app && current_tier_id.map_or(false, |current| current != tier_id) {
+                return Err(Error::TierMismatch);
+            }
+
+            self.subscribe_with_value(payer, app, tier_id, value)
+        }
+
+        /// Top up the subscription of another app, at its current tier
+        #[ink(message, payable)]
+        pub fn top_up_for(&mut self, app: AccountId) -> Result<()> {
+            let payer = self.env().caller();
+            let value = self.env().transferred_balance();
+            let tier_id = self
+                .subscriptions
+                .get(&app)
+                .map(|subscription| subscription.tier_id)
+                .ok_or(Error::NoSubscription)?;
+
+            self.subscribe_with_value(payer, app, tier_id, value)
+        }
+
+        /// Credit the subscription of the app with the given value, paid by `payer`
+        fn subscribe_with_value(
+            &mut self,
+            payer: AccountId,
+            app: AccountId,
+            tier_id: u64,
+            value: Balance,
+        ) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_active()?;
+            if self.archived_apps.contains_key(&app) {
+                return Err(Error::AppArchived);
+            }
+            let now_ms = Self::env().block_timestamp();
+            self.apply_scheduled_fee(tier_id, now_ms)?;
+            self.apply_scheduled_fee_of(app, now_ms)?;
+            self.enforce_tier_active(tier_id)?;
+            self.enforce_tier_in_cluster(app, tier_id)?;
             let fee_value = value;
             let service_v = self.service_tiers.get(&tier_id).unwrap();
             if service_v.tier_fee > fee_value {
                 //TODO: We probably need to summarize the existing balance with provided, in case app wants to deposit more than monthly amount
                 return Err(Error::InsufficientDeposit);
             }
+            let tier_limit = service_v.limit();
 
-            let subscription_opt = self.subscriptions.get(&payer);
+            let subscription_opt = self.subscriptions.get(&app);
+            let spending_cap = subscription_opt.and_then(|subscription| subscription.spending_cap);
+            if spending_cap.map_or(false, |cap| service_v.tier_fee > cap) {
+                return Err(Error::SpendingCapExceeded);
+            }
             let now = Self::env().block_timestamp();
+            let period_ms = self.period_ms();
             let mut subscription: AppSubscription;
 
-            if subscription_opt.is_none() || self.get_end_date_ms(subscription_opt.unwrap()) < now {
+            let is_new = subscription_opt.is_none();
+            let mut changed_from_tier_id = None;
+            // A top up during the grace period renews the subscription
+            let is_created = is_new || self.get_service_end_ms(subscription_opt.unwrap()) < now;
+            if is_created {
+                self.enforce_tier_capacity(app, tier_id)?;
+
+                // The unused balance of a cancelled subscription is carried over
+                let mut carried = 0;
+                if let Some(cancelled) = subscription_opt.filter(|old| !old.auto_renew) {
+                    let mut cancelled = cancelled.clone();
+                    let cancelled_tier = self.service_tiers.get(&cancelled.tier_id).unwrap();
+                    self.total_ddc_balance += Self::actualize_subscription_at_time(
+                        now,
+                        &mut cancelled,
+                        cancelled_tier,
+                        self.rounding_policy,
+                        period_ms,
+                        &mut self.daily_revenues,
+                    )?;
+                    carried = cancelled.balance;
+                }
+
                 subscription = AppSubscription {
                     start_date_ms: now,
                     tier_id,
 
                     last_update_ms: now,
-                    balance: value,
+                    balance: value + carried,
+
+                    tier_limit,
+                    spending_cap,
+
+                    auto_renew: true,
+                    renewal_end_ms: 0,
                 };
             } else {
                 subscription = subscription_opt.unwrap().clone();
 
                 subscription.balance += value;
+                subscription.auto_renew = true;
 
                 if subscription.tier_id != tier_id {
-                    self.set_tier(&mut subscription, tier_id)?;
+                    if self.reject_implicit_tier_change {
+                        return Err(Error::TierMismatch);
+                    }
+                    changed_from_tier_id = Some(subscription.tier_id);
+                    self.set_tier(app, &mut subscription, tier_id)?;
                 }
+
+                // Renewal: the app is now entitled to the current limits of its tier
+                subscription.tier_limit = tier_limit;
             }
 
-            self.subscriptions.insert(payer, subscription);
+            let end_date_ms = self.get_end_date_ms(&subscription);
+            self.subscriptions.insert(app, subscription);
+            self.count_subscriber(app, Some(tier_id));
+            if is_new {
+                self.track_storage_item(app);
+                self.orphan_app_metrics(app, false);
+            }
+            if is_created {
+                Self::env().emit_event(SubscriptionCreated {
+                    app,
+                    tier_id,
+                    end_date_ms,
+                });
+            } else {
+                if let Some(old_tier_id) = changed_from_tier_id {
+                    Self::env().emit_event(SubscriptionTierChanged {
+                        app,
+                        old_tier_id,
+                        new_tier_id: tier_id,
+                        end_date_ms,
+                    });
+                }
+                Self::env().emit_event(SubscriptionExtended { app, end_date_ms });
+            }
             self.env().emit_event(Deposit {
                 from: Some(payer),
+                to: app,
                 value,
             });
+            self.record_state_change(&("subscribe", app));
 
             Ok(())
         }
 
+        /// Move the active subscription of the caller to another tier.
+        /// The balance is first settled at the price of the previous tier, then the remaining
+        /// balance is prepaid time at the price of the new tier.
         #[ink(message)]
-        pub fn refund(&mut self) -> Result<()> {
+        pub fn change_tier(&mut self, new_tier_id: u64) -> Result<()> {
+            self.tid_in_bound(new_tier_id)?;
+            self.only_active()?;
             let caller = self.env().caller();
-            let subscription = match self.subscriptions.get_mut(&caller) {
-                None => return Err(Error::NoSubscription),
-                Some(v) => v,
-            };
+            let now = Self::env().block_timestamp();
+            self.apply_scheduled_fee(new_tier_id, now)?;
+            self.apply_scheduled_fee_of(caller, now)?;
 
-            let subscription_tier = match self.service_tiers.get(&subscription.tier_id) {
-                None => return Err(Error::TidOutOfBound),
-                Some(v) => v,
+            let mut subscription = match self.subscriptions.get(&caller) {
+                None => return Err(Error::NoSubscription),
+                Some(v) => v.clone(),
             };
-            self.total_ddc_balance += Self::actualize_subscription(subscription, subscription_tier);
-            let to_refund = subscription.balance;
-            subscription.balance = 0;
-
-            if to_refund == 0 {
+            if self.get_end_date_ms(&subscription) < now {
+                return Err(Error::NoSubscription);
+            }
+            let old_tier_id = subscription.tier_id;
+            if old_tier_id == new_tier_id {
                 return Ok(());
             }
-
-            match self.env().transfer(caller, to_refund) {
-                Err(_e) => panic!("Transfer has failed!"),
-                Ok(_) => Ok(()),
+            self.enforce_tier_active(new_tier_id)?;
+            self.enforce_tier_in_cluster(caller, new_tier_id)?;
+            if subscription.is_over_cap(self.service_tiers.get(&new_tier_id).unwrap()) {
+                return Err(Error::SpendingCapExceeded);
             }
-        }
-    }
 
-    // ---- Admin: Inspectors ----
+            self.set_tier(caller, &mut subscription, new_tier_id)?;
+            subscription.tier_limit = self.service_tiers.get(&new_tier_id).unwrap().limit();
+            let end_date_ms = self.get_end_date_ms(&subscription);
+
+            self.subscriptions.insert(caller, subscription);
+            self.count_subscriber(caller, Some(new_tier_id));
+            self.record_state_change(&("change_tier", caller, new_tier_id));
+            Self::env().emit_event(SubscriptionTierChanged {
+                app: caller,
+                old_tier_id,
+                new_tier_id,
+                end_date_ms,
+            });
 
-    #[ink(event)]
-    pub struct InspectorAdded {
-        #[ink(topic)]
-        inspector: AccountId,
-    }
+            Ok(())
+        }
 
-    #[ink(event)]
-    pub struct InspectorRemoved {
-        #[ink(topic)]
-        inspector: AccountId,
-    }
+        /// As owner, choose whether `subscribe` with a tier different from the active subscription
+        /// fails with `TierMismatch` instead of changing the tier implicitly.
+        #[ink(message)]
+        pub fn set_reject_implicit_tier_change(&mut self, reject: bool) -> Result<()> {
+            self.only_owner()?;
 
-    #[ink(event)]
-    pub struct ErrorOnlyInspector {}
+            self.reject_implicit_tier_change = reject;
+            self.record_state_change(&("set_reject_implicit_tier_change", reject));
 
-    impl Ddc {
-        /// Check if account is an approved inspector.
-        fn only_inspector(&self) -> Result<()> {
-            let caller = self.env().caller();
+            Ok(())
+        }
 
-            if self.is_inspector(caller) {
-                Ok(())
-            } else {
-                self.env().emit_event(ErrorOnlyInspector {});
-                Err(Error::OnlyInspector)
+        #[ink(message)]
+        pub fn get_reject_implicit_tier_change(&self) -> bool {
+            self.reject_implicit_tier_change
+        }
+
+        /// Cancel the subscription of the caller at the end of the current period. Nothing is
+        /// charged after that, and the remaining balance can be refunded. Paying again before
+        /// the end resumes the subscription.
+        #[ink(message)]
+        pub fn cancel_subscription(&mut self) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.cancel_subscription_at_time(now_ms)
+        }
+
+        pub fn cancel_subscription_at_time(&mut self, now_ms: u64) -> Result<()> {
+            let app = self.env().caller();
+            let period_ms = self.period_ms();
+            let mut subscription = match self.subscriptions.get(&app) {
+                Some(subscription) if self.get_end_date_ms(subscription) >= now_ms => {
+                    subscription.clone()
+                }
+                _ => return Err(Error::NoSubscription),
+            };
+            if !subscription.auto_renew {
+                return Ok(());
             }
+
+            let elapsed_ms = now_ms - subscription.start_date_ms;
+            let end_ms = now_ms - elapsed_ms % period_ms + period_ms;
+            subscription.auto_renew = false;
+            subscription.renewal_end_ms = end_ms;
+            self.subscriptions.insert(app, subscription);
+            self.record_state_change(&("cancel_subscription", app));
+            Self::env().emit_event(SubscriptionCancelled { app, end_ms });
+
+            Ok(())
         }
 
+        /// Move the subscription of the caller, with its balance, tier and start date, to
+        /// another account, e.g. when rotating keys. A sponsorship, payer or refund address
+        /// moves along.
         #[ink(message)]
-        pub fn is_inspector(&self, inspector: AccountId) -> bool {
-            self.inspectors.contains_key(&inspector)
+        pub fn transfer_subscription(&mut self, to: AccountId) -> Result<()> {
+            let app = self.env().caller();
+            if to == AccountId::default() || to == app {
+                return Err(Error::InvalidAccount);
+            }
+            if self.archived_apps.contains_key(&app) {
+                return Err(Error::AppArchived);
+            }
+            if self.subscriptions.contains_key(&to) {
+                return Err(Error::AlreadySubscribed);
+            }
+            let is_sponsored = self.sponsored_apps.contains_key(&app);
+            if is_sponsored && self.sponsored_apps.contains_key(&to) {
+                return Err(Error::AppAlreadySponsored);
+            }
+            self.apply_scheduled_fee_of(app, Self::env().block_timestamp())?;
+            let period_ms = self.period_ms();
+
+            let mut subscription = self.subscriptions.take(&app).ok_or(Error::NoSubscription)?;
+            let subscription_tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+            self.total_ddc_balance += Self::actualize_subscription(
+                &mut subscription,
+                subscription_tier,
+                self.rounding_policy,
+                period_ms,
+                &mut self.daily_revenues,
+            )?;
+            self.subscriptions.insert(to, subscription);
+            self.orphan_app_metrics(app, true);
+            self.orphan_app_metrics(to, false);
+            if let Some(tier_id) = self.subscriber_tiers.get(&app).cloned() {
+                self.count_subscriber(app, None);
+                self.count_subscriber(to, Some(tier_id));
+            }
+            if let Some(sponsor) = self.sponsored_apps.take(&app) {
+                self.sponsored_apps.insert(to, sponsor);
+            }
+            Self::move_app_funds(&mut self.sponsored_funds, app, to);
+            Self::move_app_funds(&mut self.payer_funds, app, to);
+            if let Some(payer) = self.app_payers.take(&app) {
+                self.app_payers.insert(to, payer);
+            }
+            if let Some(credit) = self.non_refundable.take(&app) {
+                self.non_refundable.insert(to, credit);
+            }
+            // The refund goes where the app decided, with its pending change and delay
+            if let Some(refund_address) = self.refund_addresses.take(&app) {
+                self.refund_addresses.insert(to, refund_address);
+            }
+            self.release_storage_item(app, 0);
+            self.track_storage_item(to);
+            self.record_state_change(&("transfer_subscription", app, to));
+
+            Self::env().emit_event(SubscriptionTransferred { from: app, to });
+
+            Ok(())
         }
 
+        /// Set the maximum amount the caller app accepts to spend per period, or None for
+        /// no limit. While its tier costs more, its service is suspended instead of charged.
         #[ink(message)]
-        pub fn add_inspector(&mut self, inspector: AccountId) -> Result<()> {
-            self.only_owner()?;
+        pub fn set_spending_cap(&mut self, spending_cap: Option<Balance>) -> Result<()> {
+            let app = self.env().caller();
+            self.apply_scheduled_fee_of(app, Self::env().block_timestamp())?;
+
+            let mut subscription = match self.subscriptions.get(&app) {
+                None => return Err(Error::NoSubscription),
+                Some(v) => v.clone(),
+            };
+            let tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+
+            // Settle the consumption until now under the previous cap
+            self.total_ddc_balance += Self::actualize_subscription(
+                &mut subscription,
+                tier,
+                self.rounding_policy,
+                self.period_ms(),
+                &mut self.daily_revenues,
+            )?;
+            subscription.spending_cap = spending_cap;
+
+            if subscription.is_over_cap(tier) {
+                Self::env().emit_event(CapReached {
+                    app,
+                    spending_cap: spending_cap.unwrap_or_default(),
+                    tier_fee: tier.tier_fee,
+                });
+            }
+
+            self.subscriptions.insert(app, subscription);
+            self.record_state_change(&("set_spending_cap", app, spending_cap));
 
-            self.inspectors.insert(inspector, ());
-            Self::env().emit_event(InspectorAdded { inspector });
             Ok(())
         }
 
         #[ink(message)]
-        pub fn remove_inspector(&mut self, inspector: AccountId) -> Result<()> {
+        pub fn get_spending_cap(&self, app: AccountId) -> Option<Balance> {
+            self.subscriptions
+                .get(&app)
+                .and_then(|subscription| subscription.spending_cap)
+        }
+
+        #[ink(message, selector = "0xa5a47441")]
+        pub fn refund(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+
+            self.refund_app_balance(caller)
+        }
+
+        fn refund_app_balance(&mut self, app: AccountId) -> Result<()> {
+            // The balance of an archived app is returned when it is pruned
+            if self.archived_apps.contains_key(&app) {
+                return Err(Error::AppArchived);
+            }
+            self.apply_scheduled_fee_of(app, Self::env().block_timestamp())?;
+            let period_ms = self.period_ms();
+            let subscription = match self.subscriptions.get_mut(&app) {
+                None => return Err(Error::NoSubscription),
+                Some(v) => v,
+            };
+
+            let subscription_tier = match self.service_tiers.get(&subscription.tier_id) {
+                None => return Err(Error::TidOutOfBound),
+                Some(v) => v,
+            };
+            self.total_ddc_balance += Self::actualize_subscription(
+                subscription,
+                subscription_tier,
+                self.rounding_policy,
+                period_ms,
+                &mut self.daily_revenues,
+            )?;
+            let balance = subscription.balance;
+            let non_refundable = self.non_refundable.get(&app).cloned().unwrap_or(0);
+            let refundable = balance - non_refundable.min(balance);
+            let fee = refundable
+                .checked_mul(self.refund_fee_bps as Balance)
+                .ok_or(Error::ArithmeticError)?
+                / BPS as Balance;
+            subscription.balance = 0;
+            self.count_subscriber(app, None);
+            self.withhold_non_refundable(app, balance);
+            let to_refund = refundable - fee;
+            self.total_ddc_balance += fee;
+            let now_ms = Self::env().block_timestamp();
+            book_revenue(&mut self.daily_revenues, now_ms, now_ms, fee);
+            self.record_state_change(&("refund", app));
+            Self::env().emit_event(Refunded {
+                app,
+                value: to_refund,
+                fee,
+            });
+
+            self.return_balance(app, to_refund);
+
+            Ok(())
+        }
+
+        /// As owner, retain a share of the refunds as early termination fee, in basis points.
+        /// The fee is added to the revenue.
+        #[ink(message)]
+        pub fn set_refund_fee(&mut self, fee_bps: u32) -> Result<()> {
             self.only_owner()?;
 
-            self.inspectors.take(&inspector);
-            Self::env().emit_event(InspectorRemoved { inspector });
+            if fee_bps > BPS {
+                return Err(Error::InvalidShare);
+            }
+
+            self.refund_fee_bps = fee_bps;
+            self.record_state_change(&("set_refund_fee", fee_bps));
+
             Ok(())
         }
-    }
 
-    // ---- DDC Node managers ----
+        #[ink(message)]
+        pub fn get_refund_fee(&self) -> u32 {
+            self.refund_fee_bps
+        }
 
-    #[ink(event)]
-    pub struct DDNManagerAdded {
-        #[ink(topic)]
-        ddn_manager: AccountId,
-    }
+        /// Send the unused balance of an app back. Its sponsors get what they paid into their
+        /// pools, then its payers what they paid, and the rest goes to the refund address of
+        /// the app.
+        fn return_balance(&mut self, app: AccountId, value: Balance) {
+            let mut value = value;
 
-    #[ink(event)]
-    pub struct DDNManagerRemoved {
-        #[ink(topic)]
-        ddn_manager: AccountId,
-    }
+            // Sponsors get back what they paid first, into their pool
+            for (sponsor, part) in Self::take_app_funds(&mut self.sponsored_funds, app, &mut value)
+            {
+                *self.sponsor_pools.entry(sponsor).or_insert(0) += part;
+            }
+            for (payer, part) in Self::take_app_funds(&mut self.payer_funds, app, &mut value) {
+                if part == 0 {
+                    continue;
+                }
+                if let Err(_e) = self.env().transfer(payer, part) {
+                    panic!("Transfer has failed!");
+                }
+            }
+            if value == 0 {
+                return;
+            }
 
-    #[ink(event)]
-    pub struct ErrorOnlyDDNManager {}
+            let destination = self.get_refund_address(app).unwrap_or(app);
+
+            if let Err(_e) = self.env().transfer(destination, value) {
+                panic!("Transfer has failed!");
+            }
+        }
+    }
 
     impl Ddc {
-        /// Check if account is an approved DDC node manager
-        fn only_ddn_manager(&self) -> Result<()> {
-            let caller = self.env().caller();
+        /// Record funds paid by a third party into the balance of an app, which is `balance`
+        /// with them. The funds consumed by the app since the last payment are dropped first.
+        fn add_app_funds(
+            funds: &mut StorageHashMap<AccountId, Vec<(AccountId, Balance)>>,
+            app: AccountId,
+            funder: AccountId,
+            value: Balance,
+            balance: Balance,
+        ) {
+            let mut records = funds.take(&app).unwrap_or_default();
+            Self::cap_app_funds(&mut records, balance.saturating_sub(value));
+            match records.iter_mut().find(|(paid_by, _)| *paid_by == funder) {
+                Some((_, paid)) => *paid += value,
+                None => records.push((funder, value)),
+            }
+            if !records.is_empty() {
+                funds.insert(app, records);
+            }
+        }
 
-            if self.is_ddn_manager(caller) || *self.owner == caller {
-                Ok(())
-            } else {
-                self.env().emit_event(ErrorOnlyDDNManager {});
-                Err(Error::OnlyDDNManager)
+        /// Drop the oldest funds in excess of the balance of the app, as they were consumed
+        fn cap_app_funds(records: &mut Vec<(AccountId, Balance)>, balance: Balance) {
+            let total: Balance = records.iter().map(|(_, paid)| *paid).sum();
+            let mut excess = total.saturating_sub(balance);
+            for (_, paid) in records.iter_mut() {
+                let consumed = (*paid).min(excess);
+                *paid -= consumed;
+                excess -= consumed;
+            }
+            records.retain(|(_, paid)| *paid > 0);
+        }
+
+        /// Take the records of the funds paid by third parties into the balance of an app,
+        /// with what goes back to each of them out of `value`. The rest is left in `value`.
+        fn take_app_funds(
+            funds: &mut StorageHashMap<AccountId, Vec<(AccountId, Balance)>>,
+            app: AccountId,
+            value: &mut Balance,
+        ) -> Vec<(AccountId, Balance)> {
+            let mut records = funds.take(&app).unwrap_or_default();
+            Self::cap_app_funds(&mut records, *value);
+            for (_, paid) in records.iter() {
+                *value -= *paid;
+            }
+
+            records
+        }
+
+        /// Move the records of the funds paid by third parties from an app to another
+        fn move_app_funds(
+            funds: &mut StorageHashMap<AccountId, Vec<(AccountId, Balance)>>,
+            from: AccountId,
+            to: AccountId,
+        ) {
+            if let Some(records) = funds.take(&from) {
+                funds.insert(to, records);
             }
         }
+    }
+
+    // ---- Subscription migration ----
 
+    impl Ddc {
+        /// As owner, export a page of the subscriptions to move them to a new deployment.
+        /// Each record is a SCALE-encoded `(AccountId, AppSubscription)`.
         #[ink(message)]
-        pub fn is_ddn_manager(&self, ddn_manager: AccountId) -> bool {
-            self.ddn_managers.contains_key(&ddn_manager)
+        pub fn export_subscriptions(&self, cursor: u32, limit: u32) -> Result<Page<Vec<u8>>> {
+            self.only_owner()?;
+
+            Ok(paginate(
+                self.subscriptions.iter().map(|record| record.encode()),
+                self.subscriptions.len(),
+                cursor,
+                limit,
+            ))
         }
 
+        /// As owner, import records from `export_subscriptions` into this deployment.
+        /// Only possible until `finish_import` is called. The apps must not be subscribed yet,
+        /// and the tiers must have room for them. The balances of the subscriptions must be
+        /// transferred to the contract separately.
         #[ink(message)]
-        pub fn add_ddn_manager(&mut self, ddn_manager: AccountId) -> Result<()> {
+        pub fn import_subscriptions(&mut self, records: Vec<Vec<u8>>) -> Result<()> {
             self.only_owner()?;
+            if self.import_finished {
+                return Err(Error::ImportFinished);
+            }
+
+            let mut decoded: Vec<(AccountId, AppSubscription)> = Vec::new();
+            for record in records.iter() {
+                let (app_id, subscription) =
+                    <(AccountId, AppSubscription)>::decode(&mut &record[..])
+                        .map_err(|_| Error::InvalidRecord)?;
+                self.tid_in_bound(subscription.tier_id)?;
+                let is_duplicate = decoded.iter().any(|(imported, _)| *imported == app_id);
+                if is_duplicate || self.subscriptions.contains_key(&app_id) {
+                    return Err(Error::AlreadySubscribed);
+                }
+                decoded.push((app_id, subscription));
+            }
+
+            for (_, subscription) in decoded.iter() {
+                let tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+                let imported = decoded
+                    .iter()
+                    .filter(|(_, other)| other.tier_id == subscription.tier_id)
+                    .count() as u64;
+                if let Some(max) = tier.max_subscribers {
+                    if self.tier_subscriber_count(subscription.tier_id) + imported > max {
+                        return Err(Error::TierFull);
+                    }
+                }
+            }
+
+            for (app_id, subscription) in decoded {
+                self.record_state_change(&("import_subscription", app_id));
+                self.count_subscriber(app_id, Some(subscription.tier_id));
+                self.subscriptions.insert(app_id, subscription);
+                self.track_storage_item(app_id);
+                self.orphan_app_metrics(app_id, false);
+            }
 
-            self.ddn_managers.insert(ddn_manager, ());
-            Self::env().emit_event(DDNManagerAdded { ddn_manager });
             Ok(())
         }
 
+        /// As owner, close the import of subscriptions for good
         #[ink(message)]
-        pub fn remove_ddn_manager(&mut self, ddn_manager: AccountId) -> Result<()> {
+        pub fn finish_import(&mut self) -> Result<()> {
             self.only_owner()?;
 
-            self.ddn_managers.take(&ddn_manager);
-            Self::env().emit_event(DDNManagerRemoved { ddn_manager });
+            self.import_finished = true;
+            self.record_state_change(&"finish_import");
+
             Ok(())
         }
-    }
-
-    // ---- DDC nodes ----
 
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct DDCNode {
-        p2p_id: String,
-        p2p_addr: String,
-        url: String,
-        /// There is only one known permission for trusted nodes:
-        ///
-        ///     is_trusted = (permissions & 1) != 0
-        permissions: u64,
+        #[ink(message)]
+        pub fn is_import_finished(&self) -> bool {
+            self.import_finished
+        }
     }
 
-    #[ink(event)]
-    pub struct DDCNodeAdded {
-        #[ink(topic)]
-        p2p_id: String,
-        p2p_addr: String,
-        url: String,
-        permissions: u64,
-    }
+    // ---- Refund addresses ----
 
     #[ink(event)]
-    pub struct DDCNodeRemoved {
+    pub struct RefundAddressChangeScheduled {
         #[ink(topic)]
-        p2p_id: String,
-        p2p_addr: String,
+        app: AccountId,
+        address: AccountId,
+        effective_ms: u64,
     }
 
     impl Ddc {
-        /// Return the list of all DDC nodes
-        #[ink(message)]
-        pub fn get_all_ddc_nodes(&self) -> Vec<DDCNode> {
-            self.ddc_nodes.values().cloned().collect()
-        }
-
-        /// Add DDC node to the list.
-        ///
-        /// If the node already exists based on p2p_id, update all fields.
+        /// Set the account receiving the refunds of the caller.
         ///
-        /// Use permissions 1 for a trusted node, otherwise 0.
+        /// The change takes effect after ADDRESS_CHANGE_DELAY_MS, so that a stolen app key
+        /// cannot redirect the refund instantly.
         #[ink(message)]
-        pub fn add_ddc_node(
-            &mut self,
-            p2p_id: String,
-            p2p_addr: String,
-            url: String,
-            permissions: u64,
-        ) -> Result<()> {
-            self.only_ddn_manager()?;
+        pub fn set_refund_address(&mut self, address: AccountId) -> Result<()> {
+            if address == AccountId::default() {
+                return Err(Error::InvalidAccount);
+            }
 
-            self.ddc_nodes.insert(
-                p2p_id.clone(),
-                DDCNode {
-                    p2p_id: p2p_id.clone(),
-                    p2p_addr: p2p_addr.clone(),
-                    url: url.clone(),
-                    permissions,
-                },
-            );
-            Self::env().emit_event(DDCNodeAdded {
-                p2p_id,
-                p2p_addr,
-                url,
-                permissions,
+            let app = self.env().caller();
+            let now_ms = Self::env().block_timestamp();
+
+            let mut refund_address = self.refund_addresses.get(&app).cloned().unwrap_or_default();
+            refund_address.schedule(address, now_ms, ADDRESS_CHANGE_DELAY_MS);
+            let effective_ms = refund_address.effective_ms;
+            self.refund_addresses.insert(app, refund_address);
+            self.record_state_change(&("set_refund_address", app));
+
+            Self::env().emit_event(RefundAddressChangeScheduled {
+                app,
+                address,
+                effective_ms,
             });
 
             Ok(())
         }
 
-        /// Check if DDC node is in the list
+        /// Return the account receiving the refunds of an app, if it is not the app itself
         #[ink(message)]
-        pub fn is_ddc_node(&self, p2p_id: String) -> bool {
-            self.ddc_nodes.contains_key(&p2p_id)
+        pub fn get_refund_address(&self, app: AccountId) -> Option<AccountId> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.refund_addresses
+                .get(&app)
+                .and_then(|refund_address| refund_address.at_time(now_ms))
         }
+    }
 
-        /// Removes DDC node from the list
-        #[ink(message)]
-        pub fn remove_ddc_node(&mut self, p2p_id: String) -> Result<()> {
-            self.only_ddn_manager()?;
+    // ---- Payer accounts ----
 
-            // Remove DDN if exists
-            let removed_node = self.ddc_nodes.take(&p2p_id).ok_or(Error::DDNNotFound)?;
-            Self::env().emit_event(DDCNodeRemoved {
-                p2p_id: p2p_id.clone(),
-                p2p_addr: removed_node.p2p_addr,
-            });
+    #[ink(event)]
+    pub struct AppRegistered {
+        #[ink(topic)]
+        payer: AccountId,
+        #[ink(topic)]
+        app: AccountId,
+    }
 
-            // Remove DDN status entries from all inspectors
-            for &inspector in self.inspectors.keys() {
-                self.ddn_statuses.take(&DDNStatusKey {
-                    inspector,
-                    p2p_id: p2p_id.clone(),
-                });
+    impl Ddc {
+        /// As an app, approve the payer account managing it, or None to revoke it
+        #[ink(message)]
+        pub fn set_app_payer(&mut self, payer: Option<AccountId>) -> Result<()> {
+            let app = self.env().caller();
+
+            match payer {
+                Some(payer) if payer == AccountId::default() || payer == app => {
+                    return Err(Error::InvalidAccount)
+                }
+                Some(payer) => {
+                    self.app_payers.insert(app, payer);
+                    Self::env().emit_event(AppRegistered { payer, app });
+                }
+                None => {
+                    self.app_payers.take(&app);
+                }
             }
+            self.record_state_change(&("set_app_payer", app, payer));
 
             Ok(())
         }
-    }
 
-    // ---- DDN Statuses ----
-    #[derive(Default, Copy, Clone, PartialEq, Encode, Decode, SpreadLayout, PackedLayout)]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct DDNStatus {
+        /// As the payer approved by an app with `set_app_payer`, pay its subscription, so that
+        /// one treasury account can run several apps. The payer gets back what it paid on
+        /// refunds, after the sponsors of the app.
+        #[ink(message, payable)]
+        pub fn subscribe_app(&mut self, app_id: AccountId, tier_id: u64) -> Result<()> {
+            let payer = self.env().caller();
+            let value = self.env().transferred_balance();
+            if self.app_payers.get(&app_id) != Some(&payer) {
+                return Err(Error::NoPermission);
+            }
+
+            self.subscribe_with_value(payer, app_id, tier_id, value)?;
+            let balance = self.subscriptions.get(&app_id).map_or(0, |s| s.balance);
+            Self::add_app_funds(&mut self.payer_funds, app_id, payer, value, balance);
+            self.record_state_change(&("subscribe_app", payer, app_id));
+
+            Ok(())
+        }
+
+        /// As payer of an app, get its unused balance back
+        #[ink(message)]
+        pub fn refund_app(&mut self, app_id: AccountId) -> Result<()> {
+            let caller = self.env().caller();
+            if self.app_payers.get(&app_id) != Some(&caller) {
+                return Err(Error::NoPermission);
+            }
+
+            self.refund_app_balance(app_id)
+        }
+
+        #[ink(message)]
+        pub fn get_app_payer(&self, app_id: AccountId) -> Option<AccountId> {
+            self.app_payers.get(&app_id).cloned()
+        }
+
+        /// Return the apps managed by a payer
+        #[ink(message)]
+        pub fn get_payer_apps(&self, payer: AccountId) -> Vec<AccountId> {
+            self.app_payers
+                .iter()
+                .filter(|(_, app_payer)| **app_payer == payer)
+                .map(|(app, _)| *app)
+                .collect()
+        }
+    }
+
+    // ---- Sponsors ----
+
+    #[ink(event)]
+    pub struct SponsorDeposit {
+        #[ink(topic)]
+        sponsor: AccountId,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SponsorshipOffered {
+        #[ink(topic)]
+        sponsor: AccountId,
+        #[ink(topic)]
+        app: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct SponsoredAppAdded {
+        #[ink(topic)]
+        sponsor: AccountId,
+        #[ink(topic)]
+        app: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct SponsoredAppRemoved {
+        #[ink(topic)]
+        sponsor: AccountId,
+        #[ink(topic)]
+        app: AccountId,
+    }
+
+    impl Ddc {
+        /// Deposit funds into the pool of the caller, used to pay for the sponsored apps
+        #[ink(message, payable)]
+        pub fn sponsor_deposit(&mut self) -> Result<()> {
+            self.only_active()?;
+            let sponsor = self.env().caller();
+            let value = self.env().transferred_balance();
+
+            *self.sponsor_pools.entry(sponsor).or_insert(0) += value;
+            Self::env().emit_event(SponsorDeposit { sponsor, value });
+            self.record_state_change(&("sponsor_deposit", sponsor));
+
+            Ok(())
+        }
+
+        /// Withdraw unused funds from the pool of the caller
+        #[ink(message)]
+        pub fn sponsor_withdraw(&mut self, amount: Balance) -> Result<()> {
+            let sponsor = self.env().caller();
+            let pool = self
+                .sponsor_pools
+                .get_mut(&sponsor)
+                .ok_or(Error::ZeroBalance)?;
+            if *pool < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            *pool -= amount;
+            self.record_state_change(&("sponsor_withdraw", sponsor));
+
+            match self.env().transfer(sponsor, amount) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+
+        /// Return the funds available in the pool of a sponsor
+        #[ink(message)]
+        pub fn sponsor_pool_of(&self, sponsor: AccountId) -> Balance {
+            *self.sponsor_pools.get(&sponsor).unwrap_or(&0)
+        }
+
+        /// Return the sponsor of an app, if any
+        #[ink(message)]
+        pub fn sponsor_of(&self, app: AccountId) -> Option<AccountId> {
+            self.sponsored_apps.get(&app).cloned()
+        }
+
+        /// Offer an app to subscribe using the funds of the caller. The app must accept the
+        /// offer with `accept_sponsor`.
+        #[ink(message)]
+        pub fn add_sponsored_app(&mut self, app: AccountId) -> Result<()> {
+            let sponsor = self.env().caller();
+
+            match self.sponsored_apps.get(&app) {
+                Some(current) if *current != sponsor => return Err(Error::AppAlreadySponsored),
+                _ => {}
+            }
+
+            self.sponsor_offers.insert((sponsor, app), ());
+            Self::env().emit_event(SponsorshipOffered { sponsor, app });
+            self.record_state_change(&("add_sponsored_app", app));
+            Ok(())
+        }
+
+        /// As an app, accept the sponsorship offered by a sponsor
+        #[ink(message)]
+        pub fn accept_sponsor(&mut self, sponsor: AccountId) -> Result<()> {
+            let app = self.env().caller();
+            if !self.sponsor_offers.contains_key(&(sponsor, app)) {
+                return Err(Error::NotSponsored);
+            }
+            if self.sponsored_apps.contains_key(&app) {
+                return Err(Error::AppAlreadySponsored);
+            }
+
+            self.sponsor_offers.take(&(sponsor, app));
+            self.sponsored_apps.insert(app, sponsor);
+            Self::env().emit_event(SponsoredAppAdded { sponsor, app });
+            self.record_state_change(&("accept_sponsor", app, sponsor));
+            Ok(())
+        }
+
+        /// Revoke the sponsorship of an app, or the offer of it. Only the sponsor of the app
+        /// can call this function
+        #[ink(message)]
+        pub fn remove_sponsored_app(&mut self, app: AccountId) -> Result<()> {
+            let sponsor = self.env().caller();
+            let was_offered = self.sponsor_offers.take(&(sponsor, app)).is_some();
+            let was_sponsored = self.sponsored_apps.get(&app) == Some(&sponsor);
+            if !was_offered && !was_sponsored {
+                return Err(Error::NotSponsored);
+            }
+
+            if was_sponsored {
+                self.sponsored_apps.take(&app);
+                Self::env().emit_event(SponsoredAppRemoved { sponsor, app });
+            }
+            self.record_state_change(&("remove_sponsored_app", app));
+            Ok(())
+        }
+
+        /// Subscribe the caller app to a tier, paid from the pool of its sponsor
+        #[ink(message)]
+        pub fn subscribe_sponsored(&mut self, tier_id: u64) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            let app = self.env().caller();
+            let sponsor = *self.sponsored_apps.get(&app).ok_or(Error::NotSponsored)?;
+            let fee = self.tier_fee_at_time(tier_id, Self::env().block_timestamp());
+
+            if self.sponsor_pool_of(sponsor) < fee {
+                return Err(Error::InsufficientBalance);
+            }
+
+            self.subscribe_with_value(sponsor, app, tier_id, fee)?;
+            *self.sponsor_pools.entry(sponsor).or_insert(0) -= fee;
+            let balance = self.subscriptions.get(&app).map_or(0, |s| s.balance);
+            Self::add_app_funds(&mut self.sponsored_funds, app, sponsor, fee, balance);
+            self.record_state_change(&("subscribe_sponsored", app));
+
+            Ok(())
+        }
+    }
+
+    // ---- Gifts ----
+
+    /// Time given to the recipient of a gift to accept it.
+    const GIFT_ACCEPT_DELAY_MS: u64 = 7 * MS_PER_DAY;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct Gift {
+        gifter: AccountId,
+        tier_id: u64,
+        value: Balance,
+        expires_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct GiftOffered {
+        #[ink(topic)]
+        gifter: AccountId,
+        #[ink(topic)]
+        recipient: AccountId,
+        tier_id: u64,
+        value: Balance,
+        expires_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct GiftAccepted {
+        #[ink(topic)]
+        gifter: AccountId,
+        #[ink(topic)]
+        recipient: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct GiftRefunded {
+        #[ink(topic)]
+        gifter: AccountId,
+        #[ink(topic)]
+        recipient: AccountId,
+        value: Balance,
+    }
+
+    impl Ddc {
+        /// Pay for a subscription of another account. The recipient must accept the gift
+        /// with `accept_gift` within GIFT_ACCEPT_DELAY_MS, otherwise the gifter can take
+        /// the funds back with `reclaim_gift`. A gifter has one pending gift per recipient.
+        #[ink(message, payable)]
+        pub fn gift_subscription(&mut self, recipient: AccountId, tier_id: u64) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.gift_subscription_at_time(recipient, tier_id, now_ms)
+        }
+
+        pub fn gift_subscription_at_time(
+            &mut self,
+            recipient: AccountId,
+            tier_id: u64,
+            now_ms: u64,
+        ) -> Result<()> {
+            self.tid_in_bound(tier_id)?;
+            self.only_active()?;
+            self.enforce_tier_active(tier_id)?;
+            let gifter = self.env().caller();
+            let value = self.env().transferred_balance();
+
+            if self.tier_fee_at_time(tier_id, now_ms) > value {
+                return Err(Error::InsufficientDeposit);
+            }
+            if self.gifts.contains_key(&(gifter, recipient)) {
+                return Err(Error::GiftPending);
+            }
+
+            let expires_ms = now_ms + GIFT_ACCEPT_DELAY_MS;
+            self.gifts.insert(
+                (gifter, recipient),
+                Gift {
+                    gifter,
+                    tier_id,
+                    value,
+                    expires_ms,
+                },
+            );
+            self.track_storage_item(gifter);
+            self.record_state_change(&("gift_subscription", recipient));
+            Self::env().emit_event(GiftOffered {
+                gifter,
+                recipient,
+                tier_id,
+                value,
+                expires_ms,
+            });
+
+            Ok(())
+        }
+
+        /// Return the gift of a gifter waiting for the acceptance of a recipient, if any
+        #[ink(message)]
+        pub fn get_gift(&self, gifter: AccountId, recipient: AccountId) -> Option<Gift> {
+            self.gifts.get(&(gifter, recipient)).cloned()
+        }
+
+        /// As the recipient of a gift, subscribe to its tier with the funds of the gifter
+        #[ink(message)]
+        pub fn accept_gift(&mut self, gifter: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.accept_gift_at_time(gifter, now_ms)
+        }
+
+        pub fn accept_gift_at_time(&mut self, gifter: AccountId, now_ms: u64) -> Result<()> {
+            let recipient = self.env().caller();
+            let gift = self
+                .gifts
+                .get(&(gifter, recipient))
+                .cloned()
+                .ok_or(Error::NoGift)?;
+
+            if now_ms > gift.expires_ms {
+                return Err(Error::GiftExpired);
+            }
+
+            self.subscribe_with_value(gift.gifter, recipient, gift.tier_id, gift.value)?;
+            self.gifts.take(&(gifter, recipient));
+            self.release_storage_item(gift.gifter, 0);
+            self.record_state_change(&("accept_gift", recipient));
+            Self::env().emit_event(GiftAccepted {
+                gifter: gift.gifter,
+                recipient,
+            });
+
+            Ok(())
+        }
+
+        /// As gifter, take back the funds of a gift which was not accepted in time
+        #[ink(message)]
+        pub fn reclaim_gift(&mut self, recipient: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.reclaim_gift_at_time(recipient, now_ms)
+        }
+
+        pub fn reclaim_gift_at_time(&mut self, recipient: AccountId, now_ms: u64) -> Result<()> {
+            let gifter = self.env().caller();
+            let gift = self
+                .gifts
+                .get(&(gifter, recipient))
+                .cloned()
+                .ok_or(Error::NoGift)?;
+
+            if now_ms <= gift.expires_ms {
+                return Err(Error::ClaimNotReady);
+            }
+
+            self.gifts.take(&(gifter, recipient));
+            self.release_storage_item(gifter, 0);
+            self.record_state_change(&("reclaim_gift", recipient));
+            Self::env().emit_event(GiftRefunded {
+                gifter,
+                recipient,
+                value: gift.value,
+            });
+
+            match self.env().transfer(gifter, gift.value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+    }
+
+    // ---- Free trials ----
+
+    #[ink(event)]
+    pub struct TrialStarted {
+        #[ink(topic)]
+        app: AccountId,
+        tier_id: u64,
+        end_ms: u64,
+    }
+
+    impl Ddc {
+        /// As owner, offer new accounts `days` days of a tier for free, or no trial with 0 days
+        #[ink(message)]
+        pub fn set_trial(&mut self, days: u64, tier_id: u64) -> Result<()> {
+            self.only_owner()?;
+            if days > 0 {
+                self.tid_in_bound(tier_id)?;
+            }
+
+            self.trial_days = days;
+            self.trial_tier_id = tier_id;
+            self.record_state_change(&("set_trial", days, tier_id));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_trial(&self) -> (u64, u64) {
+            (self.trial_days, self.trial_tier_id)
+        }
+
+        /// Start the free trial of the caller. Each account can use it once, and only before
+        /// subscribing.
+        #[ink(message)]
+        pub fn start_trial(&mut self) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.start_trial_at_time(now_ms)
+        }
+
+        pub fn start_trial_at_time(&mut self, now_ms: u64) -> Result<()> {
+            let app = self.env().caller();
+            self.only_active()?;
+            if self.trial_days == 0 {
+                return Err(Error::TrialUnavailable);
+            }
+            if self.trials.contains_key(&app) {
+                return Err(Error::TrialUsed);
+            }
+            if self.subscriptions.contains_key(&app) {
+                return Err(Error::AlreadySubscribed);
+            }
+            self.tid_in_bound(self.trial_tier_id)?;
+            self.enforce_tier_active(self.trial_tier_id)?;
+
+            let end_ms = now_ms + self.trial_days * MS_PER_DAY;
+            self.trials.insert(app, end_ms);
+            self.track_storage_item(app);
+            self.record_state_change(&("start_trial", app));
+            Self::env().emit_event(TrialStarted {
+                app,
+                tier_id: self.trial_tier_id,
+                end_ms,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_trial_used(&self, app: AccountId) -> bool {
+            self.trials.contains_key(&app)
+        }
+
+        /// Return the end of the trial of an app, if it used it
+        #[ink(message)]
+        pub fn get_trial_end_ms(&self, app: AccountId) -> Option<u64> {
+            self.trials.get(&app).cloned()
+        }
+
+        /// Limits of an app without subscription, during its trial
+        fn get_trial_limit_at_time(
+            &self,
+            app: AccountId,
+            now_ms: u64,
+        ) -> Result<AppSubscriptionLimit> {
+            match self.trials.get(&app) {
+                Some(end_ms) if now_ms < *end_ms => {}
+                _ => return Err(Error::NoSubscription),
+            }
+
+            Ok(self
+                .service_tiers
+                .get(&self.trial_tier_id)
+                .map_or(AppSubscriptionLimit::new(0, 0, 0), |tier| tier.limit()))
+        }
+    }
+
+    // ---- Promotions ----
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct Promo {
+        /// Discount on the fee of the tier, in basis points.
+        discount_bps: u32,
+        remaining_uses: u32,
+        expiry_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct PromoRedeemed {
+        #[ink(topic)]
+        app: AccountId,
+        #[ink(topic)]
+        code_hash: Hash,
+        discount: Balance,
+    }
+
+    impl Ddc {
+        /// As owner, create a promotional code, given by the Blake2x256 hash of the code.
+        /// It can be redeemed `max_uses` times before `expiry_ms`.
+        #[ink(message)]
+        pub fn create_promo(
+            &mut self,
+            code_hash: Hash,
+            discount_bps: u32,
+            max_uses: u32,
+            expiry_ms: u64,
+        ) -> Result<()> {
+            self.only_owner()?;
+
+            if discount_bps > BPS {
+                return Err(Error::InvalidShare);
+            }
+            if self.promos.contains_key(&code_hash) {
+                return Err(Error::PromoExists);
+            }
+
+            let promo = Promo {
+                discount_bps,
+                remaining_uses: max_uses,
+                expiry_ms,
+            };
+            self.promos.insert(code_hash, promo);
+            self.record_state_change(&("create_promo", code_hash));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_promo(&self, code_hash: Hash) -> Option<Promo> {
+            self.promos.get(&code_hash).cloned()
+        }
+
+        /// Subscribe with a promotional code. The fee of the tier is discounted, and the
+        /// subscription is credited with the payment plus the discount. The discount is
+        /// granted by the contract out of its revenue, so it is not refundable.
+        #[ink(message, payable)]
+        pub fn subscribe_with_promo(&mut self, tier_id: u64, code_preimage: Vec<u8>) -> Result<()> {
+            let app = self.env().caller();
+            let value = self.env().transferred_balance();
+            let now_ms = Self::env().block_timestamp();
+            self.tid_in_bound(tier_id)?;
+
+            let mut output = <Blake2x256 as HashOutput>::Type::default();
+            ink_env::hash_bytes::<Blake2x256>(&code_preimage, &mut output);
+            let code_hash = Hash::from(output);
+
+            let mut promo = match self.promos.get(&code_hash) {
+                Some(promo) if promo.remaining_uses > 0 && now_ms < promo.expiry_ms => {
+                    promo.clone()
+                }
+                _ => return Err(Error::InvalidPromo),
+            };
+            let tier_fee = self.tier_fee_at_time(tier_id, now_ms);
+            let discount = tier_fee * promo.discount_bps as Balance / BPS as Balance;
+
+            self.subscribe_with_value(app, app, tier_id, value + discount)?;
+            promo.remaining_uses -= 1;
+            self.promos.insert(code_hash, promo);
+            self.grant_non_refundable(app, discount);
+            self.record_state_change(&("subscribe_with_promo", app, code_hash));
+
+            Self::env().emit_event(PromoRedeemed {
+                app,
+                code_hash,
+                discount,
+            });
+
+            Ok(())
+        }
+
+        /// Make a part of the balance of an app non refundable. As it was not received or was
+        /// paid out already, it is not part of the revenue to forward when consumed.
+        fn grant_non_refundable(&mut self, app: AccountId, value: Balance) {
+            if value > 0 {
+                *self.non_refundable.entry(app).or_insert(0) += value;
+                self.revenue_forwarded += value;
+            }
+        }
+
+        /// Deduct the non refundable part not consumed yet from the balance of an app leaving,
+        /// that part being consumed last. Return the amount withheld.
+        fn withhold_non_refundable(&mut self, app: AccountId, balance: Balance) -> Balance {
+            let withheld = self.non_refundable.take(&app).unwrap_or(0).min(balance);
+            self.revenue_forwarded = self.revenue_forwarded.saturating_sub(withheld);
+
+            withheld
+        }
+    }
+
+    // ---- Referrals ----
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct ReferralStats {
+        referrals: u32,
+        /// Total rewards earned.
+        rewards: Balance,
+        /// Rewards not claimed yet.
+        unclaimed: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ReferralRewarded {
+        #[ink(topic)]
+        referrer: AccountId,
+        #[ink(topic)]
+        app: AccountId,
+        reward: Balance,
+    }
+
+    impl Ddc {
+        /// As owner, set the share of the first payment of a referred app rewarded to the
+        /// referrer, in basis points
+        #[ink(message)]
+        pub fn set_referral_reward(&mut self, reward_bps: u32) -> Result<()> {
+            self.only_owner()?;
+
+            if reward_bps > BPS {
+                return Err(Error::InvalidShare);
+            }
+
+            self.referral_bps = reward_bps;
+            self.record_state_change(&("set_referral_reward", reward_bps));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_referral_reward(&self) -> u32 {
+            self.referral_bps
+        }
+
+        /// Subscribe for the first time, brought by `referrer`. The referrer earns a share of
+        /// the payment, which is not refundable to the app.
+        #[ink(message, payable)]
+        pub fn subscribe_with_referrer(&mut self, tier_id: u64, referrer: AccountId) -> Result<()> {
+            let app = self.env().caller();
+            let value = self.env().transferred_balance();
+            if referrer == AccountId::default() || referrer == app {
+                return Err(Error::InvalidAccount);
+            }
+            if self.subscriptions.contains_key(&app) {
+                return Err(Error::AlreadySubscribed);
+            }
+
+            self.subscribe_with_value(app, app, tier_id, value)?;
+            let reward = value * self.referral_bps as Balance / BPS as Balance;
+            self.grant_non_refundable(app, reward);
+            let mut stats = self.referral_stats_of(referrer);
+            stats.referrals += 1;
+            stats.rewards += reward;
+            stats.unclaimed += reward;
+            self.referral_stats.insert(referrer, stats);
+            self.record_state_change(&("subscribe_with_referrer", app, referrer));
+
+            Self::env().emit_event(ReferralRewarded {
+                referrer,
+                app,
+                reward,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn referral_stats_of(&self, account: AccountId) -> ReferralStats {
+            self.referral_stats
+                .get(&account)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Send the unclaimed referral rewards of the caller
+        #[ink(message)]
+        pub fn claim_referral_rewards(&mut self) -> Result<()> {
+            let referrer = self.env().caller();
+            let mut stats = self.referral_stats_of(referrer);
+            let value = stats.unclaimed;
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+
+            stats.unclaimed = 0;
+            self.referral_stats.insert(referrer, stats);
+            self.record_state_change(&("claim_referral_rewards", referrer));
+
+            match self.env().transfer(referrer, value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+    }
+
+    // ---- Access grants ----
+
+    /// Maximum lifetime of an access grant.
+    const MAX_ACCESS_GRANT_MS: u64 = MS_PER_DAY;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct AccessGrant {
+        expires_ms: u64,
+        /// Operations allowed by the grant, as bit flags defined by the gateways.
+        scope: u64,
+        /// Storage deposit returned to the app when the grant is revoked.
+        deposit: Balance,
+    }
+
+    #[ink(event)]
+    pub struct AccessGranted {
+        #[ink(topic)]
+        app: AccountId,
+        #[ink(topic)]
+        node: String,
+        expires_ms: u64,
+        scope: u64,
+    }
+
+    impl Ddc {
+        /// Allow a DDN node or gateway to serve the caller app for the given scope until
+        /// `expires_ms`, at most `MAX_ACCESS_GRANT_MS` from now.
+        /// The grant replaces any previous grant of the app to this node.
+        ///
+        /// A new grant requires the storage deposit, if any, to be transferred.
+        #[ink(message, payable)]
+        pub fn grant_access(&mut self, node: String, expires_ms: u64, scope: u64) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.grant_access_at_time(node, expires_ms, scope, now_ms)
+        }
+
+        pub fn grant_access_at_time(
+            &mut self,
+            node: String,
+            expires_ms: u64,
+            scope: u64,
+            now_ms: u64,
+        ) -> Result<()> {
+            let app = self.env().caller();
+            if expires_ms <= now_ms || expires_ms > now_ms + MAX_ACCESS_GRANT_MS {
+                return Err(Error::UnexpectedTimestamp);
+            }
+            if !self.is_subscription_active(app, now_ms) {
+                return Err(Error::NoSubscription);
+            }
+
+            let key = (app, node.clone());
+            let value = self.env().transferred_balance();
+            let previous_deposit = self.access_grants.get(&key).map(|grant| grant.deposit);
+            let deposit = previous_deposit.unwrap_or(0) + value;
+            if deposit < self.storage_deposit_per_item {
+                return Err(Error::InsufficientDeposit);
+            }
+
+            self.access_grants.insert(
+                key,
+                AccessGrant {
+                    expires_ms,
+                    scope,
+                    deposit,
+                },
+            );
+            if previous_deposit.is_none() {
+                self.track_storage_item(app);
+            }
+            self.add_storage_deposit(app, value);
+            self.record_state_change(&("grant_access", app, &node));
+            Self::env().emit_event(AccessGranted {
+                app,
+                node,
+                expires_ms,
+                scope,
+            });
+
+            Ok(())
+        }
+
+        /// Revoke the grant of the caller app to a node, and return its storage deposit
+        #[ink(message)]
+        pub fn revoke_access(&mut self, node: String) -> Result<()> {
+            let app = self.env().caller();
+
+            let grant = match self.access_grants.take(&(app, node.clone())) {
+                Some(grant) => grant,
+                None => return Ok(()),
+            };
+            self.release_storage_item(app, grant.deposit);
+            self.record_state_change(&("revoke_access", app, node));
+
+            if grant.deposit > 0 {
+                if let Err(_e) = self.env().transfer(app, grant.deposit) {
+                    panic!("Transfer has failed!");
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Check that the app granted the node all operations of the scope, and that the grant
+        /// and the subscription of the app are still valid
+        #[ink(message)]
+        pub fn is_access_valid(&self, app: AccountId, node: String, scope: u64) -> bool {
+            let now_ms = Self::env().block_timestamp();
+
+            self.is_access_valid_at_time(app, node, scope, now_ms)
+        }
+
+        pub fn is_access_valid_at_time(
+            &self,
+            app: AccountId,
+            node: String,
+            scope: u64,
+            now_ms: u64,
+        ) -> bool {
+            match self.access_grants.get(&(app, node)) {
+                Some(grant) => {
+                    now_ms < grant.expires_ms
+                        && grant.scope & scope == scope
+                        && self.is_subscription_active(app, now_ms)
+                }
+                None => false,
+            }
+        }
+
+        fn is_subscription_active(&self, app: AccountId, now_ms: u64) -> bool {
+            self.subscriptions.get(&app).map_or(false, |subscription| {
+                self.get_end_date_ms(subscription) >= now_ms
+            })
+        }
+    }
+
+    // ---- Storage footprint ----
+
+    /// Estimate of the contract storage used on behalf of an account
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct StorageFootprint {
+        /// Items created by the account: subscriptions, DDC nodes, reports, attestations and
+        /// access grants. Access grants are deducted when revoked.
+        items: u32,
+        /// Storage deposits held for the account.
+        deposit: Balance,
+    }
+
+    impl Ddc {
+        #[ink(message)]
+        pub fn storage_footprint(&self, account: AccountId) -> StorageFootprint {
+            self.storage_footprints
+                .get(&account)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// As owner, set the refundable deposit required for each new item that users
+        /// can clean up themselves. Existing items keep their deposit.
+        #[ink(message)]
+        pub fn set_storage_deposit(&mut self, deposit_per_item: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            self.storage_deposit_per_item = deposit_per_item;
+            self.record_state_change(&("set_storage_deposit", deposit_per_item));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_storage_deposit(&self) -> Balance {
+            self.storage_deposit_per_item
+        }
+
+        fn track_storage_item(&mut self, account: AccountId) {
+            let mut footprint = self.storage_footprint(account);
+            footprint.items += 1;
+            self.storage_footprints.insert(account, footprint);
+        }
+
+        fn add_storage_deposit(&mut self, account: AccountId, value: Balance) {
+            if value > 0 {
+                let mut footprint = self.storage_footprint(account);
+                footprint.deposit += value;
+                self.storage_footprints.insert(account, footprint);
+            }
+        }
+
+        fn release_storage_item(&mut self, account: AccountId, deposit: Balance) {
+            let mut footprint = self.storage_footprint(account);
+            footprint.items = footprint.items.saturating_sub(1);
+            footprint.deposit = footprint.deposit.saturating_sub(deposit);
+            self.storage_footprints.insert(account, footprint);
+        }
+    }
+
+    // ---- Admin: Inspectors ----
+
+    #[ink(event)]
+    pub struct InspectorAdded {
+        #[ink(topic)]
+        inspector: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct InspectorRemoved {
+        #[ink(topic)]
+        inspector: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ErrorOnlyInspector {}
+
+    impl Ddc {
+        /// Check if account is an approved inspector.
+        fn only_inspector(&self) -> Result<()> {
+            let caller = self.env().caller();
+
+            if self.is_inspector(caller) {
+                Ok(())
+            } else {
+                self.env().emit_event(ErrorOnlyInspector {});
+                Err(Error::OnlyInspector)
+            }
+        }
+
+        #[ink(message, selector = "0x2dd04d5f")]
+        pub fn is_inspector(&self, inspector: AccountId) -> bool {
+            self.inspectors.contains_key(&inspector)
+        }
+
+        #[ink(message, selector = "0x59001154")]
+        pub fn add_inspector(&mut self, inspector: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.inspectors.insert(inspector, ());
+            Self::env().emit_event(InspectorAdded { inspector });
+            self.record_state_change(&("add_inspector", inspector));
+            Ok(())
+        }
+
+        #[ink(message, selector = "0x1213c16c")]
+        pub fn remove_inspector(&mut self, inspector: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.inspectors.take(&inspector);
+            Self::env().emit_event(InspectorRemoved { inspector });
+            self.record_state_change(&("remove_inspector", inspector));
+            Ok(())
+        }
+    }
+
+    // ---- Inspector rebates ----
+
+    #[ink(event)]
+    pub struct InspectorRebateClaimed {
+        #[ink(topic)]
+        inspector: AccountId,
+        value: Balance,
+    }
+
+    impl Ddc {
+        /// Add funds to the pool paying the rebates of the inspectors
+        #[ink(message, payable)]
+        pub fn fund_inspector_reward_pool(&mut self) -> Result<()> {
+            self.inspector_reward_pool += self.env().transferred_balance();
+            self.record_state_change(&"fund_inspector_reward_pool");
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_inspector_reward_pool(&self) -> Balance {
+            self.inspector_reward_pool
+        }
+
+        /// Set the flat rebate credited to an inspector for each accepted report,
+        /// meant to compensate the transaction fees of the report
+        #[ink(message)]
+        pub fn set_inspector_rebate(&mut self, rebate_per_report: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            self.inspector_rebate_per_report = rebate_per_report;
+            self.record_state_change(&("set_inspector_rebate", rebate_per_report));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_inspector_rebate(&self) -> Balance {
+            self.inspector_rebate_per_report
+        }
+
+        /// Return the rebates credited to an inspector and not claimed yet
+        #[ink(message)]
+        pub fn inspector_rebate_of(&self, inspector: AccountId) -> Balance {
+            *self.inspector_rebates.get(&inspector).unwrap_or(&0)
+        }
+
+        /// Pay the rebates credited to the caller, as far as the reward pool allows.
+        /// The rest remains credited.
+        #[ink(message)]
+        pub fn claim_inspector_rebate(&mut self) -> Result<()> {
+            let inspector = self.env().caller();
+            let credited = self.inspector_rebate_of(inspector);
+            let value = credited.min(self.inspector_reward_pool);
+
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+
+            self.inspector_rebates.insert(inspector, credited - value);
+            self.inspector_reward_pool -= value;
+            self.record_state_change(&("claim_inspector_rebate", inspector));
+
+            match self.env().transfer(inspector, value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => {
+                    Self::env().emit_event(InspectorRebateClaimed { inspector, value });
+                    Ok(())
+                }
+            }
+        }
+
+        /// Credit the reports of an inspector on a subscribed app, once per day
+        fn credit_app_report_rebate(&mut self, inspector: AccountId, app_id: AccountId, day: u64) {
+            if !self.subscriptions.contains_key(&app_id) {
+                return;
+            }
+            let key = (inspector, app_id, day % PERIOD_DAYS);
+            if self.rebated_app_reports.get(&key) == Some(&day) {
+                return;
+            }
+
+            self.rebated_app_reports.insert(key, day);
+            self.credit_inspector_rebate(inspector);
+        }
+
+        /// Credit the reports of an inspector on a node, metrics or status, once per day
+        fn credit_node_report_rebate(&mut self, inspector: AccountId, p2p_id: String, day: u64) {
+            let key = (inspector, p2p_id, day % PERIOD_DAYS);
+            if self.rebated_node_reports.get(&key) == Some(&day) {
+                return;
+            }
+
+            self.rebated_node_reports.insert(key, day);
+            self.credit_inspector_rebate(inspector);
+        }
+
+        fn credit_inspector_rebate(&mut self, inspector: AccountId) {
+            if self.inspector_rebate_per_report == 0 {
+                return;
+            }
+
+            *self.inspector_rebates.entry(inspector).or_insert(0) +=
+                self.inspector_rebate_per_report;
+        }
+    }
+
+    // ---- Inspector delegations ----
+
+    /// Time after an undelegation before the tokens can be withdrawn.
+    const UNDELEGATION_DELAY_MS: u64 = 7 * MS_PER_DAY;
+
+    #[ink(event)]
+    pub struct InspectorDelegated {
+        #[ink(topic)]
+        delegator: AccountId,
+        #[ink(topic)]
+        inspector: AccountId,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct InspectorUndelegated {
+        #[ink(topic)]
+        delegator: AccountId,
+        #[ink(topic)]
+        inspector: AccountId,
+        value: Balance,
+    }
+
+    impl Ddc {
+        /// Delegate the transferred tokens to an inspector, increasing its weight from the next day
+        #[ink(message, payable)]
+        pub fn delegate_to_inspector(&mut self, inspector: AccountId) -> Result<()> {
+            if !self.is_inspector(inspector) {
+                return Err(Error::InspectorNotFound);
+            }
+            let delegator = self.env().caller();
+            let value = self.env().transferred_balance();
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+
+            *self
+                .inspector_delegations
+                .entry((delegator, inspector))
+                .or_insert(0) += value;
+            let now_ms = Self::env().block_timestamp();
+            let stake = self.inspector_stake_of(inspector) + value;
+            self.set_inspector_stake(inspector, stake, now_ms);
+            Self::env().emit_event(InspectorDelegated {
+                delegator,
+                inspector,
+                value,
+            });
+            self.record_state_change(&("delegate_to_inspector", delegator, inspector));
+
+            Ok(())
+        }
+
+        /// Undelegate tokens from an inspector. They stop counting in its weight immediately,
+        /// and can be withdrawn with `withdraw_undelegated` after `UNDELEGATION_DELAY_MS`.
+        #[ink(message)]
+        pub fn undelegate(&mut self, inspector: AccountId, amount: Balance) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            self.undelegate_at_time(inspector, amount, now_ms)
+        }
+
+        pub fn undelegate_at_time(
+            &mut self,
+            inspector: AccountId,
+            amount: Balance,
+            now_ms: u64,
+        ) -> Result<()> {
+            let delegator = self.env().caller();
+            let delegated = self.delegation_of(delegator, inspector);
+            if amount == 0 || amount > delegated {
+                return Err(Error::InsufficientBalance);
+            }
+
+            self.inspector_delegations
+                .insert((delegator, inspector), delegated - amount);
+            let stake = self.inspector_stake_of(inspector) - amount;
+            self.set_inspector_stake(inspector, stake, now_ms);
+            // A new undelegation postpones the withdrawal of the pending ones
+            let (unbonding, _) = self.undelegation_of(delegator, inspector);
+            let unlock_ms = now_ms + UNDELEGATION_DELAY_MS;
+            self.inspector_unbondings
+                .insert((delegator, inspector), (unbonding + amount, unlock_ms));
+            self.record_state_change(&("undelegate", delegator, inspector));
+            Self::env().emit_event(InspectorUndelegated {
+                delegator,
+                inspector,
+                value: amount,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraw the tokens undelegated from an inspector, once the delay has passed
+        #[ink(message)]
+        pub fn withdraw_undelegated(&mut self, inspector: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            self.withdraw_undelegated_at_time(inspector, now_ms)
+        }
+
+        pub fn withdraw_undelegated_at_time(
+            &mut self,
+            inspector: AccountId,
+            now_ms: u64,
+        ) -> Result<()> {
+            let delegator = self.env().caller();
+            let (value, unlock_ms) = self.undelegation_of(delegator, inspector);
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+            if now_ms < unlock_ms {
+                return Err(Error::StakeLocked);
+            }
+
+            self.inspector_unbondings.take(&(delegator, inspector));
+            self.record_state_change(&("withdraw_undelegated", delegator, inspector));
+
+            match self.env().transfer(delegator, value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+
+        /// Return the tokens undelegated from an inspector and the time they can be withdrawn
+        #[ink(message)]
+        pub fn undelegation_of(
+            &self,
+            delegator: AccountId,
+            inspector: AccountId,
+        ) -> (Balance, u64) {
+            self.inspector_unbondings
+                .get(&(delegator, inspector))
+                .cloned()
+                .unwrap_or((0, 0))
+        }
+
+        #[ink(message)]
+        pub fn delegation_of(&self, delegator: AccountId, inspector: AccountId) -> Balance {
+            *self
+                .inspector_delegations
+                .get(&(delegator, inspector))
+                .unwrap_or(&0)
+        }
+
+        /// Return the total stake delegated to an inspector
+        #[ink(message)]
+        pub fn inspector_stake_of(&self, inspector: AccountId) -> Balance {
+            *self.inspector_stakes.get(&inspector).unwrap_or(&0)
+        }
+
+        /// As owner, set the delegated stake worth one unit of inspector weight.
+        /// Every inspector has a weight of 1, plus its stake divided by this unit.
+        /// Zero means that delegations do not affect the weights.
+        #[ink(message)]
+        pub fn set_stake_per_weight(&mut self, stake_per_weight: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            self.stake_per_weight = stake_per_weight;
+            self.record_state_change(&("set_stake_per_weight", stake_per_weight));
+
+            Ok(())
+        }
+
+        /// Return the current weight of an inspector. The metrics of a day are aggregated with
+        /// the weights at the start of the day.
+        #[ink(message)]
+        pub fn inspector_weight(&self, inspector: AccountId) -> u64 {
+            self.weight_of_stake(self.inspector_stake_of(inspector))
+        }
+
+        /// Return the weight of an inspector at the start of a day, within the last period
+        fn inspector_weight_on_day(&self, inspector: AccountId, day: u64) -> u64 {
+            // The stake at the start of a day is the stake before its first change since
+            let changed_stake = (day..day + PERIOD_DAYS).find_map(|changed_day| {
+                match self
+                    .inspector_stake_history
+                    .get(&(inspector, changed_day % PERIOD_DAYS))
+                {
+                    Some((history_day, stake)) if *history_day == changed_day => Some(*stake),
+                    _ => None,
+                }
+            });
+
+            self.weight_of_stake(
+                changed_stake.unwrap_or_else(|| self.inspector_stake_of(inspector)),
+            )
+        }
+
+        fn weight_of_stake(&self, stake: Balance) -> u64 {
+            if self.stake_per_weight == 0 {
+                return 1;
+            }
+
+            1 + (stake / self.stake_per_weight) as u64
+        }
+
+        /// Set the stake of an inspector, keeping its stake at the start of the day
+        fn set_inspector_stake(&mut self, inspector: AccountId, stake: Balance, now_ms: u64) {
+            let day = now_ms / MS_PER_DAY;
+            let key = (inspector, day % PERIOD_DAYS);
+            if self
+                .inspector_stake_history
+                .get(&key)
+                .map(|(history_day, _)| *history_day)
+                != Some(day)
+            {
+                let day_start_stake = self.inspector_stake_of(inspector);
+                self.inspector_stake_history
+                    .insert(key, (day, day_start_stake));
+            }
+
+            self.inspector_stakes.insert(inspector, stake);
+        }
+    }
+
+    // ---- DDC Node managers ----
+
+    #[ink(event)]
+    pub struct DDNManagerAdded {
+        #[ink(topic)]
+        ddn_manager: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct DDNManagerRemoved {
+        #[ink(topic)]
+        ddn_manager: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ErrorOnlyDDNManager {}
+
+    impl Ddc {
+        /// Check if account is an approved DDC node manager
+        fn only_ddn_manager(&self) -> Result<()> {
+            let caller = self.env().caller();
+
+            if self.is_ddn_manager(caller)
+                || *self.owner == caller
+                || self.has_role(caller, Role::NodeManager)
+            {
+                Ok(())
+            } else {
+                self.env().emit_event(ErrorOnlyDDNManager {});
+                Err(Error::OnlyDDNManager)
+            }
+        }
+
+        #[ink(message, selector = "0x253c1652")]
+        pub fn is_ddn_manager(&self, ddn_manager: AccountId) -> bool {
+            self.ddn_managers.contains_key(&ddn_manager)
+        }
+
+        #[ink(message, selector = "0xc231bdeb")]
+        pub fn add_ddn_manager(&mut self, ddn_manager: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.ddn_managers.insert(ddn_manager, ());
+            Self::env().emit_event(DDNManagerAdded { ddn_manager });
+            self.record_state_change(&("add_ddn_manager", ddn_manager));
+            Ok(())
+        }
+
+        #[ink(message, selector = "0xb8d989fc")]
+        pub fn remove_ddn_manager(&mut self, ddn_manager: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.ddn_managers.take(&ddn_manager);
+            Self::env().emit_event(DDNManagerRemoved { ddn_manager });
+            self.record_state_change(&("remove_ddn_manager", ddn_manager));
+            Ok(())
+        }
+    }
+
+    // ---- DDC nodes ----
+
+    /// Default minimum time between two changes of the record of a DDC node.
+    const NODE_UPDATE_COOLDOWN_MS: u64 = 3600 * 1000;
+
+    /// Maximum span of the queue of DDC nodes, including the nodes which left it.
+    const MAX_NODE_QUEUE_LEN: u32 = 100;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct DDCNode {
+        p2p_id: String,
+        p2p_addr: String,
+        url: String,
+        /// There is only one known permission for trusted nodes:
+        ///
+        ///     is_trusted = (permissions & 1) != 0
+        permissions: u64,
+        /// DDC network of the node, 0 for the default network.
+        namespace: u8,
+        /// Account allowed to update the addresses of the node.
+        operator: AccountId,
+
+        /// Placement information declared by the operator.
+        storage_capacity_bytes: u64,
+        region: String,
+        /// Tiers whose apps the node accepts.
+        tier_support: Vec<u64>,
+    }
+
+    #[ink(event)]
+    pub struct DDCNodeAdded {
+        #[ink(topic)]
+        p2p_id: String,
+        p2p_addr: String,
+        url: String,
+        permissions: u64,
+    }
+
+    #[ink(event)]
+    pub struct NodeUpdated {
+        #[ink(topic)]
+        p2p_id: String,
+        old_p2p_addr: String,
+        new_p2p_addr: String,
+        old_url: String,
+        new_url: String,
+    }
+
+    #[ink(event)]
+    pub struct DDCNodeRemoved {
+        #[ink(topic)]
+        p2p_id: String,
+        p2p_addr: String,
+    }
+
+    #[ink(event)]
+    pub struct NodeQueued {
+        #[ink(topic)]
+        p2p_id: String,
+        /// Number of nodes to be admitted before this one.
+        position: u32,
+    }
+
+    #[ink(event)]
+    pub struct NodeAdmitted {
+        #[ink(topic)]
+        p2p_id: String,
+    }
+
+    impl Ddc {
+        /// Return the list of all DDC nodes
+        #[ink(message, selector = "0xe6c98b60")]
+        pub fn get_all_ddc_nodes(&self) -> Vec<DDCNode> {
+            self.ddc_nodes.values().cloned().collect()
+        }
+
+        /// Return a page of the DDC nodes, starting at the cursor
+        #[ink(message)]
+        pub fn get_ddc_nodes_page(&self, cursor: u32, limit: u32) -> Page<DDCNode> {
+            paginate(
+                self.ddc_nodes.values().cloned(),
+                self.ddc_nodes.len(),
+                cursor,
+                limit,
+            )
+        }
+
+        /// Add DDC node to the list.
+        ///
+        /// If the node already exists based on p2p_id, update all fields.
+        /// If the target number of nodes is reached, a new node is queued instead.
+        ///
+        /// Use permissions 1 for a trusted node, otherwise 0.
+        #[ink(message, selector = "0x11a9e1b9")]
+        pub fn add_ddc_node(
+            &mut self,
+            p2p_id: String,
+            p2p_addr: String,
+            url: String,
+            permissions: u64,
+        ) -> Result<()> {
+            self.only_ddn_manager()?;
+
+            // An existing node stays in its namespace
+            let namespace = self.ddc_nodes.get(&p2p_id).map_or(0, |node| node.namespace);
+            let caller = self.env().caller();
+            self.insert_ddc_node(namespace, p2p_id, p2p_addr, url, permissions, caller)
+        }
+
+        /// Add or update a node. The operator is only recorded for a new node,
+        /// an existing node keeps its operator.
+        fn insert_ddc_node(
+            &mut self,
+            namespace: u8,
+            p2p_id: String,
+            p2p_addr: String,
+            url: String,
+            permissions: u64,
+            operator: AccountId,
+        ) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            if let Some(updated_ms) = self.ddc_node_updated_ms.get(&p2p_id) {
+                if now_ms < updated_ms + self.node_update_cooldown_ms {
+                    return Err(Error::NodeUpdateTooSoon);
+                }
+            }
+            let mut node = self.ddc_nodes.get(&p2p_id).cloned().unwrap_or(DDCNode {
+                operator,
+                ..DDCNode::default()
+            });
+            node.p2p_id = p2p_id.clone();
+            node.p2p_addr = p2p_addr.clone();
+            node.url = url.clone();
+            node.permissions = permissions;
+            node.namespace = namespace;
+            // A queued node keeps its position, even if there is room now
+            if let Some(queued) = self.queued_nodes.get_mut(&p2p_id) {
+                queued.1 = node;
+                self.record_state_change(&("update_queued_ddc_node", &p2p_id));
+                return Ok(());
+            }
+            if !self.ddc_nodes.contains_key(&p2p_id) && self.is_at_target_node_count() {
+                return self.enqueue_ddc_node(node);
+            }
+
+            self.record_state_change(&("add_ddc_node", &p2p_id));
+
+            // The cooldown starts on updates, and is kept if the node is removed and added again
+            if self.ddc_nodes.contains_key(&p2p_id)
+                || self.ddc_node_updated_ms.contains_key(&p2p_id)
+            {
+                self.ddc_node_updated_ms.insert(p2p_id.clone(), now_ms);
+            }
+            let old_node = self.ddc_nodes.insert(p2p_id.clone(), node);
+            // Charged to the operator, a queued node being admitted by someone else's call
+            if old_node.is_none() {
+                self.track_storage_item(operator);
+                self.orphan_node_metrics(p2p_id.clone(), false);
+            }
+            if let Some(old_node) = old_node {
+                Self::env().emit_event(NodeUpdated {
+                    p2p_id: p2p_id.clone(),
+                    old_p2p_addr: old_node.p2p_addr,
+                    new_p2p_addr: p2p_addr.clone(),
+                    old_url: old_node.url,
+                    new_url: url.clone(),
+                });
+            }
+            Self::env().emit_event(DDCNodeAdded {
+                p2p_id,
+                p2p_addr,
+                url,
+                permissions,
+            });
+
+            Ok(())
+        }
+
+        /// As owner, set the minimum time between two changes of the record of a DDC node
+        #[ink(message)]
+        pub fn set_node_update_cooldown(&mut self, cooldown_ms: u64) -> Result<()> {
+            self.only_owner()?;
+
+            self.node_update_cooldown_ms = cooldown_ms;
+            self.record_state_change(&("set_node_update_cooldown", cooldown_ms));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_node_update_cooldown(&self) -> u64 {
+            self.node_update_cooldown_ms
+        }
+
+        /// As owner, set the maximum number of DDC nodes, or None for no limit.
+        /// Queued nodes are admitted if there is room.
+        #[ink(message)]
+        pub fn set_target_node_count(&mut self, target: Option<u32>) -> Result<()> {
+            self.only_owner()?;
+
+            self.target_node_count = target;
+            self.record_state_change(&("set_target_node_count", target));
+            self.admit_queued_nodes();
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_target_node_count(&self) -> Option<u32> {
+            self.target_node_count
+        }
+
+        /// Return the nodes waiting to be admitted, in order of admission
+        #[ink(message)]
+        pub fn get_node_queue(&self) -> Vec<DDCNode> {
+            (self.node_queue_head..self.node_queue_tail)
+                .filter_map(|index| self.ddc_node_queue.get(&index))
+                .filter_map(|p2p_id| self.queued_nodes.get(p2p_id))
+                .map(|(_, node)| node.clone())
+                .collect()
+        }
+
+        /// Return the number of nodes to be admitted before a queued node
+        #[ink(message)]
+        pub fn node_queue_position(&self, p2p_id: String) -> Option<u32> {
+            let (index, _) = self.queued_nodes.get(&p2p_id)?;
+            let ahead = (self.node_queue_head..*index)
+                .filter(|other| self.ddc_node_queue.contains_key(other))
+                .count();
+
+            Some(ahead as u32)
+        }
+
+        fn is_at_target_node_count(&self) -> bool {
+            self.target_node_count
+                .map_or(false, |target| self.ddc_nodes.len() >= target)
+        }
+
+        /// Queue a node at the end of the queue, unless it spans MAX_NODE_QUEUE_LEN already
+        fn enqueue_ddc_node(&mut self, node: DDCNode) -> Result<()> {
+            if self.node_queue_tail - self.node_queue_head >= MAX_NODE_QUEUE_LEN {
+                return Err(Error::NodeQueueFull);
+            }
+
+            let p2p_id = node.p2p_id.clone();
+            let index = self.node_queue_tail;
+            self.ddc_node_queue.insert(index, p2p_id.clone());
+            self.queued_nodes.insert(p2p_id.clone(), (index, node));
+            self.node_queue_tail += 1;
+            let position = self.node_queue_position(p2p_id.clone()).unwrap_or(0);
+            self.record_state_change(&("enqueue_ddc_node", &p2p_id));
+
+            Self::env().emit_event(NodeQueued { p2p_id, position });
+
+            Ok(())
+        }
+
+        /// Admit queued nodes, first in first out, while below the target number of nodes
+        fn admit_queued_nodes(&mut self) {
+            while self.node_queue_head < self.node_queue_tail && !self.is_at_target_node_count() {
+                let index = self.node_queue_head;
+                self.node_queue_head += 1;
+                let queued = match self.ddc_node_queue.take(&index) {
+                    Some(p2p_id) => self.queued_nodes.take(&p2p_id),
+                    None => None,
+                };
+                // Skip the nodes which left the queue
+                let node = match queued {
+                    Some((_, node)) => node,
+                    None => continue,
+                };
+                let p2p_id = node.p2p_id.clone();
+
+                // Queued nodes have never been added, so there is no cooldown to wait for
+                if self
+                    .insert_ddc_node(
+                        node.namespace,
+                        node.p2p_id,
+                        node.p2p_addr,
+                        node.url,
+                        node.permissions,
+                        node.operator,
+                    )
+                    .is_ok()
+                {
+                    Self::env().emit_event(NodeAdmitted { p2p_id });
+                }
+            }
+        }
+
+        /// As operator of a node, or owner, change the URL of the node
+        #[ink(message)]
+        pub fn update_ddc_node_url(&mut self, p2p_id: String, url: String) -> Result<()> {
+            let node = self.only_operator_of(p2p_id.clone())?;
+
+            self.insert_ddc_node(
+                node.namespace,
+                p2p_id,
+                node.p2p_addr,
+                url,
+                node.permissions,
+                node.operator,
+            )
+        }
+
+        /// As operator of a node, or owner, change the p2p address of the node
+        #[ink(message)]
+        pub fn update_ddc_node_addr(&mut self, p2p_id: String, p2p_addr: String) -> Result<()> {
+            let node = self.only_operator_of(p2p_id.clone())?;
+
+            self.insert_ddc_node(
+                node.namespace,
+                p2p_id,
+                p2p_addr,
+                node.url,
+                node.permissions,
+                node.operator,
+            )
+        }
+
+        /// As operator of a node, or owner, declare its placement information:
+        /// the storage it offers, its region, and the tiers whose apps it accepts
+        #[ink(message)]
+        pub fn set_ddc_node_placement(
+            &mut self,
+            p2p_id: String,
+            storage_capacity_bytes: u64,
+            region: String,
+            tier_support: Vec<u64>,
+        ) -> Result<()> {
+            self.only_operator_of(p2p_id.clone())?;
+            for tier_id in tier_support.iter() {
+                self.tid_in_bound(*tier_id)?;
+            }
+
+            let node = self.ddc_nodes.get_mut(&p2p_id).unwrap();
+            node.storage_capacity_bytes = storage_capacity_bytes;
+            node.region = region;
+            node.tier_support = tier_support;
+            self.record_state_change(&("set_ddc_node_placement", &p2p_id));
+
+            Ok(())
+        }
+
+        /// As DDN manager, change the operator of a node
+        #[ink(message)]
+        pub fn set_ddc_node_operator(&mut self, p2p_id: String, operator: AccountId) -> Result<()> {
+            self.only_ddn_manager()?;
+
+            let node = self.ddc_nodes.get_mut(&p2p_id).ok_or(Error::DDNNotFound)?;
+            let old_operator = node.operator;
+            node.operator = operator;
+            self.release_storage_item(old_operator, 0);
+            self.track_storage_item(operator);
+            self.record_state_change(&("set_ddc_node_operator", &p2p_id, operator));
+
+            Ok(())
+        }
+
+        fn only_operator_of(&self, p2p_id: String) -> Result<DDCNode> {
+            let caller = self.env().caller();
+            let node = self.ddc_nodes.get(&p2p_id).ok_or(Error::DDNNotFound)?;
+
+            if node.operator == caller || *self.owner == caller {
+                Ok(node.clone())
+            } else {
+                Err(Error::NoPermission)
+            }
+        }
+
+        /// Check that the caller operates the node, or manages the DDC nodes
+        fn only_operator_or_manager_of(&self, p2p_id: String) -> Result<DDCNode> {
+            let caller = self.env().caller();
+
+            if self.is_ddn_manager(caller) || self.has_role(caller, Role::NodeManager) {
+                self.ddc_nodes
+                    .get(&p2p_id)
+                    .cloned()
+                    .ok_or(Error::DDNNotFound)
+            } else {
+                self.only_operator_of(p2p_id)
+            }
+        }
+
+        /// Check if DDC node is in the list
+        #[ink(message, selector = "0xa8babfa0")]
+        pub fn is_ddc_node(&self, p2p_id: String) -> bool {
+            self.ddc_nodes.contains_key(&p2p_id)
+        }
+
+        /// Removes DDC node from the list
+        #[ink(message, selector = "0xe1aa053e")]
+        pub fn remove_ddc_node(&mut self, p2p_id: String) -> Result<()> {
+            let caller = self.env().caller();
+            let namespace = self.ddc_nodes.get(&p2p_id).map(|node| node.namespace);
+            if !namespace.map_or(false, |ns| self.is_namespace_admin(ns, caller)) {
+                self.only_ddn_manager()?;
+            }
+
+            self.delete_ddc_node(p2p_id)
+        }
+
+        /// Remove a DDC node, or take it out of the queue, with all its records.
+        /// The stake of a self-registered node starts its cooldown.
+        fn delete_ddc_node(&mut self, p2p_id: String) -> Result<()> {
+            // A queued node leaves the queue
+            if let Some((index, removed_node)) = self.queued_nodes.take(&p2p_id) {
+                self.ddc_node_queue.take(&index);
+                self.unlock_node_stake(p2p_id.clone());
+                self.record_state_change(&("remove_ddc_node", &p2p_id));
+                Self::env().emit_event(DDCNodeRemoved {
+                    p2p_id,
+                    p2p_addr: removed_node.p2p_addr,
+                });
+                return Ok(());
+            }
+
+            // Remove DDN if exists
+            let removed_node = self.ddc_nodes.take(&p2p_id).ok_or(Error::DDNNotFound)?;
+            self.unlock_node_stake(p2p_id.clone());
+            self.release_storage_item(removed_node.operator, 0);
+            self.orphan_node_metrics(p2p_id.clone(), true);
+            Self::env().emit_event(DDCNodeRemoved {
+                p2p_id: p2p_id.clone(),
+                p2p_addr: removed_node.p2p_addr,
+            });
+
+            // Remove DDN status entries from all inspectors
+            for &inspector in self.inspectors.keys() {
+                self.ddn_statuses.take(&DDNStatusKey {
+                    inspector,
+                    p2p_id: p2p_id.clone(),
+                });
+            }
+            self.node_payout_addresses.take(&p2p_id);
+            self.node_capacities.take(&p2p_id);
+            self.node_penalties.take(&p2p_id);
+            self.node_contacts.take(&p2p_id);
+            self.node_clusters.take(&p2p_id);
+            self.archived_nodes.take(&p2p_id);
+            self.record_state_change(&("remove_ddc_node", p2p_id));
+            self.admit_queued_nodes();
+
+            Ok(())
+        }
+    }
+
+    // ---- Namespaces ----
+
+    #[ink(event)]
+    pub struct NamespaceAdminAdded {
+        #[ink(topic)]
+        namespace: u8,
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct NamespaceAdminRemoved {
+        #[ink(topic)]
+        namespace: u8,
+        #[ink(topic)]
+        admin: AccountId,
+    }
+
+    impl Ddc {
+        /// Check if the caller may administrate the tiers and nodes of a namespace
+        fn only_namespace_admin(&self, namespace: u8) -> Result<()> {
+            let caller = self.env().caller();
+
+            if *self.owner == caller || self.is_namespace_admin(namespace, caller) {
+                Ok(())
+            } else {
+                Err(Error::OnlyNamespaceAdmin)
+            }
+        }
+
+        #[ink(message)]
+        pub fn is_namespace_admin(&self, namespace: u8, admin: AccountId) -> bool {
+            self.namespace_admins.contains_key(&(namespace, admin))
+        }
+
+        /// As owner, delegate the administration of a namespace
+        #[ink(message)]
+        pub fn add_namespace_admin(&mut self, namespace: u8, admin: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.namespace_admins.insert((namespace, admin), ());
+            Self::env().emit_event(NamespaceAdminAdded { namespace, admin });
+            self.record_state_change(&("add_namespace_admin", namespace, admin));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn remove_namespace_admin(&mut self, namespace: u8, admin: AccountId) -> Result<()> {
+            self.only_owner()?;
+
+            self.namespace_admins.take(&(namespace, admin));
+            Self::env().emit_event(NamespaceAdminRemoved { namespace, admin });
+            self.record_state_change(&("remove_namespace_admin", namespace, admin));
+
+            Ok(())
+        }
+
+        /// Add a tier to a namespace, see `add_tier`
+        #[ink(message)]
+        pub fn add_tier_in(
+            &mut self,
+            namespace: u8,
+            tier_fee: Balance,
+            storage_bytes: u64,
+            wcu_per_minute: u64,
+            rcu_per_minute: u64,
+        ) -> Result<u64> {
+            self.only_namespace_admin(namespace)?;
+
+            self.insert_tier(
+                namespace,
+                tier_fee,
+                storage_bytes,
+                wcu_per_minute,
+                rcu_per_minute,
+                String::new(),
+                String::new(),
+            )
+        }
+
+        #[ink(message)]
+        pub fn get_tiers_in(&self, namespace: u8) -> Vec<ServiceTier> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.service_tiers
+                .values()
+                .filter(|tier| tier.namespace == namespace)
+                .map(|tier| self.effective_tier(tier, now_ms))
+                .collect()
+        }
+
+        /// Add or update a DDC node of a namespace, see `add_ddc_node`.
+        /// A namespace admin cannot take over a node of another namespace.
+        #[ink(message)]
+        pub fn add_ddc_node_in(
+            &mut self,
+            namespace: u8,
+            p2p_id: String,
+            p2p_addr: String,
+            url: String,
+            permissions: u64,
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let existing_namespace = self.ddc_nodes.get(&p2p_id).map(|node| node.namespace);
+            let is_admin_of_node = existing_namespace.map_or(true, |ns| ns == namespace)
+                && self.is_namespace_admin(namespace, caller);
+            if !is_admin_of_node {
+                self.only_ddn_manager()?;
+            }
+
+            self.insert_ddc_node(namespace, p2p_id, p2p_addr, url, permissions, caller)
+        }
+
+        #[ink(message)]
+        pub fn get_ddc_nodes_in(&self, namespace: u8) -> Vec<DDCNode> {
+            self.ddc_nodes
+                .values()
+                .filter(|node| node.namespace == namespace)
+                .cloned()
+                .collect()
+        }
+
+        /// Return the namespace of an app, which is the namespace of its tier.
+        /// The metrics of the app belong to that namespace.
+        #[ink(message)]
+        pub fn namespace_of_app(&self, app_id: AccountId) -> Result<u8> {
+            let subscription = self
+                .subscriptions
+                .get(&app_id)
+                .ok_or(Error::NoSubscription)?;
+            let tier = self
+                .service_tiers
+                .get(&subscription.tier_id)
+                .ok_or(Error::TidOutOfBound)?;
+
+            Ok(tier.namespace)
+        }
+    }
+
+    // ---- Clusters ----
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct Cluster {
+        cluster_id: u32,
+        name: String,
+        /// Number of nodes of the cluster expected to store each piece of data.
+        replication_factor: u32,
+    }
+
+    /// Service level targets of a cluster, checked when the periods are rewarded
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct ClusterSla {
+        /// Maximum downtime of a node over a period.
+        max_downtime_ms: u64,
+        /// Minimum number of nodes within the downtime target.
+        min_replication: u32,
+    }
+
+    /// Compliance of a cluster with its SLA over a rewarded period
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct ClusterSlaReport {
+        /// Nodes of the cluster.
+        nodes: u32,
+        /// Nodes with a reported status and a downtime within the target.
+        nodes_within_sla: u32,
+        /// Highest downtime of a node of the cluster.
+        max_downtime_ms: u64,
+        /// Whether at least `min_replication` nodes were within the downtime target.
+        compliant: bool,
+    }
+
+    #[ink(event)]
+    pub struct ClusterCreated {
+        #[ink(topic)]
+        cluster_id: u32,
+        name: String,
+    }
+
+    #[ink(event)]
+    pub struct NodeClusterAssigned {
+        #[ink(topic)]
+        p2p_id: String,
+        cluster_id: u32,
+    }
+
+    #[ink(event)]
+    pub struct TierAvailabilityChanged {
+        #[ink(topic)]
+        cluster_id: u32,
+        tier_id: u64,
+        offered: bool,
+    }
+
+    #[ink(event)]
+    pub struct AppClusterAssigned {
+        #[ink(topic)]
+        app_id: AccountId,
+        cluster_id: u32,
+    }
+
+    impl Ddc {
+        /// As tier manager, offer or withdraw a tier in a cluster
+        #[ink(message)]
+        pub fn set_tier_availability(
+            &mut self,
+            tier_id: u64,
+            cluster_id: u32,
+            offered: bool,
+        ) -> Result<()> {
+            self.only_role(Role::TierManager)?;
+            self.tid_in_bound(tier_id)?;
+
+            if offered {
+                self.cluster_tiers.insert((cluster_id, tier_id), ());
+            } else {
+                self.cluster_tiers.take(&(cluster_id, tier_id));
+            }
+            self.record_state_change(&("set_tier_availability", tier_id, cluster_id, offered));
+            Self::env().emit_event(TierAvailabilityChanged {
+                cluster_id,
+                tier_id,
+                offered,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_tiers_for_cluster(&self, cluster_id: u32) -> Vec<ServiceTier> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.service_tiers
+                .values()
+                .filter(|tier| tier.active && self.is_tier_offered_in(tier.tier_id, cluster_id))
+                .map(|tier| self.effective_tier(tier, now_ms))
+                .collect()
+        }
+
+        /// As DDN manager, assign an app to the cluster serving it, or None to unassign it.
+        /// An assigned app can only subscribe to the tiers offered in its cluster.
+        #[ink(message)]
+        pub fn assign_app_cluster(
+            &mut self,
+            app_id: AccountId,
+            cluster_id: Option<u32>,
+        ) -> Result<()> {
+            self.only_ddn_manager()?;
+
+            match cluster_id {
+                Some(cluster_id) => {
+                    self.app_clusters.insert(app_id, cluster_id);
+                    Self::env().emit_event(AppClusterAssigned { app_id, cluster_id });
+                }
+                None => {
+                    self.app_clusters.take(&app_id);
+                }
+            }
+            self.record_state_change(&("assign_app_cluster", app_id, cluster_id));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn cluster_of_app(&self, app_id: AccountId) -> Option<u32> {
+            self.app_clusters.get(&app_id).copied()
+        }
+
+        /// As DDN manager, create a cluster with a replication factor of 1.
+        /// Return the id of the cluster.
+        #[ink(message)]
+        pub fn create_cluster(&mut self, name: String) -> Result<u32> {
+            self.only_ddn_manager()?;
+
+            // Clusters are never removed
+            let cluster_id = self.clusters.len() + 1;
+            self.clusters.insert(
+                cluster_id,
+                Cluster {
+                    cluster_id,
+                    name: name.clone(),
+                    replication_factor: 1,
+                },
+            );
+            self.record_state_change(&("create_cluster", cluster_id));
+            Self::env().emit_event(ClusterCreated { cluster_id, name });
+
+            Ok(cluster_id)
+        }
+
+        /// As DDN manager, set the target replication factor of a cluster
+        #[ink(message)]
+        pub fn set_cluster_replication_factor(
+            &mut self,
+            cluster_id: u32,
+            replication_factor: u32,
+        ) -> Result<()> {
+            self.only_ddn_manager()?;
+
+            if replication_factor == 0 {
+                return Err(Error::InvalidReplicationFactor);
+            }
+            let cluster = self
+                .clusters
+                .get_mut(&cluster_id)
+                .ok_or(Error::ClusterNotFound)?;
+            cluster.replication_factor = replication_factor;
+            self.record_state_change(&(
+                "set_cluster_replication_factor",
+                cluster_id,
+                replication_factor,
+            ));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_cluster(&self, cluster_id: u32) -> Option<Cluster> {
+            self.clusters.get(&cluster_id).cloned()
+        }
+
+        #[ink(message)]
+        pub fn get_clusters(&self) -> Vec<Cluster> {
+            self.clusters.values().cloned().collect()
+        }
+
+        /// As DDN manager, move a DDC node to a cluster
+        #[ink(message)]
+        pub fn assign_node_to_cluster(&mut self, p2p_id: String, cluster_id: u32) -> Result<()> {
+            self.only_ddn_manager()?;
+
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+            if !self.clusters.contains_key(&cluster_id) {
+                return Err(Error::ClusterNotFound);
+            }
+            if self.is_node_leaving(p2p_id.clone()) {
+                return Err(Error::NodeLeaving);
+            }
+
+            self.node_clusters.insert(p2p_id.clone(), cluster_id);
+            self.record_state_change(&("assign_node_to_cluster", &p2p_id, cluster_id));
+            Self::env().emit_event(NodeClusterAssigned { p2p_id, cluster_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_cluster_nodes(&self, cluster_id: u32) -> Vec<DDCNode> {
+            self.node_clusters
+                .iter()
+                .filter(|(_, node_cluster_id)| **node_cluster_id == cluster_id)
+                .filter_map(|(p2p_id, _)| self.ddc_nodes.get(p2p_id).cloned())
+                .collect()
+        }
+
+        /// As owner, set the SLA targets of a cluster: the maximum downtime of its nodes over
+        /// a period, and the minimum number of nodes within it. The compliance of the cluster
+        /// is recorded when each period is rewarded, see `cluster_sla_report`.
+        #[ink(message)]
+        pub fn set_cluster_sla(
+            &mut self,
+            cluster_id: u32,
+            max_downtime_ms: u64,
+            min_replication: u32,
+        ) -> Result<()> {
+            self.only_owner()?;
+
+            if !self.clusters.contains_key(&cluster_id) {
+                return Err(Error::ClusterNotFound);
+            }
+            if min_replication == 0 {
+                return Err(Error::InvalidReplicationFactor);
+            }
+
+            self.cluster_slas.insert(
+                cluster_id,
+                ClusterSla {
+                    max_downtime_ms,
+                    min_replication,
+                },
+            );
+            self.record_state_change(&(
+                "set_cluster_sla",
+                cluster_id,
+                max_downtime_ms,
+                min_replication,
+            ));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_cluster_sla(&self, cluster_id: u32) -> Option<ClusterSla> {
+            self.cluster_slas.get(&cluster_id).cloned()
+        }
+
+        /// Return the compliance of a cluster with its SLA over a rewarded period, if the
+        /// cluster had SLA targets then
+        #[ink(message)]
+        pub fn cluster_sla_report(
+            &self,
+            cluster_id: u32,
+            period_start_ms: u64,
+        ) -> Option<ClusterSlaReport> {
+            self.cluster_sla_reports
+                .get(&(cluster_id, period_start_ms))
+                .cloned()
+        }
+
+        /// Record the compliance of the clusters with SLA targets over the days
+        /// [first_day, end_day), from the downtime of their nodes
+        fn record_cluster_sla_reports(
+            &mut self,
+            period_start_ms: u64,
+            first_day: u64,
+            end_day: u64,
+        ) {
+            if self.cluster_slas.is_empty() {
+                return;
+            }
+
+            let mut reports: Vec<(u32, ClusterSlaReport)> = Vec::new();
+            for (p2p_id, cluster_id) in self.node_clusters.iter() {
+                if self.archived_nodes.contains_key(p2p_id) {
+                    continue;
+                }
+                let sla = match self.cluster_slas.get(cluster_id) {
+                    None => continue,
+                    Some(sla) => sla,
+                };
+                let index = match reports.iter().position(|(id, _)| id == cluster_id) {
+                    Some(index) => index,
+                    None => {
+                        reports.push((*cluster_id, ClusterSlaReport::default()));
+                        reports.len() - 1
+                    }
+                };
+                let report = &mut reports[index].1;
+                let downtime = self.ddn_downtime_in_days(p2p_id.clone(), first_day, end_day);
+
+                report.nodes += 1;
+                report.max_downtime_ms = report.max_downtime_ms.max(downtime);
+                // Nodes never reported are not known to be up
+                if self.get_ddn_status(p2p_id.clone()).is_ok() && downtime <= sla.max_downtime_ms {
+                    report.nodes_within_sla += 1;
+                }
+            }
+
+            for (cluster_id, mut report) in reports {
+                let min_replication = self.cluster_slas.get(&cluster_id).unwrap().min_replication;
+                report.compliant = report.nodes_within_sla >= min_replication;
+                self.cluster_sla_reports
+                    .insert((cluster_id, period_start_ms), report);
+            }
+        }
+
+        fn is_tier_offered_in(&self, tier_id: u64, cluster_id: u32) -> bool {
+            self.cluster_tiers.contains_key(&(cluster_id, tier_id))
+        }
+
+        /// Check that the cluster of the app, if any, offers the tier
+        fn enforce_tier_in_cluster(&self, app_id: AccountId, tier_id: u64) -> Result<()> {
+            match self.cluster_of_app(app_id) {
+                Some(cluster_id) if !self.is_tier_offered_in(tier_id, cluster_id) => {
+                    Err(Error::TierNotInCluster)
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    // ---- DDC node payout addresses ----
+
+    #[ink(event)]
+    pub struct NodePayoutAddressChangeScheduled {
+        #[ink(topic)]
+        p2p_id: String,
+        address: AccountId,
+        effective_ms: u64,
+    }
+
+    impl Ddc {
+        /// As operator of a DDC node, or DDN manager, set the account receiving the rewards of
+        /// the node, e.g. a cold wallet.
+        ///
+        /// The change takes effect after ADDRESS_CHANGE_DELAY_MS, so that a stolen operational
+        /// key cannot redirect the rewards instantly.
+        #[ink(message)]
+        pub fn set_node_payout_address(
+            &mut self,
+            p2p_id: String,
+            address: AccountId,
+        ) -> Result<()> {
+            self.only_operator_or_manager_of(p2p_id.clone())?;
+
+            if address == AccountId::default() {
+                return Err(Error::InvalidAccount);
+            }
+
+            let now_ms = Self::env().block_timestamp();
+            let mut payout_address = self
+                .node_payout_addresses
+                .get(&p2p_id)
+                .cloned()
+                .unwrap_or_default();
+            payout_address.schedule(address, now_ms, ADDRESS_CHANGE_DELAY_MS);
+            let effective_ms = payout_address.effective_ms;
+            self.node_payout_addresses
+                .insert(p2p_id.clone(), payout_address);
+            self.record_state_change(&("set_node_payout_address", &p2p_id));
+
+            Self::env().emit_event(NodePayoutAddressChangeScheduled {
+                p2p_id,
+                address,
+                effective_ms,
+            });
+
+            Ok(())
+        }
+
+        /// Return the account receiving the rewards of a DDC node, if set
+        #[ink(message)]
+        pub fn get_node_payout_address(&self, p2p_id: String) -> Option<AccountId> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.node_payout_addresses
+                .get(&p2p_id)
+                .and_then(|payout_address| payout_address.at_time(now_ms))
+        }
+    }
+
+    // ---- DDC node contacts ----
+
+    /// Maximum length of a node contact, in bytes.
+    const MAX_NODE_CONTACT_LEN: usize = 128;
+
+    #[ink(event)]
+    pub struct NodeContactChanged {
+        #[ink(topic)]
+        p2p_id: String,
+        contact: Option<String>,
+    }
+
+    impl Ddc {
+        /// As operator of a node, or owner, set where to notify the operator, e.g. the CID of
+        /// an encrypted document or an XCM-addressable identity. Not interpreted by the contract.
+        #[ink(message)]
+        pub fn set_node_contact(&mut self, p2p_id: String, contact: String) -> Result<()> {
+            self.only_operator_of(p2p_id.clone())?;
+
+            if contact.len() > MAX_NODE_CONTACT_LEN {
+                return Err(Error::ContactTooLong);
+            }
+
+            self.node_contacts.insert(p2p_id.clone(), contact.clone());
+            self.record_state_change(&("set_node_contact", &p2p_id));
+            Self::env().emit_event(NodeContactChanged {
+                p2p_id,
+                contact: Some(contact),
+            });
+
+            Ok(())
+        }
+
+        /// As operator of a node, or owner, remove the contact of the node
+        #[ink(message)]
+        pub fn clear_node_contact(&mut self, p2p_id: String) -> Result<()> {
+            self.only_operator_of(p2p_id.clone())?;
+
+            if self.node_contacts.take(&p2p_id).is_some() {
+                self.record_state_change(&("clear_node_contact", &p2p_id));
+                Self::env().emit_event(NodeContactChanged {
+                    p2p_id,
+                    contact: None,
+                });
+            }
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_node_contact(&self, p2p_id: String) -> Option<String> {
+            self.node_contacts.get(&p2p_id).cloned()
+        }
+    }
+
+    // ---- DDC node capacities ----
+
+    /// Number of capacity declarations kept per node.
+    const MAX_CAPACITY_HISTORY: usize = 12;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct NodeCapacity {
+        effective_ms: u64,
+        storage_bytes: u64,
+        wcu_per_minute: u64,
+        rcu_per_minute: u64,
+    }
+
+    #[ink(event)]
+    pub struct NodeCapacityUpdated {
+        #[ink(topic)]
+        p2p_id: String,
+        capacity: NodeCapacity,
+    }
+
+    impl Ddc {
+        /// As operator of a DDC node, or DDN manager, declare the capacity of the node from
+        /// now on. Only the last MAX_CAPACITY_HISTORY declarations are kept.
+        #[ink(message)]
+        pub fn update_capacity(
+            &mut self,
+            p2p_id: String,
+            storage_bytes: u64,
+            wcu_per_minute: u64,
+            rcu_per_minute: u64,
+        ) -> Result<()> {
+            self.only_operator_or_manager_of(p2p_id.clone())?;
+
+            let capacity = NodeCapacity {
+                effective_ms: Self::env().block_timestamp(),
+                storage_bytes,
+                wcu_per_minute,
+                rcu_per_minute,
+            };
+            let mut history = self
+                .node_capacities
+                .get(&p2p_id)
+                .cloned()
+                .unwrap_or_default();
+            // A declaration in the same block replaces the previous one
+            if history.last().map(|last| last.effective_ms) == Some(capacity.effective_ms) {
+                history.pop();
+            }
+            history.push(capacity.clone());
+            if history.len() > MAX_CAPACITY_HISTORY {
+                history.remove(0);
+            }
+            self.node_capacities.insert(p2p_id.clone(), history);
+            self.record_state_change(&("update_capacity", &p2p_id));
+
+            Self::env().emit_event(NodeCapacityUpdated { p2p_id, capacity });
+
+            Ok(())
+        }
+
+        /// Return the kept capacity declarations of a DDC node, oldest first
+        #[ink(message)]
+        pub fn get_capacity_history(&self, p2p_id: String) -> Vec<NodeCapacity> {
+            self.node_capacities
+                .get(&p2p_id)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Return the capacity declared for a DDC node at the given time
+        #[ink(message)]
+        pub fn capacity_at(&self, p2p_id: String, time_ms: u64) -> Option<NodeCapacity> {
+            self.node_capacities.get(&p2p_id).and_then(|history| {
+                history
+                    .iter()
+                    .rev()
+                    .find(|capacity| capacity.effective_ms <= time_ms)
+                    .cloned()
+            })
+        }
+    }
+
+    // ---- DDC node stakes ----
+
+    /// Time between the deregistration of a node and the withdrawal of its stake,
+    /// during which the stake can still be slashed.
+    const NODE_UNSTAKE_COOLDOWN_MS: u64 = 7 * MS_PER_DAY;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct NodeStake {
+        operator: AccountId,
+        value: Balance,
+        /// Time from which the stake can be withdrawn, None while the node is active.
+        unlock_ms: Option<u64>,
+    }
+
+    #[ink(event)]
+    pub struct NodeStaked {
+        #[ink(topic)]
+        p2p_id: String,
+        #[ink(topic)]
+        operator: AccountId,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct NodeSlashed {
+        #[ink(topic)]
+        p2p_id: String,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct NodeUnregistered {
+        #[ink(topic)]
+        p2p_id: String,
+        unlock_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct NodeStakeWithdrawn {
+        #[ink(topic)]
+        p2p_id: String,
+        #[ink(topic)]
+        operator: AccountId,
+        value: Balance,
+    }
+
+    impl Ddc {
+        /// As owner, set the minimum deposit of the nodes registered by their operators.
+        /// Self-registration is closed while it is 0.
+        #[ink(message)]
+        pub fn set_min_node_stake(&mut self, min_stake: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            self.min_node_stake = min_stake;
+            self.record_state_change(&("set_min_node_stake", min_stake));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_min_node_stake(&self) -> Balance {
+            self.min_node_stake
+        }
+
+        #[ink(message)]
+        pub fn node_stake_of(&self, p2p_id: String) -> Option<NodeStake> {
+            self.node_stakes.get(&p2p_id).cloned()
+        }
+
+        /// As operator of an untrusted DDC node, register it with the transferred deposit.
+        /// The operator can update its record and add to the deposit the same way.
+        #[ink(message, payable)]
+        pub fn register_ddc_node(
+            &mut self,
+            p2p_id: String,
+            p2p_addr: String,
+            url: String,
+        ) -> Result<()> {
+            self.only_active()?;
+            // Without a minimum stake, nodes could join for free
+            if self.min_node_stake == 0 {
+                return Err(Error::RegistrationClosed);
+            }
+            let operator = self.env().caller();
+            let value = self.env().transferred_balance();
+
+            let mut stake = match self.node_stakes.get(&p2p_id) {
+                Some(stake) if stake.operator == operator => stake.clone(),
+                Some(_) => return Err(Error::NodeAlreadyRegistered),
+                None if self.is_known_node(p2p_id.clone()) => {
+                    return Err(Error::NodeAlreadyRegistered)
+                }
+                None => NodeStake {
+                    operator,
+                    value: 0,
+                    unlock_ms: None,
+                },
+            };
+            stake.value += value;
+            if stake.value < self.min_node_stake {
+                return Err(Error::InsufficientDeposit);
+            }
+
+            self.insert_ddc_node(0, p2p_id.clone(), p2p_addr, url, 0, operator)?;
+            stake.unlock_ms = None;
+            self.node_stakes.insert(p2p_id.clone(), stake);
+            self.record_state_change(&("register_ddc_node", &p2p_id));
+            Self::env().emit_event(NodeStaked {
+                p2p_id,
+                operator,
+                value,
+            });
+
+            Ok(())
+        }
+
+        /// As operator, remove a self-registered node. The stake can be withdrawn after
+        /// NODE_UNSTAKE_COOLDOWN_MS.
+        #[ink(message)]
+        pub fn deregister_ddc_node(&mut self, p2p_id: String) -> Result<()> {
+            self.only_node_operator(p2p_id.clone())?;
+
+            self.delete_ddc_node(p2p_id)
+        }
+
+        /// As operator, announce that a self-registered node leaves. The node is not assigned
+        /// to clusters anymore, but stays listed and keeps its share of the rewards until its
+        /// stake is withdrawn, after NODE_UNSTAKE_COOLDOWN_MS.
+        #[ink(message)]
+        pub fn unregister_node(&mut self, p2p_id: String) -> Result<()> {
+            let stake = self.only_node_operator(p2p_id.clone())?;
+
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+            if stake.unlock_ms.is_some() {
+                return Err(Error::NodeLeaving);
+            }
+
+            self.unlock_node_stake(p2p_id.clone());
+            let unlock_ms = self.node_stakes.get(&p2p_id).unwrap().unlock_ms.unwrap();
+            self.record_state_change(&("unregister_node", &p2p_id));
+            Self::env().emit_event(NodeUnregistered { p2p_id, unlock_ms });
+
+            Ok(())
+        }
+
+        /// Whether a listed node has been unregistered and waits for the withdrawal of its stake
+        #[ink(message)]
+        pub fn is_node_leaving(&self, p2p_id: String) -> bool {
+            self.ddc_nodes.contains_key(&p2p_id)
+                && self
+                    .node_stakes
+                    .get(&p2p_id)
+                    .map_or(false, |stake| stake.unlock_ms.is_some())
+        }
+
+        /// As operator of a deregistered or unregistered node, withdraw its stake once the
+        /// cooldown has passed. An unregistered node is removed at this point.
+        #[ink(message)]
+        pub fn withdraw_node_stake(&mut self, p2p_id: String) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.withdraw_node_stake_at_time(p2p_id, now_ms)
+        }
+
+        pub fn withdraw_node_stake_at_time(&mut self, p2p_id: String, now_ms: u64) -> Result<()> {
+            let stake = self.only_node_operator(p2p_id.clone())?;
+
+            if stake.unlock_ms.map_or(true, |unlock_ms| now_ms < unlock_ms) {
+                return Err(Error::StakeLocked);
+            }
+            if self.ddc_nodes.contains_key(&p2p_id) {
+                self.delete_ddc_node(p2p_id.clone())?;
+            }
+
+            self.node_stakes.take(&p2p_id);
+            self.record_state_change(&("withdraw_node_stake", &p2p_id));
+            Self::env().emit_event(NodeStakeWithdrawn {
+                p2p_id,
+                operator: stake.operator,
+                value: stake.value,
+            });
+
+            match self.env().transfer(stake.operator, stake.value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+
+        /// As owner, slash the stake of a misbehaving node. The slashed funds stay in the
+        /// contract, and the same amount is withheld from the next rewards of the node.
+        /// A node left with less than the minimum stake is removed, and its stake unlocks
+        /// after the cooldown.
+        #[ink(message)]
+        pub fn slash_node_stake(&mut self, p2p_id: String, amount: Balance) -> Result<()> {
+            self.only_owner()?;
+
+            let mut stake = self
+                .node_stakes
+                .get(&p2p_id)
+                .cloned()
+                .ok_or(Error::DDNNotFound)?;
+            if amount > stake.value {
+                return Err(Error::InsufficientBalance);
+            }
+
+            stake.value -= amount;
+            let is_below_min = stake.value < self.min_node_stake;
+            self.node_stakes.insert(p2p_id.clone(), stake);
+            *self.node_penalties.entry(p2p_id.clone()).or_insert(0) += amount;
+            self.record_state_change(&("slash_node_stake", &p2p_id, amount));
+            Self::env().emit_event(NodeSlashed {
+                p2p_id: p2p_id.clone(),
+                value: amount,
+            });
+
+            let is_listed =
+                self.ddc_nodes.contains_key(&p2p_id) || self.queued_nodes.contains_key(&p2p_id);
+            if is_listed && is_below_min {
+                self.delete_ddc_node(p2p_id)?;
+            }
+
+            Ok(())
+        }
+
+        fn only_node_operator(&self, p2p_id: String) -> Result<NodeStake> {
+            match self.node_stakes.get(&p2p_id) {
+                Some(stake) if stake.operator == self.env().caller() => Ok(stake.clone()),
+                _ => Err(Error::NoPermission),
+            }
+        }
+
+        fn unlock_node_stake(&mut self, p2p_id: String) {
+            let now_ms = Self::env().block_timestamp();
+
+            if let Some(stake) = self.node_stakes.get_mut(&p2p_id) {
+                if stake.unlock_ms.is_none() {
+                    stake.unlock_ms = Some(now_ms + NODE_UNSTAKE_COOLDOWN_MS);
+                }
+            }
+        }
+    }
+
+    // ---- DDC node join requests ----
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct JoinRequest {
+        applicant: AccountId,
+        p2p_addr: String,
+        url: String,
+    }
+
+    #[ink(event)]
+    pub struct NodeJoinRequested {
+        #[ink(topic)]
+        p2p_id: String,
+        #[ink(topic)]
+        applicant: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct NodeApproved {
+        #[ink(topic)]
+        p2p_id: String,
+    }
+
+    #[ink(event)]
+    pub struct NodeRejected {
+        #[ink(topic)]
+        p2p_id: String,
+    }
+
+    impl Ddc {
+        /// Ask the owner to add an untrusted DDC node, see `approve_node`.
+        /// The applicant can update its pending request the same way.
+        #[ink(message)]
+        pub fn request_join(
+            &mut self,
+            p2p_id: String,
+            p2p_addr: String,
+            url: String,
+        ) -> Result<()> {
+            self.only_active()?;
+            let applicant = self.env().caller();
+
+            let is_new = match self.join_requests.get(&p2p_id) {
+                Some(request) if request.applicant == applicant => false,
+                Some(_) => return Err(Error::NodeAlreadyRegistered),
+                None if self.is_known_node(p2p_id.clone()) => {
+                    return Err(Error::NodeAlreadyRegistered)
+                }
+                None => true,
+            };
+
+            self.join_requests.insert(
+                p2p_id.clone(),
+                JoinRequest {
+                    applicant,
+                    p2p_addr,
+                    url,
+                },
+            );
+            if is_new {
+                self.track_storage_item(applicant);
+            }
+            self.record_state_change(&("request_join", &p2p_id));
+            Self::env().emit_event(NodeJoinRequested { p2p_id, applicant });
+
+            Ok(())
+        }
+
+        /// Return the pending join requests
+        #[ink(message)]
+        pub fn get_join_requests(&self) -> Vec<(String, JoinRequest)> {
+            self.join_requests
+                .iter()
+                .map(|(p2p_id, request)| (p2p_id.clone(), request.clone()))
+                .collect()
+        }
+
+        /// As owner, add the node of a join request, or queue it if the target number
+        /// of nodes is reached
+        #[ink(message)]
+        pub fn approve_node(&mut self, p2p_id: String) -> Result<()> {
+            self.only_owner()?;
+
+            let request = self
+                .join_requests
+                .get(&p2p_id)
+                .cloned()
+                .ok_or(Error::DDNNotFound)?;
+            self.insert_ddc_node(
+                0,
+                p2p_id.clone(),
+                request.p2p_addr,
+                request.url,
+                0,
+                request.applicant,
+            )?;
+
+            self.join_requests.take(&p2p_id);
+            self.release_storage_item(request.applicant, 0);
+            self.record_state_change(&("approve_node", &p2p_id));
+            Self::env().emit_event(NodeApproved { p2p_id });
+
+            Ok(())
+        }
+
+        /// As owner, discard a join request
+        #[ink(message)]
+        pub fn reject_node(&mut self, p2p_id: String) -> Result<()> {
+            self.only_owner()?;
+
+            let request = self.join_requests.take(&p2p_id).ok_or(Error::DDNNotFound)?;
+            self.release_storage_item(request.applicant, 0);
+            self.record_state_change(&("reject_node", &p2p_id));
+            Self::env().emit_event(NodeRejected { p2p_id });
+
+            Ok(())
+        }
+
+        /// Whether a node is added, queued, staked or waiting for approval
+        fn is_known_node(&self, p2p_id: String) -> bool {
+            self.ddc_nodes.contains_key(&p2p_id)
+                || self.node_stakes.contains_key(&p2p_id)
+                || self.join_requests.contains_key(&p2p_id)
+                || self.queued_nodes.contains_key(&p2p_id)
+        }
+    }
+
+    // ---- Clock skew ----
+
+    /// How to handle a time earlier than the last recorded one, beyond the skew tolerance
+    #[derive(Copy, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(Debug, scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum SkewPolicy {
+        /// Fail with `UnexpectedTimestamp`.
+        Reject,
+        /// Record the last time instead.
+        Clamp,
+    }
+
+    #[ink(event)]
+    pub struct SkewDetected {
+        #[ink(topic)]
+        reporter: AccountId,
+        skew_ms: u64,
+    }
+
+    impl Ddc {
+        /// As owner, configure the clock skew accepted from the reporters. A time up to
+        /// `tolerance_ms` earlier than the last recorded one is clamped to it, and the
+        /// policy decides for larger skews. The cutoffs of the reports are delayed by the
+        /// tolerance as well.
+        #[ink(message)]
+        pub fn set_clock_skew(&mut self, tolerance_ms: u64, policy: SkewPolicy) -> Result<()> {
+            self.only_owner()?;
+
+            self.clock_skew_tolerance_ms = tolerance_ms;
+            self.skew_policy = policy;
+            self.record_state_change(&("set_clock_skew", tolerance_ms, policy));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_clock_skew(&self) -> (u64, SkewPolicy) {
+            (self.clock_skew_tolerance_ms, self.skew_policy)
+        }
+
+        /// Return the time to record for a time reported after `last_ms`, see `set_clock_skew`
+        fn monotonic_time(&self, reporter: AccountId, now_ms: u64, last_ms: u64) -> Result<u64> {
+            if now_ms >= last_ms {
+                return Ok(now_ms);
+            }
+
+            self.enforce_skew_accepted(now_ms, last_ms)?;
+            let skew_ms = last_ms - now_ms;
+            Self::env().emit_event(SkewDetected { reporter, skew_ms });
+
+            Ok(last_ms)
+        }
+
+        /// Fail if a time behind the last one is rejected by the skew policy
+        fn enforce_skew_accepted(&self, now_ms: u64, last_ms: u64) -> Result<()> {
+            let skew_ms = last_ms.saturating_sub(now_ms);
+            if skew_ms > self.clock_skew_tolerance_ms && self.skew_policy == SkewPolicy::Reject {
+                return Err(Error::UnexpectedTimestamp);
+            }
+
+            Ok(())
+        }
+
+        /// Time used by the cutoffs of the reports, earlier by the skew tolerance
+        fn tolerant_now_ms(&self, now_ms: u64) -> u64 {
+            now_ms.saturating_sub(self.clock_skew_tolerance_ms)
+        }
+    }
+
+    // ---- DDN Statuses ----
+    #[derive(Default, Copy, Clone, PartialEq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct DDNStatus {
         is_online: bool,
         total_downtime: u64,
         reference_timestamp: u64,
         last_timestamp: u64,
     }
 
-    // ---- DDN Status Key ----
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct DDNStatusKey {
-        inspector: AccountId,
-        p2p_id: String,
+    // ---- DDN Status Key ----
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct DDNStatusKey {
+        inspector: AccountId,
+        p2p_id: String,
+    }
+
+    impl Ddc {
+        /// Update DDC node connectivity status (online/offline)
+        /// Called by OCW to set DDN offline status if fetching of node metrics failed
+        /// Called by SC to set online status when metrics is reported
+        #[ink(message, selector = "0x83fd8226")]
+        pub fn report_ddn_status(&mut self, p2p_id: String, is_online: bool) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.report_ddn_status_at_time(p2p_id, is_online, now_ms)
+        }
+
+        pub fn report_ddn_status_at_time(
+            &mut self,
+            p2p_id: String,
+            is_online: bool,
+            now_ms: u64,
+        ) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            self.set_ddn_status(inspector, p2p_id.clone(), is_online, now_ms)?;
+            self.credit_node_report_rebate(inspector, p2p_id, now_ms / MS_PER_DAY);
+
+            Ok(())
+        }
+
+        fn set_ddn_status(
+            &mut self,
+            inspector: AccountId,
+            p2p_id: String,
+            is_online: bool,
+            now_ms: u64,
+        ) -> Result<()> {
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+            if self.archived_nodes.contains_key(&p2p_id) {
+                return Err(Error::NodeArchived);
+            }
+
+            let key = DDNStatusKey { inspector, p2p_id };
+            let now = match self.ddn_statuses.get(&key) {
+                Some(status) => self.monotonic_time(
+                    inspector,
+                    now_ms,
+                    status.last_timestamp.max(status.reference_timestamp),
+                )?,
+                None => now_ms,
+            };
+
+            // Add new DDN status if not exists
+            if !self.ddn_statuses.contains_key(&key) {
+                let new_ddn_status = DDNStatus {
+                    is_online,
+                    total_downtime: 0,
+                    reference_timestamp: now,
+                    last_timestamp: now,
+                };
+                self.ddn_statuses.insert(key.clone(), new_ddn_status);
+            }
+
+            let ddn_status = self.ddn_statuses.get_mut(&key).unwrap();
+            let was_offline = !ddn_status.is_online;
+            let last_timestamp = ddn_status.last_timestamp;
+
+            // Update total downlime
+            if was_offline {
+                let last_downtime = now - ddn_status.last_timestamp;
+                ddn_status.total_downtime += last_downtime;
+            }
+
+            ddn_status.is_online = is_online;
+            ddn_status.last_timestamp = now;
+            if was_offline {
+                self.book_ddn_downtime(inspector, key.p2p_id.clone(), last_timestamp, now);
+            }
+            self.record_state_change(&("report_ddn_status", key));
+            self.ops_stats_mut().status_updates += 1;
+
+            Ok(())
+        }
+
+        /// Get DDC node status, aggregated over the inspectors: the median total downtime,
+        /// and online unless a majority of the inspectors report it offline
+        #[ink(message, selector = "0x42d0c283")]
+        pub fn get_ddn_status(&self, p2p_id: String) -> Result<DDNStatus> {
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+
+            let mut ddn_statuses: Vec<&DDNStatus> = Vec::new();
+
+            // Collect DDN statuses from all inspectors
+            for &inspector in self.inspectors.keys() {
+                if self.is_inspector_suspended(inspector) {
+                    continue;
+                }
+                let key = DDNStatusKey {
+                    inspector,
+                    p2p_id: p2p_id.clone(),
+                };
+
+                if let Some(ddn_status) = self.ddn_statuses.get(&key) {
+                    ddn_statuses.push(ddn_status);
+                }
+            }
+
+            let offline_count = ddn_statuses
+                .iter()
+                .filter(|status| !status.is_online)
+                .count();
+            let is_online = offline_count * 2 <= ddn_statuses.len();
+
+            // Get DDN status by using median value of total downtime
+            let mut ddn_status = get_median_by_key(ddn_statuses, |item| item.total_downtime)
+                .cloned()
+                .ok_or(Error::DDNNoStatus)?;
+            ddn_status.is_online = is_online;
+
+            Ok(ddn_status)
+        }
+    }
+
+    // ---- Metrics Reporting ----
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct MetricKey {
+        inspector: AccountId,
+        app_id: AccountId,
+        day_of_period: u64,
+    }
+
+    // ---- Metric per DDN ----
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct MetricKeyDDN {
+        inspector: AccountId,
+        p2p_id: String,
+        day_of_period: u64,
+    }
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct MetricValue {
+        start_ms: u64,
+        storage_bytes: u64,
+        wcu_used: u64,
+        rcu_used: u64,
+    }
+
+    impl MetricValue {
+        /// Usage saturates instead of overflowing, past any limit of a tier.
+        pub fn add_assign(&mut self, other: Self) {
+            self.storage_bytes = self.storage_bytes.saturating_add(other.storage_bytes);
+            self.wcu_used = self.wcu_used.saturating_add(other.wcu_used);
+            self.rcu_used = self.rcu_used.saturating_add(other.rcu_used);
+        }
+    }
+
+    /// How to account for a day without any report from the inspectors
+    #[derive(Copy, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(Debug, scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum NoReportPolicy {
+        /// The day counts as no usage.
+        TreatAsZero,
+        /// The day counts as the last reported day of the period.
+        CarryForward,
+        /// The period cannot be billed on usage until all its days are reported.
+        MarkUnbillable,
+    }
+
+    /// Median of the metrics reported by the inspectors for a day, or their correction
+    #[derive(Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct DayAggregate {
+        metrics: MetricValue,
+        inspectors_count: u32,
+    }
+
+    #[ink(event)]
+    pub struct MetricReported {
+        #[ink(topic)]
+        inspector: AccountId,
+        #[ink(topic)]
+        key: MetricKey,
+        metrics: MetricValue,
+    }
+
+    #[ink(event)]
+    pub struct MetricDDNReported {
+        #[ink(topic)]
+        inspector: AccountId,
+        #[ink(topic)]
+        key: MetricKeyDDN,
+        metrics: MetricValue,
+    }
+
+    #[ink(event)]
+    pub struct MetricsCorrected {
+        #[ink(topic)]
+        inspector: AccountId,
+        #[ink(topic)]
+        key: MetricKey,
+        metrics: MetricValue,
+    }
+
+    /// Maximum number of reports in a batch.
+    const MAX_REPORT_BATCH: usize = 100;
+
+    /// Report of an app, or of a DDC node: id, day_start_ms, storage_bytes, wcu_used, rcu_used.
+    pub type AppReport = (AccountId, u64, u64, u64, u64);
+    pub type DDNReport = (String, u64, u64, u64, u64);
+
+    #[ink(event)]
+    pub struct MetricsBatchReported {
+        #[ink(topic)]
+        inspector: AccountId,
+        /// Number of reports written, identical resubmissions excluded.
+        reports: u32,
+    }
+
+    #[ink(event)]
+    pub struct MetricsDDNBatchReported {
+        #[ink(topic)]
+        inspector: AccountId,
+        reports: u32,
+    }
+
+    #[ink(event)]
+    pub struct MetricPeriodFinalized {
+        #[ink(topic)]
+        inspector: AccountId,
+        start_ms: u64,
+    }
+
+    /// Get median value from a vector
+    fn get_median<T: Clone + Ord>(mut source: Vec<T>) -> Option<T> {
+        let length = source.len();
+        // sort_unstable is faster, it doesn't preserve the order of equal elements
+        source.sort_unstable();
+        let index_correction = length != 0 && length % 2 == 0;
+        let median_index = length / 2 - index_correction as usize;
+        source.get(median_index).cloned()
+    }
+
+    /// Get the median of weighted values: the lowest value such that the values up to it
+    /// have at least half of the total weight. With equal weights, same as `get_median`.
+    fn get_weighted_median(mut source: Vec<(u64, u64)>) -> Option<u64> {
+        source.sort_unstable();
+        let total_weight: u128 = source.iter().map(|(_, weight)| *weight as u128).sum();
+
+        let mut cumulated_weight = 0_u128;
+        for (value, weight) in source {
+            cumulated_weight += weight as u128;
+            if cumulated_weight * 2 >= total_weight {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Get median value from a vector of structs by key
+    fn get_median_by_key<T, F, K>(mut source: Vec<T>, f: F) -> Option<T>
+    where
+        T: Clone,
+        F: FnMut(&T) -> K,
+        K: Ord,
+    {
+        let length = source.len();
+        // sort_unstable is faster, it doesn't preserve the order of equal elements
+        source.sort_unstable_by_key(f);
+        let index_correction = length != 0 && length % 2 == 0;
+        let median_index = length / 2 - index_correction as usize;
+        source.get(median_index).cloned()
+    }
+
+    /// Whether the usage of an app is within the limit of its tier, per dimension
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct LimitCheck {
+        storage_ok: bool,
+        wcu_ok: bool,
+        rcu_ok: bool,
+    }
+
+    impl Ddc {
+        /// Compare the usage of an app in its current period with the limit of its tier,
+        /// so that gateways can throttle each dimension separately.
+        #[ink(message)]
+        pub fn check_limits(&self, app_id: AccountId) -> Result<LimitCheck> {
+            let now_ms = Self::env().block_timestamp();
+            self.check_limits_at_time(app_id, now_ms)
+        }
+
+        pub fn check_limits_at_time(&self, app_id: AccountId, now_ms: u64) -> Result<LimitCheck> {
+            let limit = self.get_app_limit_at_time(app_id, now_ms)?;
+            // Apps on a free trial have no subscription date, their periods start at 0
+            let start_ms = self
+                .subscriptions
+                .get(&app_id)
+                .map_or(0, |subscription| subscription.start_date_ms);
+            let metrics = self.metrics_for_period(app_id, start_ms, now_ms);
+
+            Ok(LimitCheck {
+                storage_ok: metrics.storage_bytes <= limit.storage_bytes,
+                wcu_ok: metrics.wcu_used <= limit.wcu_per_minute,
+                rcu_ok: metrics.rcu_used <= limit.rcu_per_minute,
+            })
+        }
+    }
+
+    impl Ddc {
+        #[ink(message, selector = "0xa7e90f2c")]
+        pub fn metrics_since_subscription(&self, app_id: AccountId) -> Result<MetricValue> {
+            let subscription = self
+                .subscriptions
+                .get(&app_id)
+                .ok_or(Error::NoSubscription)?;
+
+            let now_ms = Self::env().block_timestamp() as u64;
+            let metrics = self.metrics_for_period(app_id, subscription.start_date_ms, now_ms);
+
+            Ok(metrics)
+        }
+
+        #[ink(message, selector = "0x0f49f085")]
+        pub fn metrics_for_period(
+            &self,
+            app_id: AccountId,
+            subscription_start_ms: u64,
+            now_ms: u64,
+        ) -> MetricValue {
+            // The start date may be several months away. When did the current period start?
+            let (period_start_days, now_days) =
+                get_current_period_days(subscription_start_ms, now_ms, self.period_days);
+
+            let mut period_metrics = MetricValue {
+                start_ms: period_start_days * MS_PER_DAY,
+                storage_bytes: 0,
+                wcu_used: 0,
+                rcu_used: 0,
+            };
+
+            let mut last_reported_metrics: Option<MetricValue> = None;
+
+            for day in period_start_days..=now_days {
+                let aggregate = self.app_day_aggregate(app_id, day);
+
+                if aggregate.inspectors_count == 0 {
+                    if self.no_report_policy == NoReportPolicy::CarryForward {
+                        if let Some(last_reported_metrics) = &last_reported_metrics {
+                            period_metrics.add_assign(last_reported_metrics.clone());
+                        }
+                    }
+                    continue;
+                }
+
+                last_reported_metrics = Some(aggregate.metrics.clone());
+                period_metrics.add_assign(aggregate.metrics);
+            }
+
+            period_metrics
+        }
+
+        /// Check whether the usage of an app in the current period can be billed.
+        ///
+        /// With the MarkUnbillable policy, the period is not billable if no inspector reported
+        /// on any of its completed days. It is always billable with the other policies.
+        /// Settlement does not charge the apps while their period is not billable.
+        #[ink(message)]
+        pub fn is_period_billable(
+            &self,
+            app_id: AccountId,
+            subscription_start_ms: u64,
+            now_ms: u64,
+        ) -> bool {
+            if self.no_report_policy != NoReportPolicy::MarkUnbillable {
+                return true;
+            }
+
+            let (period_start_days, now_days) =
+                get_current_period_days(subscription_start_ms, now_ms, self.period_days);
+
+            self.is_billable_in_days(app_id, period_start_days, now_days)
+        }
+
+        /// Whether the usage of an app in a range of days can be billed, see `is_period_billable`
+        fn is_billable_in_days(&self, app_id: AccountId, first_day: u64, end_day: u64) -> bool {
+            self.no_report_policy != NoReportPolicy::MarkUnbillable
+                || (first_day..end_day)
+                    .all(|day| self.app_day_aggregate(app_id, day).inspectors_count != 0)
+        }
+
+        /// Set how the days without any report are billed
+        #[ink(message)]
+        pub fn set_no_report_policy(&mut self, policy: NoReportPolicy) -> Result<()> {
+            self.only_owner()?;
+
+            self.no_report_policy = policy;
+            self.record_state_change(&("set_no_report_policy", policy));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_no_report_policy(&self) -> NoReportPolicy {
+            self.no_report_policy
+        }
+
+        /// Return the current median of the metrics reported for an app on a day, along with
+        /// the number of inspectors who reported them. The result may change until the day
+        /// is finalized by the inspectors.
+        #[ink(message)]
+        pub fn preview_day_aggregate(
+            &self,
+            app_id: AccountId,
+            day_start_ms: u64,
+        ) -> Result<DayAggregate> {
+            enforce_time_is_start_of_day(day_start_ms)?;
+            let day = day_start_ms / MS_PER_DAY;
+
+            Ok(self.app_day_aggregate(app_id, day))
+        }
+
+        /// Aggregate the metrics of an app on a day, preferring the correction of a dispute
+        /// over the median of the reports
+        fn app_day_aggregate(&self, app_id: AccountId, day: u64) -> DayAggregate {
+            let mut aggregate = self.reported_day_aggregate(app_id, day);
+            match self.corrected_metrics.get(&(app_id, day % PERIOD_DAYS)) {
+                // Ignore out-of-date corrections from a previous period
+                Some(corrected) if corrected.start_ms == day * MS_PER_DAY => {
+                    aggregate.metrics = corrected.clone();
+                }
+                _ => {}
+            }
+
+            aggregate
+        }
+
+        /// Aggregate the metrics reported by all inspectors for an app on a day
+        fn reported_day_aggregate(&self, app_id: AccountId, day: u64) -> DayAggregate {
+            let mut day_storage_bytes: Vec<(u64, u64)> = Vec::new();
+            let mut day_wcu_used: Vec<(u64, u64)> = Vec::new();
+            let mut day_rcu_used: Vec<(u64, u64)> = Vec::new();
+
+            for inspector in self.inspectors.keys() {
+                if self.is_inspector_suspended(*inspector) {
+                    continue;
+                }
+                let inspector_day_metric = self.metrics_for_day(*inspector, app_id, day);
+                if let Some(inspector_day_metric) = inspector_day_metric {
+                    let weight = self.inspector_weight_on_day(*inspector, day);
+                    day_storage_bytes.push((inspector_day_metric.storage_bytes, weight));
+                    day_wcu_used.push((inspector_day_metric.wcu_used, weight));
+                    day_rcu_used.push((inspector_day_metric.rcu_used, weight));
+                }
+            }
+
+            DayAggregate {
+                inspectors_count: day_storage_bytes.len() as u32,
+                metrics: MetricValue {
+                    start_ms: day * MS_PER_DAY,
+                    storage_bytes: get_weighted_median(day_storage_bytes).unwrap_or(0),
+                    wcu_used: get_weighted_median(day_wcu_used).unwrap_or(0),
+                    rcu_used: get_weighted_median(day_rcu_used).unwrap_or(0),
+                },
+            }
+        }
+
+        fn metrics_for_day(
+            &self,
+            inspector: AccountId,
+            app_id: AccountId,
+            day: u64,
+        ) -> Option<&MetricValue> {
+            let day_of_period = day % PERIOD_DAYS;
+            let day_key = MetricKey {
+                inspector,
+                app_id,
+                day_of_period,
+            };
+
+            self.metrics.get(&day_key).and_then(|day_metrics| {
+                // Ignore out-of-date metrics from a previous period
+                if day_metrics.start_ms != day * MS_PER_DAY {
+                    None
+                } else {
+                    Some(day_metrics)
+                }
+            })
+        }
+
+        #[ink(message, selector = "0xe8e28257")]
+        pub fn metrics_for_ddn(&self, p2p_id: String) -> Vec<MetricValue> {
+            let now_ms = Self::env().block_timestamp() as u64;
+            self.metrics_for_ddn_at_time(p2p_id, now_ms)
+        }
+
+        pub fn metrics_for_ddn_at_time(&self, p2p_id: String, now_ms: u64) -> Vec<MetricValue> {
+            let period_days = self.period_days;
+            let mut period_metrics: Vec<MetricValue> = Vec::with_capacity(period_days as usize);
+
+            let last_day = now_ms / MS_PER_DAY + 1; // non-inclusive.
+            let first_day = if last_day >= period_days {
+                last_day - period_days
+            } else {
+                0
+            };
+
+            for day in first_day..last_day {
+                let summary = self
+                    .ddn_day_summaries
+                    .get(&(p2p_id.clone(), day % PERIOD_DAYS))
+                    // Ignore out-of-date summaries from a previous period
+                    .filter(|summary| summary.start_ms == day * MS_PER_DAY)
+                    .cloned();
+
+                period_metrics
+                    .push(summary.unwrap_or_else(|| self.ddn_day_median(p2p_id.clone(), day)));
+            }
+
+            period_metrics
+        }
+
+        /// Return the stored median of the metrics of a DDC node on a finalized day, if any
+        #[ink(message)]
+        pub fn get_ddn_day_summary(
+            &self,
+            p2p_id: String,
+            day_start_ms: u64,
+        ) -> Option<MetricValue> {
+            let day = day_start_ms / MS_PER_DAY;
+
+            self.ddn_day_summaries
+                .get(&(p2p_id, day % PERIOD_DAYS))
+                .filter(|summary| summary.start_ms == day * MS_PER_DAY)
+                .cloned()
+        }
+
+        fn ddn_day_median(&self, p2p_id: String, day: u64) -> MetricValue {
+            let mut day_storage_bytes: Vec<u64> = Vec::new();
+            let mut day_wcu_used: Vec<u64> = Vec::new();
+            let mut day_rcu_used: Vec<u64> = Vec::new();
+
+            for inspector in self.inspectors.keys() {
+                if self.is_inspector_suspended(*inspector) {
+                    continue;
+                }
+                let day_metric = self.metrics_for_ddn_day(*inspector, p2p_id.clone(), day);
+
+                if let Some(day_metric) = day_metric {
+                    day_storage_bytes.push(day_metric.storage_bytes);
+                    day_wcu_used.push(day_metric.wcu_used);
+                    day_rcu_used.push(day_metric.rcu_used);
+                }
+            }
+
+            MetricValue {
+                storage_bytes: get_median(day_storage_bytes).unwrap_or(0),
+                wcu_used: get_median(day_wcu_used).unwrap_or(0),
+                rcu_used: get_median(day_rcu_used).unwrap_or(0),
+                start_ms: day * MS_PER_DAY,
+            }
+        }
+
+        /// Store the medians of all DDC nodes for a day, so that `metrics_for_ddn` does not
+        /// recompute them
+        fn summarize_ddn_day(&mut self, day: u64) {
+            let p2p_ids: Vec<String> = self.ddc_nodes.keys().cloned().collect();
+
+            for p2p_id in p2p_ids {
+                let summary = self.ddn_day_median(p2p_id.clone(), day);
+                self.ddn_day_summaries
+                    .insert((p2p_id, day % PERIOD_DAYS), summary);
+            }
+        }
+
+        fn metrics_for_ddn_day(
+            &self,
+            inspector: AccountId,
+            p2p_id: String,
+            day: u64,
+        ) -> Option<MetricValue> {
+            let day_of_period = day % PERIOD_DAYS;
+            let day_key = MetricKeyDDN {
+                inspector,
+                p2p_id,
+                day_of_period,
+            };
+
+            self.metrics_ddn
+                .get(&day_key)
+                .and_then(|metric| {
+                    // Ignore out-of-date metrics from a previous period
+                    if metric.start_ms != day * MS_PER_DAY {
+                        None
+                    } else {
+                        Some(metric)
+                    }
+                })
+                .cloned()
+        }
+
+        #[ink(message, selector = "0x35320bbe")]
+        pub fn report_metrics(
+            &mut self,
+            app_id: AccountId,
+            day_start_ms: u64,
+            storage_bytes: u64,
+            wcu_used: u64,
+            rcu_used: u64,
+        ) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            let report = (app_id, day_start_ms, storage_bytes, wcu_used, rcu_used);
+
+            self.report_metrics_at_time(report, now_ms)
+        }
+
+        pub fn report_metrics_at_time(&mut self, report: AppReport, now_ms: u64) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            let (key, metrics) = match self.prepare_report(inspector, report, now_ms)? {
+                Some(prepared) => prepared,
+                None => return Ok(()),
+            };
+            self.write_report(key.clone(), metrics.clone());
+
+            self.env().emit_event(MetricReported {
+                inspector,
+                key,
+                metrics,
+            });
+
+            Ok(())
+        }
+
+        /// Report the metrics of several apps in one transaction, see `report_metrics`.
+        /// Nothing is written if any of the reports is invalid.
+        #[ink(message)]
+        pub fn report_metrics_batch(&mut self, reports: Vec<AppReport>) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            if reports.len() > MAX_REPORT_BATCH {
+                return Err(Error::BatchTooLarge);
+            }
+
+            let now_ms = Self::env().block_timestamp();
+            let mut prepared = Vec::with_capacity(reports.len());
+            for report in reports {
+                if let Some(entry) = self.prepare_report(inspector, report, now_ms)? {
+                    prepared.push(entry);
+                }
+            }
+
+            let count = prepared.len() as u32;
+            for (key, metrics) in prepared {
+                self.write_report(key, metrics);
+            }
+
+            self.env().emit_event(MetricsBatchReported {
+                inspector,
+                reports: count,
+            });
+
+            Ok(())
+        }
+
+        /// Check that a report of an app can be stored, and return the entry to write,
+        /// or None for an identical resubmission (e.g. an OCW retry)
+        fn prepare_report(
+            &self,
+            inspector: AccountId,
+            report: AppReport,
+            now_ms: u64,
+        ) -> Result<Option<(MetricKey, MetricValue)>> {
+            let (app_id, day_start_ms, storage_bytes, wcu_used, rcu_used) = report;
+
+            enforce_time_is_start_of_day(day_start_ms)?;
+            if self.archived_apps.contains_key(&app_id) {
+                return Err(Error::AppArchived);
+            }
+            let day = day_start_ms / MS_PER_DAY;
+            if let Some(window_days) = self.report_window_days {
+                if day + window_days <= self.tolerant_now_ms(now_ms) / MS_PER_DAY {
+                    return Err(Error::LateReport);
+                }
+            }
+            let day_of_period = day % PERIOD_DAYS;
+
+            let key = MetricKey {
+                inspector,
+                app_id,
+                day_of_period,
+            };
+            let metrics = MetricValue {
+                start_ms: day_start_ms,
+                storage_bytes,
+                wcu_used,
+                rcu_used,
+            };
+
+            if self.metrics.get(&key) == Some(&metrics) {
+                return Ok(None);
+            }
+
+            enforce_not_stale(
+                day_start_ms,
+                self.tolerant_now_ms(now_ms),
+                self.metrics.get(&key),
+            )?;
+
+            Ok(Some((key, metrics)))
+        }
+
+        fn write_report(&mut self, key: MetricKey, metrics: MetricValue) {
+            let inspector = key.inspector;
+            let app_id = key.app_id;
+            let day = metrics.start_ms / MS_PER_DAY;
+
+            self.record_state_change(&("report_metrics", &key));
+            self.ops_stats_mut().reports += 1;
+            if self.metrics.insert(key, metrics).is_none() {
+                self.track_storage_item(inspector);
+                self.count_app_metrics(app_id, true);
+            }
+            self.index_reported_app(app_id, day);
+            self.credit_app_report_rebate(inspector, app_id, day);
+        }
+
+        /// Add an app to the apps reported on a day, to be scored by `finalize_day`
+        fn index_reported_app(&mut self, app_id: AccountId, day: u64) {
+            let mut apps = match self.reported_apps.get(&(day % PERIOD_DAYS)) {
+                // Ignore out-of-date apps from a previous period
+                Some((reported_day, apps)) if *reported_day == day => apps.clone(),
+                _ => Vec::new(),
+            };
+            if !apps.contains(&app_id) {
+                apps.push(app_id);
+                self.reported_apps.insert(day % PERIOD_DAYS, (day, apps));
+            }
+        }
+
+        /// As owner, set the number of days, up to today, in which inspectors can report
+        /// apps, or None for no limit. Older days can only be fixed with `correct_metrics`.
+        #[ink(message)]
+        pub fn set_report_window(&mut self, window_days: Option<u64>) -> Result<()> {
+            self.only_owner()?;
+
+            if window_days == Some(0) {
+                return Err(Error::InvalidPeriod);
+            }
+
+            self.report_window_days = window_days;
+            self.record_state_change(&("set_report_window", window_days));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_report_window(&self) -> Option<u64> {
+            self.report_window_days
+        }
+
+        /// As owner, replace the metrics reported by an inspector for an app, regardless of
+        /// the report window. For exceptional fixes only.
+        #[ink(message)]
+        pub fn correct_metrics(
+            &mut self,
+            inspector: AccountId,
+            app_id: AccountId,
+            day_start_ms: u64,
+            storage_bytes: u64,
+            wcu_used: u64,
+            rcu_used: u64,
+        ) -> Result<()> {
+            self.only_owner()?;
+
+            enforce_time_is_start_of_day(day_start_ms)?;
+            let key = MetricKey {
+                inspector,
+                app_id,
+                day_of_period: day_start_ms / MS_PER_DAY % PERIOD_DAYS,
+            };
+            let metrics = MetricValue {
+                start_ms: day_start_ms,
+                storage_bytes,
+                wcu_used,
+                rcu_used,
+            };
+
+            let now_ms = self.tolerant_now_ms(Self::env().block_timestamp());
+            enforce_not_stale(day_start_ms, now_ms, self.metrics.get(&key))?;
+
+            self.record_state_change(&("correct_metrics", &key));
+            if self.metrics.insert(key.clone(), metrics.clone()).is_none() {
+                self.track_storage_item(inspector);
+                self.count_app_metrics(app_id, true);
+            }
+            self.index_reported_app(app_id, day_start_ms / MS_PER_DAY);
+            self.env().emit_event(MetricsCorrected {
+                inspector,
+                key,
+                metrics,
+            });
+
+            Ok(())
+        }
+
+        /// Reports DDC node metrics
+        /// Called by OCW if node metrics is successfully fetched
+        /// Updates DDC node connectivity status to online
+        #[ink(message, selector = "0xde028ad8")]
+        pub fn report_metrics_ddn(
+            &mut self,
+            p2p_id: String,
+            day_start_ms: u64,
+            storage_bytes: u64,
+            wcu_used: u64,
+            rcu_used: u64,
+        ) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            let now_ms = Self::env().block_timestamp();
+            let report = (p2p_id, day_start_ms, storage_bytes, wcu_used, rcu_used);
+            let (key, metrics) = self.prepare_ddn_report(inspector, report, now_ms)?;
+            self.write_ddn_report(key.clone(), metrics.clone(), now_ms)?;
+
+            self.env().emit_event(MetricDDNReported {
+                inspector,
+                key,
+                metrics,
+            });
+
+            Ok(())
+        }
+
+        /// Report the metrics of several DDC nodes in one transaction, see `report_metrics_ddn`.
+        /// Nothing is written if any of the reports is invalid.
+        #[ink(message)]
+        pub fn report_metrics_ddn_batch(&mut self, reports: Vec<DDNReport>) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            if reports.len() > MAX_REPORT_BATCH {
+                return Err(Error::BatchTooLarge);
+            }
+
+            let now_ms = Self::env().block_timestamp();
+            let mut prepared = Vec::with_capacity(reports.len());
+            for report in reports {
+                prepared.push(self.prepare_ddn_report(inspector, report, now_ms)?);
+            }
+
+            let count = prepared.len() as u32;
+            for (key, metrics) in prepared {
+                self.write_ddn_report(key, metrics, now_ms)?;
+            }
+
+            self.env().emit_event(MetricsDDNBatchReported {
+                inspector,
+                reports: count,
+            });
+
+            Ok(())
+        }
+
+        fn prepare_ddn_report(
+            &self,
+            inspector: AccountId,
+            report: DDNReport,
+            now_ms: u64,
+        ) -> Result<(MetricKeyDDN, MetricValue)> {
+            let (p2p_id, day_start_ms, storage_bytes, wcu_used, rcu_used) = report;
+
+            enforce_time_is_start_of_day(day_start_ms)?;
+            // The report sets the status of the node, checked here to write all or nothing
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+            if self.archived_nodes.contains_key(&p2p_id) {
+                return Err(Error::NodeArchived);
+            }
+            let status_key = DDNStatusKey {
+                inspector,
+                p2p_id: p2p_id.clone(),
+            };
+            if let Some(status) = self.ddn_statuses.get(&status_key) {
+                let last_ms = status.last_timestamp.max(status.reference_timestamp);
+                self.enforce_skew_accepted(now_ms, last_ms)?;
+            }
+            let day = day_start_ms / MS_PER_DAY;
+            let day_of_period = day % PERIOD_DAYS;
+
+            let key = MetricKeyDDN {
+                inspector,
+                p2p_id,
+                day_of_period,
+            };
+            let metrics = MetricValue {
+                start_ms: day_start_ms,
+                storage_bytes,
+                wcu_used,
+                rcu_used,
+            };
+
+            enforce_not_stale(
+                day_start_ms,
+                self.tolerant_now_ms(now_ms),
+                self.metrics_ddn.get(&key),
+            )?;
+
+            Ok((key, metrics))
+        }
+
+        fn write_ddn_report(
+            &mut self,
+            key: MetricKeyDDN,
+            metrics: MetricValue,
+            now_ms: u64,
+        ) -> Result<()> {
+            let inspector = key.inspector;
+            let p2p_id = key.p2p_id.clone();
+            let day = metrics.start_ms / MS_PER_DAY;
+
+            self.record_state_change(&("report_metrics_ddn", &key));
+            self.ops_stats_mut().reports += 1;
+            // A late report changes the median of a day which may already be summarized
+            self.ddn_day_summaries
+                .take(&(p2p_id.clone(), key.day_of_period));
+            if self.metrics_ddn.insert(key, metrics).is_none() {
+                self.track_storage_item(inspector);
+                self.count_node_metrics(p2p_id.clone(), true);
+            }
+
+            self.set_ddn_status(inspector, p2p_id.clone(), true, now_ms)?;
+            self.credit_node_report_rebate(inspector, p2p_id, day);
+
+            Ok(())
+        }
+
+        #[ink(message, selector = "0xb269d557")]
+        pub fn finalize_metric_period(&mut self, start_ms: u64) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            enforce_time_is_start_of_day(start_ms)?;
+            let next_period_ms = start_ms + MS_PER_DAY;
+            self.current_period_ms.insert(inspector, next_period_ms);
+            self.summarize_ddn_day(start_ms / MS_PER_DAY);
+            self.record_state_change(&("finalize_metric_period", inspector));
+
+            self.env().emit_event(MetricPeriodFinalized {
+                inspector,
+                start_ms,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message, selector = "0xace4ecb3")]
+        pub fn get_current_period_ms(&self) -> u64 {
+            let caller = self.env().caller();
+            self.get_current_period_ms_of(caller)
+        }
+
+        #[ink(message, selector = "0x85391e52")]
+        pub fn get_current_period_ms_of(&self, inspector_id: AccountId) -> u64 {
+            let current_period_ms = self.current_period_ms.get(&inspector_id);
+            match current_period_ms {
+                None => {
+                    let now: u64 = Self::env().block_timestamp(); // Epoch in milisecond
+                    let today_ms = now - now % MS_PER_DAY; // The beginning of today
+                    today_ms
+                }
+                Some(current_period_ms) => *current_period_ms,
+            }
+        }
+    }
+
+    // ---- Inspector coverage ----
+
+    impl Ddc {
+        /// As owner, set the number of distinct inspectors expected to report each app
+        /// in a period, see `low_coverage_apps`
+        #[ink(message)]
+        pub fn set_coverage_quorum(&mut self, quorum: u32) -> Result<()> {
+            self.only_owner()?;
+
+            self.coverage_quorum = quorum;
+            self.record_state_change(&("set_coverage_quorum", quorum));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_coverage_quorum(&self) -> u32 {
+            self.coverage_quorum
+        }
+
+        /// Return the number of distinct inspectors who reported the app at least once
+        /// in the period starting at `period_start_ms`
+        #[ink(message)]
+        pub fn coverage(&self, app_id: AccountId, period_start_ms: u64) -> Result<u32> {
+            enforce_time_is_start_of_day(period_start_ms)?;
+            let first_day = period_start_ms / MS_PER_DAY;
+
+            Ok(self.coverage_in_days(app_id, first_day, first_day + self.period_days))
+        }
+
+        /// Return up to `limit` apps with an active subscription reported by fewer inspectors
+        /// than the quorum in their current period so far, with their coverage
+        #[ink(message)]
+        pub fn low_coverage_apps(&self, limit: u32) -> Vec<(AccountId, u32)> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.low_coverage_apps_at_time(limit, now_ms)
+        }
+
+        pub fn low_coverage_apps_at_time(&self, limit: u32, now_ms: u64) -> Vec<(AccountId, u32)> {
+            self.subscriptions
+                .iter()
+                .filter(|(_, subscription)| self.get_end_date_ms(subscription) >= now_ms)
+                .map(|(app_id, subscription)| {
+                    let (period_start_days, now_days) = get_current_period_days(
+                        subscription.start_date_ms,
+                        now_ms,
+                        self.period_days,
+                    );
+                    let coverage = self.coverage_in_days(*app_id, period_start_days, now_days + 1);
+                    (*app_id, coverage)
+                })
+                .filter(|(_, coverage)| *coverage < self.coverage_quorum)
+                .take(limit.min(MAX_PAGE_LIMIT) as usize)
+                .collect()
+        }
+
+        fn coverage_in_days(&self, app_id: AccountId, first_day: u64, end_day: u64) -> u32 {
+            self.inspectors
+                .keys()
+                .filter(|inspector| {
+                    (first_day..end_day)
+                        .any(|day| self.metrics_for_day(**inspector, app_id, day).is_some())
+                })
+                .count() as u32
+        }
+    }
+
+    // ---- Metrics per bucket ----
+
+    /// Logical bucket of an app, defined by the gateways
+    pub type BucketId = u64;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct MetricKeyBucket {
+        inspector: AccountId,
+        app_id: AccountId,
+        bucket_id: BucketId,
+        day_of_period: u64,
+    }
+
+    #[ink(event)]
+    pub struct MetricBucketReported {
+        #[ink(topic)]
+        inspector: AccountId,
+        #[ink(topic)]
+        key: MetricKeyBucket,
+        metrics: MetricValue,
+    }
+
+    impl Ddc {
+        /// Report the usage of a bucket of an app on a day, see `report_metrics`
+        #[ink(message)]
+        pub fn report_metrics_bucket(
+            &mut self,
+            app_id: AccountId,
+            bucket_id: BucketId,
+            day_start_ms: u64,
+            storage_bytes: u64,
+            wcu_used: u64,
+            rcu_used: u64,
+        ) -> Result<()> {
+            let inspector = self.env().caller();
+            self.only_inspector()?;
+
+            enforce_time_is_start_of_day(day_start_ms)?;
+            let day = day_start_ms / MS_PER_DAY;
+            let day_of_period = day % PERIOD_DAYS;
+
+            let key = MetricKeyBucket {
+                inspector,
+                app_id,
+                bucket_id,
+                day_of_period,
+            };
+            let metrics = MetricValue {
+                start_ms: day_start_ms,
+                storage_bytes,
+                wcu_used,
+                rcu_used,
+            };
+
+            if self.metrics_bucket.get(&key) == Some(&metrics) {
+                return Ok(());
+            }
+
+            let now_ms = self.tolerant_now_ms(Self::env().block_timestamp());
+            enforce_not_stale(day_start_ms, now_ms, self.metrics_bucket.get(&key))?;
+
+            self.record_state_change(&("report_metrics_bucket", &key));
+            self.ops_stats_mut().reports += 1;
+            if self
+                .metrics_bucket
+                .insert(key.clone(), metrics.clone())
+                .is_none()
+            {
+                self.track_storage_item(inspector);
+            }
+            let mut buckets = self
+                .reported_buckets
+                .get(&app_id)
+                .cloned()
+                .unwrap_or_default();
+            if !buckets.contains(&bucket_id) {
+                buckets.push(bucket_id);
+                self.reported_buckets.insert(app_id, buckets);
+            }
+            self.credit_app_report_rebate(inspector, app_id, day_start_ms / MS_PER_DAY);
+
+            self.env().emit_event(MetricBucketReported {
+                inspector,
+                key,
+                metrics,
+            });
+
+            Ok(())
+        }
+
+        /// Return the usage of a bucket in the current period of the subscription,
+        /// like `metrics_for_period`. Days without reports count as no usage.
+        #[ink(message)]
+        pub fn metrics_for_bucket_period(
+            &self,
+            app_id: AccountId,
+            bucket_id: BucketId,
+            subscription_start_ms: u64,
+            now_ms: u64,
+        ) -> MetricValue {
+            let (period_start_days, now_days) =
+                get_current_period_days(subscription_start_ms, now_ms, self.period_days);
+
+            let mut period_metrics = MetricValue {
+                start_ms: period_start_days * MS_PER_DAY,
+                storage_bytes: 0,
+                wcu_used: 0,
+                rcu_used: 0,
+            };
+
+            for day in period_start_days..=now_days {
+                let mut day_storage_bytes: Vec<(u64, u64)> = Vec::new();
+                let mut day_wcu_used: Vec<(u64, u64)> = Vec::new();
+                let mut day_rcu_used: Vec<(u64, u64)> = Vec::new();
+
+                for inspector in self.inspectors.keys() {
+                    if self.is_inspector_suspended(*inspector) {
+                        continue;
+                    }
+                    let key = MetricKeyBucket {
+                        inspector: *inspector,
+                        app_id,
+                        bucket_id,
+                        day_of_period: day % PERIOD_DAYS,
+                    };
+                    match self.metrics_bucket.get(&key) {
+                        // Ignore out-of-date metrics from a previous period
+                        Some(metric) if metric.start_ms == day * MS_PER_DAY => {
+                            let weight = self.inspector_weight_on_day(*inspector, day);
+                            day_storage_bytes.push((metric.storage_bytes, weight));
+                            day_wcu_used.push((metric.wcu_used, weight));
+                            day_rcu_used.push((metric.rcu_used, weight));
+                        }
+                        _ => {}
+                    }
+                }
+
+                period_metrics.add_assign(MetricValue {
+                    start_ms: day * MS_PER_DAY,
+                    storage_bytes: get_weighted_median(day_storage_bytes).unwrap_or(0),
+                    wcu_used: get_weighted_median(day_wcu_used).unwrap_or(0),
+                    rcu_used: get_weighted_median(day_rcu_used).unwrap_or(0),
+                });
+            }
+
+            period_metrics
+        }
+    }
+
+    // ---- App attestations ----
+
+    #[ink(event)]
+    pub struct UsageAttested {
+        #[ink(topic)]
+        app_id: AccountId,
+        day_start_ms: u64,
+    }
+
+    impl Ddc {
+        /// As an app, co-sign the usage figures of one of its days. The day is attested
+        /// if they match the median of the inspectors within the tolerance.
+        #[ink(message)]
+        pub fn attest_usage(
+            &mut self,
+            day_start_ms: u64,
+            storage_bytes: u64,
+            wcu_used: u64,
+            rcu_used: u64,
+        ) -> Result<()> {
+            let app_id = self.env().caller();
+            enforce_time_is_start_of_day(day_start_ms)?;
+
+            if !self.subscriptions.contains_key(&app_id) {
+                return Err(Error::NoSubscription);
+            }
+
+            let day_of_period = day_start_ms / MS_PER_DAY % PERIOD_DAYS;
+            let attestation = MetricValue {
+                start_ms: day_start_ms,
+                storage_bytes,
+                wcu_used,
+                rcu_used,
+            };
+            if self
+                .app_attestations
+                .insert((app_id, day_of_period), attestation)
+                .is_none()
+            {
+                self.track_storage_item(app_id);
+            }
+            self.record_state_change(&("attest_usage", app_id, day_start_ms));
+            Self::env().emit_event(UsageAttested {
+                app_id,
+                day_start_ms,
+            });
+
+            Ok(())
+        }
+
+        /// As owner, set the maximum relative difference, in basis points, between
+        /// the attestation of an app and the median of the inspectors.
+        #[ink(message)]
+        pub fn set_attestation_tolerance(&mut self, tolerance_bps: u32) -> Result<()> {
+            self.only_owner()?;
+
+            if tolerance_bps > BPS {
+                return Err(Error::InvalidShare);
+            }
+
+            self.attestation_tolerance_bps = tolerance_bps;
+            self.record_state_change(&("set_attestation_tolerance", tolerance_bps));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_attestation_tolerance(&self) -> u32 {
+            self.attestation_tolerance_bps
+        }
+
+        /// Return whether the app attested its usage on a day and the inspectors agree with it.
+        #[ink(message)]
+        pub fn is_day_attested(&self, app_id: AccountId, day_start_ms: u64) -> Result<bool> {
+            enforce_time_is_start_of_day(day_start_ms)?;
+            let day = day_start_ms / MS_PER_DAY;
+
+            let attestation = match self.app_attestations.get(&(app_id, day % PERIOD_DAYS)) {
+                // Ignore out-of-date attestations from a previous period
+                Some(attestation) if attestation.start_ms == day_start_ms => attestation,
+                _ => return Ok(false),
+            };
+
+            let aggregate = self.app_day_aggregate(app_id, day);
+            if aggregate.inspectors_count == 0 {
+                return Ok(false);
+            }
+
+            let median = aggregate.metrics;
+            let tolerance = self.attestation_tolerance_bps;
+            Ok(
+                is_within_tolerance(attestation.storage_bytes, median.storage_bytes, tolerance)
+                    && is_within_tolerance(attestation.wcu_used, median.wcu_used, tolerance)
+                    && is_within_tolerance(attestation.rcu_used, median.rcu_used, tolerance),
+            )
+        }
+    }
+
+    /// Whether `value` differs from `reference` by at most `tolerance_bps` of `reference`
+    fn is_within_tolerance(value: u64, reference: u64, tolerance_bps: u32) -> bool {
+        let diff = if value > reference {
+            value - reference
+        } else {
+            reference - value
+        };
+
+        diff as u128 * BPS as u128 <= reference as u128 * tolerance_bps as u128
+    }
+
+    // ---- Period exports ----
+
+    #[ink(event)]
+    pub struct PeriodExportCommitted {
+        #[ink(topic)]
+        period_start_ms: u64,
+        cid: String,
+    }
+
+    impl Ddc {
+        /// As owner, record the content hash of the full off-chain usage export of an ended period.
+        /// A commitment cannot be changed afterwards.
+        #[ink(message)]
+        pub fn commit_period_export(&mut self, period_start_ms: u64, cid: String) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.commit_period_export_at_time(period_start_ms, cid, now_ms)
+        }
+
+        pub fn commit_period_export_at_time(
+            &mut self,
+            period_start_ms: u64,
+            cid: String,
+            now_ms: u64,
+        ) -> Result<()> {
+            self.only_owner()?;
+            enforce_time_is_start_of_day(period_start_ms)?;
+
+            if period_start_ms + self.period_ms() > now_ms {
+                return Err(Error::PeriodNotEnded);
+            }
+            if self.period_exports.contains_key(&period_start_ms) {
+                return Err(Error::ExportAlreadyCommitted);
+            }
+
+            // Record the apps whose usage in the period cannot be billed, from their subscription
+            let first_day = period_start_ms / MS_PER_DAY;
+            let end_day = first_day + self.period_days;
+            let unbillable_apps: Vec<AccountId> = self
+                .subscriptions
+                .iter()
+                .filter(|(app, subscription)| {
+                    let start_day = first_day.max(subscription.start_date_ms / MS_PER_DAY);
+                    !self.is_billable_in_days(**app, start_day, end_day)
+                })
+                .map(|(app, _)| *app)
+                .collect();
+
+            self.period_exports.insert(period_start_ms, cid.clone());
+            self.period_unbillable_apps
+                .insert(period_start_ms, unbillable_apps);
+            self.record_state_change(&("commit_period_export", period_start_ms, &cid));
+            Self::env().emit_event(PeriodExportCommitted {
+                period_start_ms,
+                cid,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_period_export(&self, period_start_ms: u64) -> Option<String> {
+            self.period_exports.get(&period_start_ms).cloned()
+        }
+
+        /// Return the apps whose usage in the period of a committed export cannot be billed,
+        /// under the MarkUnbillable policy
+        #[ink(message)]
+        pub fn get_period_unbillable_apps(&self, period_start_ms: u64) -> Vec<AccountId> {
+            self.period_unbillable_apps
+                .get(&period_start_ms)
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+
+    // ---- Payout eligibility ----
+
+    #[derive(Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct PayoutEligibility {
+        /// Start of each day of the period with metrics of the node, from at least one inspector.
+        /// Missing days accrue nothing.
+        reported_days: Vec<u64>,
+        required_days: u64,
+        eligible: bool,
+    }
+
+    impl Ddc {
+        /// As owner, require nodes to have metrics for at least `days` days of a period
+        /// to be eligible for the payouts of that period.
+        #[ink(message)]
+        pub fn set_min_reported_days(&mut self, days: u64) -> Result<()> {
+            self.only_owner()?;
+
+            if days > self.period_days {
+                return Err(Error::TooManyDays);
+            }
+
+            self.min_reported_days = days;
+            self.record_state_change(&("set_min_reported_days", days));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_min_reported_days(&self) -> u64 {
+            self.min_reported_days
+        }
+
+        /// Return the days for which a node has metrics in the period starting at
+        /// `period_start_ms`, and whether this is enough to be eligible for payouts.
+        #[ink(message)]
+        pub fn payout_eligibility(
+            &self,
+            p2p_id: String,
+            period_start_ms: u64,
+        ) -> Result<PayoutEligibility> {
+            enforce_time_is_start_of_day(period_start_ms)?;
+
+            let first_day = period_start_ms / MS_PER_DAY;
+            let reported_days: Vec<u64> = (first_day..first_day + self.period_days)
+                .filter(|day| {
+                    self.inspectors.keys().any(|inspector| {
+                        self.metrics_for_ddn_day(*inspector, p2p_id.clone(), *day)
+                            .is_some()
+                    })
+                })
+                .map(|day| day * MS_PER_DAY)
+                .collect();
+
+            Ok(PayoutEligibility {
+                eligible: reported_days.len() as u64 >= self.min_reported_days,
+                reported_days,
+                required_days: self.min_reported_days,
+            })
+        }
+    }
+
+    // ---- Node rewards ----
+
+    /// How the revenue of a period is shared among the nodes
+    #[derive(Copy, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(
+        feature = "std",
+        derive(Debug, scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum RewardWeighting {
+        /// In proportion to the uptime of the nodes.
+        Uptime,
+        /// In proportion to the work of the nodes, the average of their shares of the
+        /// storage, WCU and RCU reported in the period.
+        Usage,
+    }
+
+    /// Fixed-point unit of the usage shares.
+    const USAGE_SHARE_UNIT: u128 = 1_000_000_000;
+
+    /// Book revenue earned evenly over a time range in the days it spans
+    fn book_revenue(
+        revenues: &mut StorageHashMap<u64, Balance>,
+        from_ms: u64,
+        to_ms: u64,
+        value: Balance,
+    ) {
+        if value == 0 {
+            return;
+        }
+        if to_ms <= from_ms {
+            *revenues.entry(to_ms / MS_PER_DAY).or_insert(0) += value;
+            return;
+        }
+
+        let duration = (to_ms - from_ms) as u128;
+        let mut booked = 0;
+        let mut day_start_ms = from_ms - from_ms % MS_PER_DAY;
+        while day_start_ms < to_ms {
+            let day_end_ms = to_ms.min(day_start_ms + MS_PER_DAY);
+            // Cumulated, so that the rounding does not lose anything
+            let booked_until = value * (day_end_ms - from_ms) as u128 / duration;
+            *revenues.entry(day_start_ms / MS_PER_DAY).or_insert(0) += booked_until - booked;
+            booked = booked_until;
+            day_start_ms += MS_PER_DAY;
+        }
+    }
+
+    /// How the reward of a node for a period was computed
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct PayoutStatement {
+        /// Share of the work of the rewarded nodes, in USAGE_SHARE_UNIT.
+        usage_share: u128,
+        /// Uptime of the node over the period, in basis points.
+        sla_bps: u32,
+        /// Share of the revenue of the period, see `set_reward_weighting`.
+        gross: Balance,
+        /// Slashes of the stake of the node withheld from the reward.
+        penalties: Balance,
+        /// Reward credited.
+        net: Balance,
+    }
+
+    #[ink(event)]
+    pub struct NodeRewardAccrued {
+        #[ink(topic)]
+        p2p_id: String,
+        #[ink(topic)]
+        account: AccountId,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct NodeRewardsDistributed {
+        #[ink(topic)]
+        period_start_ms: u64,
+        value: Balance,
+    }
+
+    #[ink(event)]
+    pub struct NodeRewardsClaimed {
+        #[ink(topic)]
+        account: AccountId,
+        value: Balance,
+    }
+
+    impl Ddc {
+        /// As owner, settle the subscriptions and distribute the revenue earned in a past
+        /// period to the nodes eligible for its payouts, see `set_reward_weighting`.
+        /// After the first one, the periods are rewarded in order, and their revenue is
+        /// not forwarded by `settle_revenue` until then.
+        ///
+        /// The rewards of a node are credited to its payout address, or to its operator.
+        #[ink(message)]
+        pub fn distribute_node_rewards(&mut self, period_start_ms: u64) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.distribute_node_rewards_at_time(period_start_ms, now_ms)
+        }
+
+        pub fn distribute_node_rewards_at_time(
+            &mut self,
+            period_start_ms: u64,
+            now_ms: u64,
+        ) -> Result<()> {
+            self.only_owner()?;
+            enforce_time_is_start_of_day(period_start_ms)?;
+            let period_ms = self.period_ms();
+
+            if now_ms < period_start_ms + period_ms {
+                return Err(Error::PeriodNotEnded);
+            }
+            match self.next_reward_period_ms {
+                Some(next_ms) if period_start_ms < next_ms => {
+                    return Err(Error::PeriodAlreadyRewarded)
+                }
+                Some(next_ms) if period_start_ms > next_ms => return Err(Error::PeriodNotNext),
+                _ => {}
+            }
+            let first_day = period_start_ms / MS_PER_DAY;
+            let end_day = first_day + self.period_days;
+
+            let mut uptimes: Vec<(String, u128)> = Vec::new();
+            for p2p_id in self.ddc_nodes.keys() {
+                if self.archived_nodes.contains_key(p2p_id) {
+                    continue;
+                }
+                // Never reported, not eligible
+                if self.get_ddn_status(p2p_id.clone()).is_err() {
+                    continue;
+                }
+                if !self
+                    .payout_eligibility(p2p_id.clone(), period_start_ms)?
+                    .eligible
+                {
+                    continue;
+                }
+                let downtime = self.ddn_downtime_in_days(p2p_id.clone(), first_day, end_day);
+                let uptime = period_ms.saturating_sub(downtime);
+                uptimes.push((p2p_id.clone(), uptime as u128));
+            }
+
+            let p2p_ids = uptimes.iter().map(|(p2p_id, _)| p2p_id.clone()).collect();
+            let usages = self.usage_weights(p2p_ids, period_start_ms);
+            let weights = match self.reward_weighting {
+                RewardWeighting::Uptime => uptimes.clone(),
+                RewardWeighting::Usage => usages.clone(),
+            };
+            let total_weight: u128 = weights.iter().map(|(_, weight)| weight).sum();
+            if total_weight == 0 {
+                return Err(Error::NoEligibleNodes);
+            }
+
+            self.actualize_subscriptions_at_time(now_ms)?;
+            // Consumed free credit was never received, hence the cap
+            let value = self
+                .revenue_in_days(first_day, end_day)
+                .min(self.get_unforwarded_revenue());
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+
+            let total_usage: u128 = usages.iter().map(|(_, usage)| usage).sum();
+            let mut distributed = 0;
+            for (((p2p_id, weight), (_, uptime)), (_, usage)) in
+                weights.into_iter().zip(uptimes).zip(usages)
+            {
+                let gross = value * weight / total_weight;
+                // Penalties exceeding the rewards are withheld from the next ones
+                let pending_penalties = self.node_penalties.take(&p2p_id).unwrap_or(0);
+                let penalties = pending_penalties.min(gross);
+                if pending_penalties > penalties {
+                    self.node_penalties
+                        .insert(p2p_id.clone(), pending_penalties - penalties);
+                }
+                let net = gross - penalties;
+
+                self.payout_statements.insert(
+                    (p2p_id.clone(), period_start_ms),
+                    PayoutStatement {
+                        usage_share: if total_usage == 0 {
+                            0
+                        } else {
+                            usage * USAGE_SHARE_UNIT / total_usage
+                        },
+                        sla_bps: (uptime * BPS as u128 / period_ms as u128) as u32,
+                        gross,
+                        penalties,
+                        net,
+                    },
+                );
+                self.credit_node_reward(p2p_id, net);
+                distributed += net;
+            }
+            self.record_cluster_sla_reports(period_start_ms, first_day, end_day);
+
+            let rewarded_days: Vec<u64> = self
+                .daily_revenues
+                .keys()
+                .filter(|day| **day < end_day)
+                .cloned()
+                .collect();
+            for day in rewarded_days {
+                self.daily_revenues.take(&day);
+            }
+            self.next_reward_period_ms = Some(period_start_ms + period_ms);
+            self.revenue_forwarded += distributed;
+            self.ops_stats_mut().settlements += 1;
+            self.record_state_change(&("distribute_node_rewards", period_start_ms, distributed));
+            Self::env().emit_event(NodeRewardsDistributed {
+                period_start_ms,
+                value: distributed,
+            });
+
+            Ok(())
+        }
+
+        /// Return how the reward of a node for a rewarded period was computed
+        #[ink(message)]
+        pub fn payout_statement(
+            &self,
+            p2p_id: String,
+            period_start_ms: u64,
+        ) -> Option<PayoutStatement> {
+            self.payout_statements
+                .get(&(p2p_id, period_start_ms))
+                .cloned()
+        }
+
+        /// Return the start of the next period whose revenue is to be distributed to the
+        /// nodes, None before the first distribution
+        #[ink(message)]
+        pub fn get_next_reward_period(&self) -> Option<u64> {
+            self.next_reward_period_ms
+        }
+
+        /// Revenue earned in the days [first_day, end_day) and not distributed yet
+        fn revenue_in_days(&self, first_day: u64, end_day: u64) -> Balance {
+            (first_day..end_day)
+                .map(|day| *self.daily_revenues.get(&day).unwrap_or(&0))
+                .sum()
+        }
+
+        /// Revenue earned since the last distribution to the nodes, kept for the next ones.
+        /// Nothing is kept before the first distribution.
+        fn reserved_node_revenue(&self) -> Balance {
+            match self.next_reward_period_ms {
+                Some(_) => self.daily_revenues.values().sum(),
+                None => 0,
+            }
+        }
+
+        /// Downtime of a node in the days [first_day, end_day), the median over the
+        /// inspectors reporting its status
+        fn ddn_downtime_in_days(&self, p2p_id: String, first_day: u64, end_day: u64) -> u64 {
+            let downtimes: Vec<u64> = self
+                .inspectors
+                .keys()
+                .filter(|inspector| {
+                    !self.is_inspector_suspended(**inspector)
+                        && self.ddn_statuses.contains_key(&DDNStatusKey {
+                            inspector: **inspector,
+                            p2p_id: p2p_id.clone(),
+                        })
+                })
+                .map(|inspector| {
+                    (first_day..end_day)
+                        .map(|day| {
+                            match self.ddn_day_downtimes.get(&(
+                                *inspector,
+                                p2p_id.clone(),
+                                day % PERIOD_DAYS,
+                            )) {
+                                Some((booked_day, downtime)) if *booked_day == day => *downtime,
+                                _ => 0,
+                            }
+                        })
+                        .sum()
+                })
+                .collect();
+
+            get_median(downtimes).unwrap_or(0)
+        }
+
+        /// Book a downtime of a node reported by an inspector in the days it spans.
+        /// Only the last PERIOD_DAYS days are kept.
+        fn book_ddn_downtime(
+            &mut self,
+            inspector: AccountId,
+            p2p_id: String,
+            from_ms: u64,
+            to_ms: u64,
+        ) {
+            let from_ms = from_ms.max(to_ms.saturating_sub(PERIOD_MS));
+            let mut day_start_ms = from_ms - from_ms % MS_PER_DAY;
+
+            while day_start_ms < to_ms {
+                let day = day_start_ms / MS_PER_DAY;
+                let downtime = to_ms.min(day_start_ms + MS_PER_DAY) - from_ms.max(day_start_ms);
+                let key = (inspector, p2p_id.clone(), day % PERIOD_DAYS);
+                let booked = match self.ddn_day_downtimes.get(&key) {
+                    Some((booked_day, booked)) if *booked_day == day => *booked,
+                    _ => 0,
+                };
+                self.ddn_day_downtimes.insert(key, (day, booked + downtime));
+                day_start_ms += MS_PER_DAY;
+            }
+        }
+
+        /// As owner, choose how the revenue is shared among the nodes, by uptime by default
+        #[ink(message)]
+        pub fn set_reward_weighting(&mut self, weighting: RewardWeighting) -> Result<()> {
+            self.only_owner()?;
+
+            self.reward_weighting = weighting;
+            self.record_state_change(&("set_reward_weighting", weighting));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_reward_weighting(&self) -> RewardWeighting {
+            self.reward_weighting
+        }
+
+        /// Weight each node by the sum of its shares of the storage, WCU and RCU, from the
+        /// daily medians of the period
+        fn usage_weights(&self, p2p_ids: Vec<String>, period_start_ms: u64) -> Vec<(String, u128)> {
+            let first_day = period_start_ms / MS_PER_DAY;
+            let usages: Vec<(String, MetricValue)> = p2p_ids
+                .into_iter()
+                .map(|p2p_id| {
+                    let mut usage = MetricValue::default();
+                    for day in first_day..first_day + self.period_days {
+                        usage.add_assign(self.ddn_day_median(p2p_id.clone(), day));
+                    }
+                    (p2p_id, usage)
+                })
+                .collect();
+
+            let total = usages
+                .iter()
+                .fold(MetricValue::default(), |mut total, (_, usage)| {
+                    total.add_assign(usage.clone());
+                    total
+                });
+            let share = |value: u64, total: u64| {
+                if total == 0 {
+                    0
+                } else {
+                    value as u128 * USAGE_SHARE_UNIT / total as u128
+                }
+            };
+
+            usages
+                .into_iter()
+                .map(|(p2p_id, usage)| {
+                    let weight = share(usage.storage_bytes, total.storage_bytes)
+                        + share(usage.wcu_used, total.wcu_used)
+                        + share(usage.rcu_used, total.rcu_used);
+                    (p2p_id, weight)
+                })
+                .collect()
+        }
+
+        /// Return the rewards an account can claim as payout address or operator of nodes
+        #[ink(message)]
+        pub fn node_rewards_of(&self, account: AccountId) -> Balance {
+            *self.node_rewards.get(&account).unwrap_or(&0)
+        }
+
+        /// Transfer the node rewards of the caller
+        #[ink(message)]
+        pub fn claim_node_rewards(&mut self) -> Result<()> {
+            let account = self.env().caller();
+            let value = self.node_rewards.take(&account).unwrap_or(0);
+            if value == 0 {
+                return Err(Error::ZeroBalance);
+            }
+            self.record_state_change(&("claim_node_rewards", account));
+            Self::env().emit_event(NodeRewardsClaimed { account, value });
+
+            match self.env().transfer(account, value) {
+                Err(_e) => panic!("Transfer has failed!"),
+                Ok(_) => Ok(()),
+            }
+        }
+
+        fn credit_node_reward(&mut self, p2p_id: String, value: Balance) {
+            let operator = self.ddc_nodes.get(&p2p_id).unwrap().operator;
+            let beneficiary = self
+                .get_node_payout_address(p2p_id.clone())
+                .unwrap_or(operator);
+
+            *self.node_rewards.entry(beneficiary).or_insert(0) += value;
+            Self::env().emit_event(NodeRewardAccrued {
+                p2p_id,
+                account: beneficiary,
+                value,
+            });
+        }
+    }
+
+    // ---- Archive ----
+
+    /// Time during which the records of archived apps and nodes stay queryable before
+    /// they can be pruned, one period of metrics.
+    const ARCHIVE_RETENTION_MS: u64 = PERIOD_MS;
+
+    #[ink(event)]
+    pub struct AppArchived {
+        #[ink(topic)]
+        app: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct DDCNodeArchived {
+        #[ink(topic)]
+        p2p_id: String,
+    }
+
+    #[ink(event)]
+    pub struct AppPruned {
+        #[ink(topic)]
+        app: AccountId,
+        refunded: Balance,
+    }
+
+    impl Ddc {
+        /// As owner, freeze a retired app: it is charged until now, then it can neither
+        /// subscribe nor be reported on, and its records stay queryable until pruned.
+        #[ink(message)]
+        pub fn archive_app(&mut self, app: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.archive_app_at_time(app, now_ms)
+        }
+
+        pub fn archive_app_at_time(&mut self, app: AccountId, now_ms: u64) -> Result<()> {
+            self.only_owner()?;
+            if self.archived_apps.contains_key(&app) {
+                return Err(Error::AppArchived);
+            }
+            self.apply_scheduled_fee_of(app, now_ms)?;
+            let period_ms = self.period_ms();
+            let subscription = self
+                .subscriptions
+                .get_mut(&app)
+                .ok_or(Error::NoSubscription)?;
+            let subscription_tier = self
+                .service_tiers
+                .get(&subscription.tier_id)
+                .ok_or(Error::TidOutOfBound)?;
+
+            self.total_ddc_balance += Self::actualize_subscription_at_time(
+                now_ms,
+                subscription,
+                subscription_tier,
+                self.rounding_policy,
+                period_ms,
+                &mut self.daily_revenues,
+            )?;
+            self.archived_apps.insert(app, now_ms);
+            self.record_state_change(&("archive_app", app));
+
+            Self::env().emit_event(AppArchived { app });
+
+            Ok(())
+        }
+
+        /// As DDN manager, freeze a retired node: it gets no status, metrics or rewards
+        /// anymore, and its records stay queryable until pruned.
+        #[ink(message)]
+        pub fn archive_ddc_node(&mut self, p2p_id: String) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.archive_ddc_node_at_time(p2p_id, now_ms)
+        }
+
+        pub fn archive_ddc_node_at_time(&mut self, p2p_id: String, now_ms: u64) -> Result<()> {
+            self.only_ddn_manager()?;
+            if !self.ddc_nodes.contains_key(&p2p_id) {
+                return Err(Error::DDNNotFound);
+            }
+            if self.archived_nodes.contains_key(&p2p_id) {
+                return Err(Error::NodeArchived);
+            }
+
+            self.archived_nodes.insert(p2p_id.clone(), now_ms);
+            self.record_state_change(&("archive_ddc_node", &p2p_id));
+
+            Self::env().emit_event(DDCNodeArchived { p2p_id });
+
+            Ok(())
+        }
+
+        /// Return the time at which an app was archived, if it is
+        #[ink(message)]
+        pub fn get_app_archived_ms(&self, app: AccountId) -> Option<u64> {
+            self.archived_apps.get(&app).cloned()
+        }
+
+        /// Return the time at which a DDC node was archived, if it is
+        #[ink(message)]
+        pub fn get_ddc_node_archived_ms(&self, p2p_id: String) -> Option<u64> {
+            self.archived_nodes.get(&p2p_id).cloned()
+        }
+
+        /// After the retention period, remove an archived app with its subscription and
+        /// metrics. Its remaining balance is refunded.
+        #[ink(message)]
+        pub fn prune_archived_app(&mut self, app: AccountId) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.prune_archived_app_at_time(app, now_ms)
+        }
+
+        pub fn prune_archived_app_at_time(&mut self, app: AccountId, now_ms: u64) -> Result<()> {
+            self.only_owner()?;
+            let archived_ms = *self.archived_apps.get(&app).ok_or(Error::NotArchived)?;
+            if now_ms < archived_ms + ARCHIVE_RETENTION_MS {
+                return Err(Error::RetentionNotEnded);
+            }
+            // The arbiters close the disputes first, which may correct the metrics
+            if self.app_disputes.contains_key(&app) {
+                return Err(Error::DisputeExists);
+            }
+
+            self.archived_apps.take(&app);
+            self.count_subscriber(app, None);
+            let balance = match self.subscriptions.take(&app) {
+                Some(subscription) => {
+                    self.orphan_app_metrics(app, true);
+                    subscription.balance
+                }
+                None => 0,
+            };
+            let refunded = balance - self.withhold_non_refundable(app, balance);
+            self.release_storage_item(app, 0);
+            let inspectors: Vec<AccountId> = self.inspectors.keys().cloned().collect();
+            for &inspector in inspectors.iter() {
+                for day_of_period in 0..PERIOD_DAYS {
+                    let key = MetricKey {
+                        inspector,
+                        app_id: app,
+                        day_of_period,
+                    };
+                    if self.metrics.take(&key).is_some() {
+                        self.release_storage_item(inspector, 0);
+                        self.count_app_metrics(app, false);
+                    }
+                }
+            }
+            for bucket_id in self.reported_buckets.take(&app).unwrap_or_default() {
+                for &inspector in inspectors.iter() {
+                    for day_of_period in 0..PERIOD_DAYS {
+                        let key = MetricKeyBucket {
+                            inspector,
+                            app_id: app,
+                            bucket_id,
+                            day_of_period,
+                        };
+                        if self.metrics_bucket.take(&key).is_some() {
+                            self.release_storage_item(inspector, 0);
+                        }
+                    }
+                }
+            }
+            for day_of_period in 0..PERIOD_DAYS {
+                self.corrected_metrics.take(&(app, day_of_period));
+                if self.app_attestations.take(&(app, day_of_period)).is_some() {
+                    self.release_storage_item(app, 0);
+                }
+            }
+            self.record_state_change(&("prune_archived_app", app));
+            self.return_balance(app, refunded);
+            self.app_payers.take(&app);
+            // After the refund, which goes to the refund address
+            self.refund_addresses.take(&app);
+
+            Self::env().emit_event(AppPruned { app, refunded });
+
+            Ok(())
+        }
+
+        /// After the retention period, remove an archived DDC node with all its records
+        #[ink(message)]
+        pub fn prune_archived_ddc_node(&mut self, p2p_id: String) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+
+            self.prune_archived_ddc_node_at_time(p2p_id, now_ms)
+        }
+
+        pub fn prune_archived_ddc_node_at_time(
+            &mut self,
+            p2p_id: String,
+            now_ms: u64,
+        ) -> Result<()> {
+            self.only_ddn_manager()?;
+            let archived_ms = *self.archived_nodes.get(&p2p_id).ok_or(Error::NotArchived)?;
+            if now_ms < archived_ms + ARCHIVE_RETENTION_MS {
+                return Err(Error::RetentionNotEnded);
+            }
+
+            let inspectors: Vec<AccountId> = self.inspectors.keys().cloned().collect();
+            for inspector in inspectors {
+                for day_of_period in 0..PERIOD_DAYS {
+                    let key = MetricKeyDDN {
+                        inspector,
+                        p2p_id: p2p_id.clone(),
+                        day_of_period,
+                    };
+                    if self.metrics_ddn.take(&key).is_some() {
+                        self.count_node_metrics(p2p_id.clone(), false);
+                    }
+                    self.ddn_day_downtimes
+                        .take(&(inspector, p2p_id.clone(), day_of_period));
+                }
+            }
+
+            self.delete_ddc_node(p2p_id)
+        }
+    }
+
+    // ---- Day status ----
+
+    /// Progress of a day through the metrics and settlement pipeline
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum DayStatus {
+        /// Fewer inspectors than the coverage quorum reported metrics for the day.
+        Open,
+        /// Enough inspectors reported metrics for the day.
+        QuorumReached,
+        /// Metrics of the day are disputed by apps, and may still be corrected.
+        Disputed,
+        /// Enough inspectors finalized the day, its metrics should not change anymore.
+        Finalized,
+        /// The revenue of the day was distributed to the nodes, or precedes the first
+        /// distribution.
+        Settled,
     }
 
     impl Ddc {
-        /// Update DDC node connectivity status (online/offline)
-        /// Called by OCW to set DDN offline status if fetching of node metrics failed
-        /// Called by SC to set online status when metrics is reported
+        /// Return the status of a day, the same for the OCW, the nodes and the apps
         #[ink(message)]
-        pub fn report_ddn_status(&mut self, p2p_id: String, is_online: bool) -> Result<()> {
-            let inspector = self.env().caller();
-            self.only_inspector()?;
+        pub fn day_status(&self, day_start_ms: u64) -> Result<DayStatus> {
+            enforce_time_is_start_of_day(day_start_ms)?;
+            let quorum = self.coverage_quorum.max(1) as usize;
 
-            if !self.ddc_nodes.contains_key(&p2p_id) {
-                return Err(Error::DDNNotFound);
+            let is_settled = self
+                .next_reward_period_ms
+                .map_or(false, |next_ms| day_start_ms < next_ms);
+            if is_settled {
+                return Ok(DayStatus::Settled);
+            }
+            let day = day_start_ms / MS_PER_DAY;
+            if self.day_disputes.contains_key(&day) {
+                return Ok(DayStatus::Disputed);
             }
 
-            let now = Self::env().block_timestamp();
-            let key = DDNStatusKey { inspector, p2p_id };
+            let finalized_count = self
+                .inspectors
+                .keys()
+                .filter(|inspector| {
+                    self.current_period_ms
+                        .get(inspector)
+                        .map_or(false, |next_day_ms| *next_day_ms > day_start_ms)
+                })
+                .count();
+            if finalized_count >= quorum {
+                return Ok(DayStatus::Finalized);
+            }
 
-            // Add new DDN status if not exists
-            if !self.ddn_statuses.contains_key(&key) {
-                let new_ddn_status = DDNStatus {
-                    is_online,
-                    total_downtime: 0,
-                    reference_timestamp: now,
-                    last_timestamp: now,
-                };
-                self.ddn_statuses.insert(key.clone(), new_ddn_status);
+            let apps = match self.reported_apps.get(&(day % PERIOD_DAYS)) {
+                Some((reported_day, apps)) if *reported_day == day => apps.as_slice(),
+                _ => &[],
+            };
+            let reporters = self
+                .inspectors
+                .keys()
+                .filter(|inspector| {
+                    apps.iter()
+                        .any(|app_id| self.metrics_for_day(**inspector, *app_id, day).is_some())
+                })
+                .count();
+
+            if reporters >= quorum {
+                Ok(DayStatus::QuorumReached)
+            } else {
+                Ok(DayStatus::Open)
             }
+        }
+    }
 
-            let ddn_status = self.ddn_statuses.get_mut(&key).unwrap();
+    // ---- Metric disputes ----
+
+    /// Time after a day in which its metrics can be disputed, and after a dispute in which
+    /// the metrics can be corrected.
+    const DISPUTE_WINDOW_MS: u64 = 7 * MS_PER_DAY;
+
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct Dispute {
+        /// Hash of the reason of the dispute, kept off-chain.
+        reason_hash: Hash,
+        opened_ms: u64,
+    }
+
+    #[ink(event)]
+    pub struct MetricDisputed {
+        #[ink(topic)]
+        app: AccountId,
+        day_start_ms: u64,
+        reason_hash: Hash,
+    }
+
+    #[ink(event)]
+    pub struct MetricCorrected {
+        #[ink(topic)]
+        app: AccountId,
+        day_start_ms: u64,
+        metrics: MetricValue,
+    }
+
+    impl Ddc {
+        /// As an app, dispute the metrics reported for one of its days, up to
+        /// `DISPUTE_WINDOW_MS` after the day.
+        #[ink(message)]
+        pub fn dispute_metric(&mut self, day_start_ms: u64, reason_hash: Hash) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            self.dispute_metric_at_time(day_start_ms, reason_hash, now_ms)
+        }
 
-            if now < ddn_status.last_timestamp || now < ddn_status.reference_timestamp {
+        pub fn dispute_metric_at_time(
+            &mut self,
+            day_start_ms: u64,
+            reason_hash: Hash,
+            now_ms: u64,
+        ) -> Result<()> {
+            let app = self.env().caller();
+            enforce_time_is_start_of_day(day_start_ms)?;
+            if day_start_ms > now_ms {
                 return Err(Error::UnexpectedTimestamp);
             }
-
-            // Update total downlime
-            if !ddn_status.is_online {
-                let last_downtime = now - ddn_status.last_timestamp;
-                ddn_status.total_downtime += last_downtime;
+            if day_start_ms.saturating_add(DISPUTE_WINDOW_MS) <= now_ms {
+                return Err(Error::DisputeWindowEnded);
+            }
+            if self.disputes.contains_key(&(app, day_start_ms)) {
+                return Err(Error::DisputeExists);
+            }
+            let day = day_start_ms / MS_PER_DAY;
+            if self.app_day_aggregate(app, day).inspectors_count == 0 {
+                return Err(Error::NoMetrics);
             }
 
-            ddn_status.is_online = is_online;
-            ddn_status.last_timestamp = now;
+            let dispute = Dispute {
+                reason_hash,
+                opened_ms: now_ms,
+            };
+            self.record_state_change(&("dispute_metric", app, day_start_ms, &dispute));
+            self.disputes.insert((app, day_start_ms), dispute);
+            self.track_storage_item(app);
+            self.count_dispute(app, day, true);
+            self.env().emit_event(MetricDisputed {
+                app,
+                day_start_ms,
+                reason_hash,
+            });
 
             Ok(())
         }
 
-        /// Get DDC node status
         #[ink(message)]
-        pub fn get_ddn_status(&self, p2p_id: String) -> Result<DDNStatus> {
-            if !self.ddc_nodes.contains_key(&p2p_id) {
-                return Err(Error::DDNNotFound);
+        pub fn get_dispute(&self, app_id: AccountId, day_start_ms: u64) -> Option<Dispute> {
+            self.disputes.get(&(app_id, day_start_ms)).cloned()
+        }
+
+        /// As an arbiter, close a dispute. With corrected metrics, they replace the median of
+        /// the reports for the day, up to `DISPUTE_WINDOW_MS` after the dispute. The reports
+        /// themselves are kept. Without, the dispute can be closed at any time.
+        #[ink(message)]
+        pub fn resolve_dispute(
+            &mut self,
+            app_id: AccountId,
+            day_start_ms: u64,
+            corrected: Option<MetricValue>,
+        ) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            self.resolve_dispute_at_time(app_id, day_start_ms, corrected, now_ms)
+        }
+
+        pub fn resolve_dispute_at_time(
+            &mut self,
+            app_id: AccountId,
+            day_start_ms: u64,
+            corrected: Option<MetricValue>,
+            now_ms: u64,
+        ) -> Result<()> {
+            self.only_role(Role::Arbiter)?;
+
+            let dispute = self
+                .disputes
+                .get(&(app_id, day_start_ms))
+                .ok_or(Error::NoDispute)?;
+            let can_correct = now_ms < dispute.opened_ms.saturating_add(DISPUTE_WINDOW_MS);
+            if corrected.is_some() && !can_correct {
+                return Err(Error::DisputeWindowEnded);
             }
 
-            let mut ddn_statuses: Vec<&DDNStatus> = Vec::new();
+            self.record_state_change(&("resolve_dispute", app_id, day_start_ms, &corrected));
+            self.disputes.take(&(app_id, day_start_ms));
+            self.release_storage_item(app_id, 0);
+            self.count_dispute(app_id, day_start_ms / MS_PER_DAY, false);
+            if self.disputes.is_empty() {
+                self.withdrawn_in_dispute = 0;
+            }
 
-            // Collect DDN statuses from all inspectors
-            for &inspector in self.inspectors.keys() {
-                let key = DDNStatusKey {
-                    inspector,
-                    p2p_id: p2p_id.clone(),
-                };
+            if let Some(mut metrics) = corrected {
+                metrics.start_ms = day_start_ms;
+                let day_of_period = day_start_ms / MS_PER_DAY % PERIOD_DAYS;
+                self.corrected_metrics
+                    .insert((app_id, day_of_period), metrics.clone());
 
-                if let Some(ddn_status) = self.ddn_statuses.get(&key) {
-                    ddn_statuses.push(ddn_status);
-                }
+                self.env().emit_event(MetricCorrected {
+                    app: app_id,
+                    day_start_ms,
+                    metrics,
+                });
             }
 
-            // Get DDN status by using median value of total downtime
-            get_median_by_key(ddn_statuses, |item| item.total_downtime)
-                .cloned()
-                .ok_or(Error::DDNNoStatus)
+            Ok(())
         }
-    }
 
-    // ---- Metrics Reporting ----
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct MetricKey {
-        inspector: AccountId,
-        app_id: AccountId,
-        day_of_period: u64,
-    }
+        fn count_dispute(&mut self, app_id: AccountId, day: u64, opened: bool) {
+            let count = self.day_disputes.get(&day).cloned().unwrap_or(0);
+            let count = if opened {
+                count + 1
+            } else {
+                count.saturating_sub(1)
+            };
+            if count == 0 {
+                self.day_disputes.take(&day);
+            } else {
+                self.day_disputes.insert(day, count);
+            }
 
-    // ---- Metric per DDN ----
-    #[derive(
-        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
-    )]
-    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct MetricKeyDDN {
-        inspector: AccountId,
-        p2p_id: String,
-        day_of_period: u64,
+            let count = self.app_disputes.get(&app_id).cloned().unwrap_or(0);
+            let count = if opened {
+                count + 1
+            } else {
+                count.saturating_sub(1)
+            };
+            if count == 0 {
+                self.app_disputes.take(&app_id);
+            } else {
+                self.app_disputes.insert(app_id, count);
+            }
+        }
     }
 
+    // ---- Reporter reputation ----
+
     #[derive(
         Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
     )]
     #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
-    pub struct MetricValue {
-        start_ms: u64,
-        storage_bytes: u64,
-        wcu_used: u64,
-        rcu_used: u64,
-    }
-
-    impl MetricValue {
-        pub fn add_assign(&mut self, other: Self) {
-            self.storage_bytes += other.storage_bytes;
-            self.wcu_used += other.wcu_used;
-            self.rcu_used += other.rcu_used;
-        }
+    pub struct ReporterStats {
+        /// Reports compared with the median.
+        reports: u64,
+        /// Sum of the deviations of the reports from the median, in basis points.
+        deviation_bps: u64,
     }
 
     #[ink(event)]
-    pub struct MetricReported {
+    pub struct InspectorSuspended {
         #[ink(topic)]
         inspector: AccountId,
-        #[ink(topic)]
-        key: MetricKey,
-        metrics: MetricValue,
+        reputation: u64,
     }
 
     #[ink(event)]
-    pub struct MetricDDNReported {
+    pub struct InspectorReinstated {
         #[ink(topic)]
         inspector: AccountId,
-        #[ink(topic)]
-        key: MetricKeyDDN,
-        metrics: MetricValue,
     }
 
     #[ink(event)]
-    pub struct MetricPeriodFinalized {
-        #[ink(topic)]
-        inspector: AccountId,
-        start_ms: u64,
-    }
-
-    /// Get median value from a vector
-    fn get_median<T: Clone + Ord>(mut source: Vec<T>) -> Option<T> {
-        let length = source.len();
-        // sort_unstable is faster, it doesn't preserve the order of equal elements
-        source.sort_unstable();
-        let index_correction = length != 0 && length % 2 == 0;
-        let median_index = length / 2 - index_correction as usize;
-        source.get(median_index).cloned()
-    }
-
-    /// Get median value from a vector of structs by key
-    fn get_median_by_key<T, F, K>(mut source: Vec<T>, f: F) -> Option<T>
-    where
-        T: Clone,
-        F: FnMut(&T) -> K,
-        K: Ord,
-    {
-        let length = source.len();
-        // sort_unstable is faster, it doesn't preserve the order of equal elements
-        source.sort_unstable_by_key(f);
-        let index_correction = length != 0 && length % 2 == 0;
-        let median_index = length / 2 - index_correction as usize;
-        source.get(median_index).cloned()
+    pub struct DayFinalized {
+        day_start_ms: u64,
+        /// Reports compared with the median.
+        reports: u32,
     }
 
     impl Ddc {
+        /// Compare the reports of a completed day with their median, and add the deviation
+        /// of each inspector to its reputation. Each day is counted once, by anyone after the
+        /// report window of the day, or by the owner if there is no report window.
         #[ink(message)]
-        pub fn metrics_since_subscription(&self, app_id: AccountId) -> Result<MetricValue> {
-            let subscription = self
-                .subscriptions
-                .get(&app_id)
-                .ok_or(Error::NoSubscription)?;
-
-            let now_ms = Self::env().block_timestamp() as u64;
-            let metrics = self.metrics_for_period(app_id, subscription.start_date_ms, now_ms);
-
-            Ok(metrics)
+        pub fn finalize_day(&mut self, day_start_ms: u64) -> Result<()> {
+            let now_ms = Self::env().block_timestamp();
+            self.finalize_day_at_time(day_start_ms, now_ms)
         }
 
-        #[ink(message)]
-        pub fn metrics_for_period(
-            &self,
-            app_id: AccountId,
-            subscription_start_ms: u64,
-            now_ms: u64,
-        ) -> MetricValue {
-            // The start date may be several months away. When did the current period start?
-            let (period_start_days, now_days) =
-                get_current_period_days(subscription_start_ms, now_ms);
+        pub fn finalize_day_at_time(&mut self, day_start_ms: u64, now_ms: u64) -> Result<()> {
+            enforce_time_is_start_of_day(day_start_ms)?;
+            if day_start_ms.saturating_add(MS_PER_DAY) > now_ms {
+                return Err(Error::DayNotEnded);
+            }
+            enforce_not_stale(day_start_ms, now_ms, None)?;
+            let day = day_start_ms / MS_PER_DAY;
+            match self.report_window_days {
+                // Reports are accepted until they are stale, so the owner decides when
+                None => self.only_owner()?,
+                Some(window_days) => {
+                    if day + window_days > self.tolerant_now_ms(now_ms) / MS_PER_DAY {
+                        return Err(Error::DayNotEnded);
+                    }
+                }
+            }
+            if self.finalized_days.contains_key(&day) {
+                return Err(Error::DayAlreadyFinalized);
+            }
 
-            let mut period_metrics = MetricValue {
-                start_ms: period_start_days * MS_PER_DAY,
-                storage_bytes: 0,
-                wcu_used: 0,
-                rcu_used: 0,
+            let apps = match self.reported_apps.get(&(day % PERIOD_DAYS)) {
+                Some((reported_day, apps)) if *reported_day == day => apps.clone(),
+                _ => Vec::new(),
             };
 
-            for day in period_start_days..=now_days {
-                let mut day_storage_bytes: Vec<u64> = Vec::new();
-                let mut day_wcu_used: Vec<u64> = Vec::new();
-                let mut day_rcu_used: Vec<u64> = Vec::new();
-
+            let mut deviations: Vec<(AccountId, u64)> = Vec::new();
+            for app_id in apps {
+                // Reports are compared with their median, not with a correction
+                let median = self.reported_day_aggregate(app_id, day).metrics;
                 for inspector in self.inspectors.keys() {
-                    let inspector_day_metric = self.metrics_for_day(*inspector, app_id, day);
-                    if let Some(inspector_day_metric) = inspector_day_metric {
-                        day_storage_bytes.push(inspector_day_metric.storage_bytes);
-                        day_wcu_used.push(inspector_day_metric.wcu_used);
-                        day_rcu_used.push(inspector_day_metric.rcu_used);
+                    if let Some(reported) = self.metrics_for_day(*inspector, app_id, day) {
+                        deviations.push((*inspector, report_deviation_bps(reported, &median)));
                     }
                 }
+            }
+
+            self.record_state_change(&("finalize_day", day_start_ms));
+            self.finalized_days.insert(day, ());
+            for (inspector, deviation_bps) in deviations.iter() {
+                let mut stats = self.reporter_stats_of(*inspector);
+                stats.reports += 1;
+                stats.deviation_bps = stats.deviation_bps.saturating_add(*deviation_bps);
+                self.reporter_stats.insert(*inspector, stats);
+            }
+            let mut reporters: Vec<AccountId> =
+                deviations.iter().map(|(inspector, _)| *inspector).collect();
+            reporters.sort_unstable();
+            reporters.dedup();
+            for inspector in reporters {
+                self.suspend_if_outlier(inspector);
+            }
+            self.env().emit_event(DayFinalized {
+                day_start_ms,
+                reports: deviations.len() as u32,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_day_finalized(&self, day_start_ms: u64) -> bool {
+            self.finalized_days
+                .contains_key(&(day_start_ms / MS_PER_DAY))
+        }
+
+        #[ink(message)]
+        pub fn reporter_stats_of(&self, account: AccountId) -> ReporterStats {
+            self.reporter_stats
+                .get(&account)
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// As owner, set the reputation under which inspectors are suspended when a day is
+        /// finalized, or None to never suspend them
+        #[ink(message)]
+        pub fn set_suspension_threshold(&mut self, threshold: Option<u64>) -> Result<()> {
+            self.only_owner()?;
+
+            if threshold.map_or(false, |threshold| threshold > BPS as u64) {
+                return Err(Error::InvalidShare);
+            }
+
+            self.suspension_threshold = threshold;
+            self.record_state_change(&("set_suspension_threshold", threshold));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_suspension_threshold(&self) -> Option<u64> {
+            self.suspension_threshold
+        }
+
+        /// Whether the reports of an inspector are ignored by the medians
+        #[ink(message)]
+        pub fn is_inspector_suspended(&self, inspector: AccountId) -> bool {
+            self.suspended_inspectors.contains_key(&inspector)
+        }
+
+        /// As owner, count the reports of a suspended inspector again. Its reputation starts
+        /// over, so that it is not suspended again by its past reports.
+        #[ink(message)]
+        pub fn reinstate_inspector(&mut self, inspector: AccountId) -> Result<()> {
+            self.only_owner()?;
 
-                period_metrics.add_assign(MetricValue {
-                    storage_bytes: get_median(day_storage_bytes).unwrap_or(0),
-                    wcu_used: get_median(day_wcu_used).unwrap_or(0),
-                    rcu_used: get_median(day_rcu_used).unwrap_or(0),
-                    start_ms: 0, // Ignored by add_assign, but required by type
-                });
+            if self.suspended_inspectors.take(&inspector).is_none() {
+                return Err(Error::NotSuspended);
             }
+            self.reporter_stats.take(&inspector);
 
-            period_metrics
+            self.record_state_change(&("reinstate_inspector", inspector));
+            self.env().emit_event(InspectorReinstated { inspector });
+
+            Ok(())
         }
 
-        fn metrics_for_day(
-            &self,
-            inspector: AccountId,
-            app_id: AccountId,
-            day: u64,
-        ) -> Option<&MetricValue> {
-            let day_of_period = day % PERIOD_DAYS;
-            let day_key = MetricKey {
-                inspector,
-                app_id,
-                day_of_period,
+        fn suspend_if_outlier(&mut self, inspector: AccountId) {
+            let threshold = match self.suspension_threshold {
+                Some(threshold) => threshold,
+                None => return,
             };
+            let reputation = self.reporter_reputation(inspector);
+            if reputation >= threshold || self.is_inspector_suspended(inspector) {
+                return;
+            }
 
-            self.metrics.get(&day_key).and_then(|day_metrics| {
-                // Ignore out-of-date metrics from a previous period
-                if day_metrics.start_ms != day * MS_PER_DAY {
-                    None
-                } else {
-                    Some(day_metrics)
-                }
-            })
+            self.suspended_inspectors.insert(inspector, ());
+            self.env().emit_event(InspectorSuspended {
+                inspector,
+                reputation,
+            });
         }
 
+        /// Reputation of an inspector, from 10000 basis points when its reports match the
+        /// median down to 0, from the average deviation of its reports in finalized days.
         #[ink(message)]
-        pub fn metrics_for_ddn(&self, p2p_id: String) -> Vec<MetricValue> {
-            let now_ms = Self::env().block_timestamp() as u64;
-            self.metrics_for_ddn_at_time(p2p_id, now_ms)
-        }
+        pub fn reporter_reputation(&self, account: AccountId) -> u64 {
+            let stats = self.reporter_stats_of(account);
+            if stats.reports == 0 {
+                return BPS as u64;
+            }
 
-        pub fn metrics_for_ddn_at_time(&self, p2p_id: String, now_ms: u64) -> Vec<MetricValue> {
-            let mut period_metrics: Vec<MetricValue> = Vec::with_capacity(PERIOD_DAYS as usize);
+            (BPS as u64).saturating_sub(stats.deviation_bps / stats.reports)
+        }
+    }
 
-            let last_day = now_ms / MS_PER_DAY + 1; // non-inclusive.
-            let first_day = if last_day >= PERIOD_DAYS {
-                last_day - PERIOD_DAYS
+    /// Largest relative deviation of a report from the median over the dimensions, in basis
+    /// points, up to 10000.
+    fn report_deviation_bps(reported: &MetricValue, median: &MetricValue) -> u64 {
+        let deviation = |value: u64, median: u64| {
+            let distance = if value > median {
+                value - median
             } else {
-                0
+                median - value
             };
+            (distance as u128 * BPS as u128 / median.max(1) as u128).min(BPS as u128) as u64
+        };
 
-            for day in first_day..last_day {
-                let mut day_storage_bytes: Vec<u64> = Vec::new();
-                let mut day_wcu_used: Vec<u64> = Vec::new();
-                let mut day_rcu_used: Vec<u64> = Vec::new();
+        deviation(reported.storage_bytes, median.storage_bytes)
+            .max(deviation(reported.wcu_used, median.wcu_used))
+            .max(deviation(reported.rcu_used, median.rcu_used))
+    }
 
-                for inspector in self.inspectors.keys() {
-                    let day_metric = self.metrics_for_ddn_day(*inspector, p2p_id.clone(), day);
+    // ---- Read delegations ----
 
-                    if let Some(day_metric) = day_metric {
-                        day_storage_bytes.push(day_metric.storage_bytes);
-                        day_wcu_used.push(day_metric.wcu_used);
-                        day_rcu_used.push(day_metric.rcu_used);
-                    }
-                }
+    impl Ddc {
+        /// Allow another account to read the private queries about the caller, like
+        /// `account_overview`
+        #[ink(message)]
+        pub fn grant_read_access(&mut self, delegate: AccountId) -> Result<()> {
+            let subject = self.env().caller();
 
-                period_metrics.push(MetricValue {
-                    storage_bytes: get_median(day_storage_bytes).unwrap_or(0),
-                    wcu_used: get_median(day_wcu_used).unwrap_or(0),
-                    rcu_used: get_median(day_rcu_used).unwrap_or(0),
-                    start_ms: day * MS_PER_DAY,
-                });
-            }
+            self.read_delegates.insert((subject, delegate), ());
+            self.record_state_change(&("grant_read_access", subject, delegate));
 
-            period_metrics
+            Ok(())
         }
 
-        fn metrics_for_ddn_day(
-            &self,
-            inspector: AccountId,
-            p2p_id: String,
-            day: u64,
-        ) -> Option<MetricValue> {
-            let day_of_period = day % PERIOD_DAYS;
-            let day_key = MetricKeyDDN {
-                inspector,
-                p2p_id,
-                day_of_period,
-            };
+        #[ink(message)]
+        pub fn revoke_read_access(&mut self, delegate: AccountId) -> Result<()> {
+            let subject = self.env().caller();
 
-            self.metrics_ddn
-                .get(&day_key)
-                .and_then(|metric| {
-                    // Ignore out-of-date metrics from a previous period
-                    if metric.start_ms != day * MS_PER_DAY {
-                        None
-                    } else {
-                        Some(metric)
-                    }
-                })
-                .cloned()
+            self.read_delegates.take(&(subject, delegate));
+            self.record_state_change(&("revoke_read_access", subject, delegate));
+
+            Ok(())
         }
 
         #[ink(message)]
-        pub fn report_metrics(
-            &mut self,
-            app_id: AccountId,
-            day_start_ms: u64,
-            storage_bytes: u64,
-            wcu_used: u64,
-            rcu_used: u64,
-        ) -> Result<()> {
-            let inspector = self.env().caller();
-            self.only_inspector()?;
+        pub fn can_read(&self, subject: AccountId, reader: AccountId) -> bool {
+            subject == reader || self.read_delegates.contains_key(&(subject, reader))
+        }
 
-            enforce_time_is_start_of_day(day_start_ms)?;
-            let day = day_start_ms / MS_PER_DAY;
-            let day_of_period = day % PERIOD_DAYS;
+        /// Check if the caller can read the private queries about the subject
+        fn only_reader_of(&self, subject: AccountId) -> Result<()> {
+            if self.can_read(subject, self.env().caller()) {
+                Ok(())
+            } else {
+                Err(Error::NoReadAccess)
+            }
+        }
+    }
 
-            let key = MetricKey {
-                inspector,
-                app_id,
-                day_of_period,
-            };
-            let metrics = MetricValue {
-                start_ms: day_start_ms,
-                storage_bytes,
-                wcu_used,
-                rcu_used,
-            };
+    // ---- Account overview ----
 
-            self.metrics.insert(key.clone(), metrics.clone());
+    #[derive(Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct AccountOverview {
+        is_owner: bool,
+        is_inspector: bool,
+        is_ddn_manager: bool,
+        /// DDC nodes paying their rewards to the account.
+        payout_nodes: Vec<String>,
+
+        /// Subscription of the account as an app.
+        subscription: Option<AppSubscriptionDetails>,
+        /// Part of the subscription balance consumed since the last actualization.
+        unsettled_consumption: Balance,
+        sponsor: Option<AccountId>,
+
+        sponsor_pool: Balance,
+        inspector_rebate: Balance,
+        inspector_stake: Balance,
+        /// Total stake delegated by the account to inspectors.
+        delegated: Balance,
+    }
 
-            self.env().emit_event(MetricReported {
-                inspector,
-                key,
-                metrics,
-            });
+    impl Ddc {
+        /// Return everything the contract knows about an account in one query.
+        /// Only readable by the account and its read delegates.
+        #[ink(message)]
+        pub fn account_overview(&self, account: AccountId) -> Result<AccountOverview> {
+            self.only_reader_of(account)?;
+            let now_ms = Self::env().block_timestamp();
 
-            Ok(())
+            let subscription = self.get_subscription_details_of(account).ok();
+            let unsettled_consumption = match self.subscriptions.get(&account) {
+                Some(subscription) => {
+                    let tier = self.service_tiers.get(&subscription.tier_id).unwrap();
+                    Self::get_consumed_balance_at_time(
+                        now_ms,
+                        subscription,
+                        tier,
+                        self.rounding_policy,
+                        self.period_ms(),
+                    )?
+                    .min(subscription.balance)
+                }
+                None => 0,
+            };
+
+            Ok(AccountOverview {
+                is_owner: *self.owner == account,
+                is_inspector: self.is_inspector(account),
+                is_ddn_manager: self.is_ddn_manager(account),
+                payout_nodes: self
+                    .node_payout_addresses
+                    .iter()
+                    .filter(|(_, payout_address)| payout_address.at_time(now_ms) == Some(account))
+                    .map(|(p2p_id, _)| p2p_id.clone())
+                    .collect(),
+                subscription,
+                unsettled_consumption,
+                sponsor: self.sponsor_of(account),
+                sponsor_pool: self.sponsor_pool_of(account),
+                inspector_rebate: self.inspector_rebate_of(account),
+                inspector_stake: self.inspector_stake_of(account),
+                delegated: self
+                    .inspector_delegations
+                    .iter()
+                    .filter(|((delegator, _), _)| *delegator == account)
+                    .map(|(_, value)| *value)
+                    .sum(),
+            })
         }
+    }
 
-        /// Reports DDC node metrics
-        /// Called by OCW if node metrics is successfully fetched
-        /// Updates DDC node connectivity status to online
-        #[ink(message)]
-        pub fn report_metrics_ddn(
-            &mut self,
-            p2p_id: String,
-            day_start_ms: u64,
-            storage_bytes: u64,
-            wcu_used: u64,
-            rcu_used: u64,
-        ) -> Result<()> {
-            let inspector = self.env().caller();
-            self.only_inspector()?;
+    // ---- Operation statistics ----
 
-            enforce_time_is_start_of_day(day_start_ms)?;
-            let day = day_start_ms / MS_PER_DAY;
-            let day_of_period = day % PERIOD_DAYS;
+    /// Number of operations processed in a period, to plan the block weight usage
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct OpsStats {
+        /// Metrics reports of apps, DDC nodes and buckets.
+        reports: u64,
+        /// Status updates of DDC nodes, including those implied by their metrics reports.
+        status_updates: u64,
+        /// Revenue settlements and distributions of node rewards.
+        settlements: u64,
+    }
 
-            let key = MetricKeyDDN {
-                inspector,
-                p2p_id: p2p_id.clone(),
-                day_of_period,
-            };
-            let metrics = MetricValue {
-                start_ms: day_start_ms,
-                storage_bytes,
-                wcu_used,
-                rcu_used,
-            };
+    impl Ddc {
+        /// Return the operations processed in the period starting at `period_start_ms`
+        #[ink(message)]
+        pub fn ops_stats(&self, period_start_ms: u64) -> OpsStats {
+            self.ops_stats
+                .get(&period_start_ms)
+                .cloned()
+                .unwrap_or_default()
+        }
 
-            self.metrics_ddn.insert(key.clone(), metrics.clone());
+        /// Statistics of the current period
+        fn ops_stats_mut(&mut self) -> &mut OpsStats {
+            let now_ms = Self::env().block_timestamp();
+            let period_start_ms = now_ms - now_ms % self.period_ms();
 
-            self.report_ddn_status(p2p_id, true).unwrap();
+            if !self.ops_stats.contains_key(&period_start_ms) {
+                self.ops_stats.insert(period_start_ms, OpsStats::default());
+            }
+            self.ops_stats.get_mut(&period_start_ms).unwrap()
+        }
+    }
 
-            self.env().emit_event(MetricDDNReported {
-                inspector,
-                key,
-                metrics,
-            });
+    // ---- Health check ----
 
-            Ok(())
-        }
+    #[derive(Default, Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct HealthReport {
+        contract_balance: Balance,
+        minimum_balance: Balance,
+        /// Sum of the stored subscription balances, before the consumption since their last
+        /// actualization, so an upper bound of what the apps can still spend.
+        subscription_balances: Balance,
+        /// Whether the subscription balances are covered by the contract balance above the
+        /// minimum balance.
+        balances_covered: bool,
+        /// Whether the forwarded and burned revenue does not exceed `total_ddc_balance`.
+        revenue_consistent: bool,
+        /// Metrics of apps without subscription and of DDC nodes no longer known.
+        orphaned_metrics: u32,
+    }
 
+    impl Ddc {
+        /// Check the accounting invariants of the contract, for monitoring
         #[ink(message)]
-        pub fn finalize_metric_period(&mut self, start_ms: u64) -> Result<()> {
-            let inspector = self.env().caller();
-            self.only_inspector()?;
+        pub fn health_check(&self) -> HealthReport {
+            let contract_balance = self.env().balance();
+            let minimum_balance = self.env().minimum_balance();
+            let subscription_balances: Balance = self
+                .subscriptions
+                .values()
+                .map(|subscription| subscription.balance)
+                .sum();
+            // Promotional discounts and referral rewards are not backed by the balance
+            let non_refundable: Balance = self.non_refundable.values().sum();
+
+            HealthReport {
+                contract_balance,
+                minimum_balance,
+                subscription_balances,
+                balances_covered: subscription_balances.saturating_sub(non_refundable)
+                    <= contract_balance.saturating_sub(minimum_balance),
+                revenue_consistent: self.revenue_forwarded.saturating_sub(non_refundable)
+                    <= self.total_ddc_balance
+                    && self.total_burned <= self.revenue_forwarded,
+                orphaned_metrics: self.orphaned_metrics,
+            }
+        }
 
-            enforce_time_is_start_of_day(start_ms)?;
-            let next_period_ms = start_ms + MS_PER_DAY;
-            self.current_period_ms.insert(inspector, next_period_ms);
+        /// Count a metrics entry of an app being stored or removed, orphaned while the app
+        /// has no subscription
+        fn count_app_metrics(&mut self, app_id: AccountId, added: bool) {
+            let entries = self.app_metric_entries.get(&app_id).cloned().unwrap_or(0);
+            let entries = if added {
+                entries + 1
+            } else {
+                entries.saturating_sub(1)
+            };
+            if entries == 0 {
+                self.app_metric_entries.take(&app_id);
+            } else {
+                self.app_metric_entries.insert(app_id, entries);
+            }
+            if !self.subscriptions.contains_key(&app_id) {
+                self.count_orphaned_metrics(1, added);
+            }
+        }
 
-            self.env().emit_event(MetricPeriodFinalized {
-                inspector,
-                start_ms,
-            });
+        /// Count a metrics entry of a DDC node being stored or removed, orphaned while the
+        /// node is not known
+        fn count_node_metrics(&mut self, p2p_id: String, added: bool) {
+            let entries = self.node_metric_entries.get(&p2p_id).cloned().unwrap_or(0);
+            let entries = if added {
+                entries + 1
+            } else {
+                entries.saturating_sub(1)
+            };
+            let orphaned = !self.ddc_nodes.contains_key(&p2p_id);
+            if entries == 0 {
+                self.node_metric_entries.take(&p2p_id);
+            } else {
+                self.node_metric_entries.insert(p2p_id, entries);
+            }
+            if orphaned {
+                self.count_orphaned_metrics(1, added);
+            }
+        }
 
-            Ok(())
+        /// The metrics of an app become orphaned when its subscription is removed, and are
+        /// no longer when it subscribes again
+        fn orphan_app_metrics(&mut self, app_id: AccountId, orphaned: bool) {
+            let entries = self.app_metric_entries.get(&app_id).cloned().unwrap_or(0);
+            self.count_orphaned_metrics(entries, orphaned);
         }
 
-        #[ink(message)]
-        pub fn get_current_period_ms(&self) -> u64 {
-            let caller = self.env().caller();
-            self.get_current_period_ms_of(caller)
+        /// The metrics of a DDC node become orphaned when the node is removed, and are no
+        /// longer when it is added again
+        fn orphan_node_metrics(&mut self, p2p_id: String, orphaned: bool) {
+            let entries = self.node_metric_entries.get(&p2p_id).cloned().unwrap_or(0);
+            self.count_orphaned_metrics(entries, orphaned);
         }
 
-        #[ink(message)]
-        pub fn get_current_period_ms_of(&self, inspector_id: AccountId) -> u64 {
-            let current_period_ms = self.current_period_ms.get(&inspector_id);
-            match current_period_ms {
-                None => {
-                    let now: u64 = Self::env().block_timestamp(); // Epoch in milisecond
-                    let today_ms = now - now % MS_PER_DAY; // The beginning of today
-                    today_ms
-                }
-                Some(current_period_ms) => *current_period_ms,
+        fn count_orphaned_metrics(&mut self, entries: u32, added: bool) {
+            if added {
+                self.orphaned_metrics += entries;
+            } else {
+                self.orphaned_metrics = self.orphaned_metrics.saturating_sub(entries);
             }
         }
     }
 
     // ---- Utils ----
-    #[derive(Debug, PartialEq, Eq, scale::Encode)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
     pub enum Error {
         OnlyOwner,
@@ -1339,22 +8754,355 @@ mod ddc {
         NoFreeTier,
         DDNNotFound,
         DDNNoStatus,
+        TierFull,
+        NotSponsored,
+        AppAlreadySponsored,
+        StalePeriod,
+        NoPaymentSink,
+        PeriodNotEnded,
+        ExportAlreadyCommitted,
+        TierMismatch,
+        OnlyNamespaceAdmin,
+        ImportFinished,
+        InvalidRecord,
+        InspectorNotFound,
+        InvalidShare,
+        NodeUpdateTooSoon,
+        SpendingCapExceeded,
+        OnlyGuardian,
+        OwnerStillActive,
+        ClaimNotReady,
+        TooManyDays,
+        MissingRole,
+        TierNotInCluster,
+        TierInactive,
+        TierActive,
+        TierInUse,
+        NoReadAccess,
+        InvalidPeriod,
+        BatchTooLarge,
+        GiftPending,
+        NoGift,
+        GiftExpired,
+        NodeAlreadyRegistered,
+        StakeLocked,
+        LateReport,
+        ContactTooLong,
+        ClusterNotFound,
+        InvalidReplicationFactor,
+        PeriodAlreadyRewarded,
+        NoEligibleNodes,
+        AppArchived,
+        NodeArchived,
+        NotArchived,
+        RetentionNotEnded,
+        AlreadySubscribed,
+        TrialUnavailable,
+        TrialUsed,
+        PromoExists,
+        InvalidPromo,
+        ArithmeticError,
+        NoMetrics,
+        DisputeExists,
+        NoDispute,
+        DisputeWindowEnded,
+        DayNotEnded,
+        DayAlreadyFinalized,
+        NotSuspended,
+        NodeLeaving,
+        PeriodNotNext,
+        WithdrawLocked,
+        SubscriptionActive,
+        NodeQueueFull,
+        RegistrationClosed,
+    }
+
+    impl Error {
+        /// Stable numeric code of the error, for SDKs mapping errors to their own messages.
+        /// A code is never reused: new errors get the next free code, whatever their position
+        /// in the enum.
+        pub fn code(self) -> u16 {
+            match self {
+                Error::OnlyOwner => 0,
+                Error::OnlyInspector => 1,
+                Error::OnlyDDNManager => 2,
+                Error::SameDepositValue => 3,
+                Error::NoPermission => 4,
+                Error::InsufficientDeposit => 5,
+                Error::TransferFailed => 6,
+                Error::ZeroBalance => 7,
+                Error::InsufficientBalance => 8,
+                Error::InvalidAccount => 9,
+                Error::OverLimit => 10,
+                Error::TidOutOfBound => 11,
+                Error::ContractPaused => 12,
+                Error::ContractActive => 13,
+                Error::UnexpectedTimestamp => 14,
+                Error::NoSubscription => 15,
+                Error::NoFreeTier => 16,
+                Error::DDNNotFound => 17,
+                Error::DDNNoStatus => 18,
+                Error::TierFull => 19,
+                Error::NotSponsored => 20,
+                Error::AppAlreadySponsored => 21,
+                Error::StalePeriod => 22,
+                Error::NoPaymentSink => 23,
+                Error::PeriodNotEnded => 24,
+                Error::ExportAlreadyCommitted => 25,
+                Error::TierMismatch => 26,
+                Error::OnlyNamespaceAdmin => 27,
+                Error::ImportFinished => 28,
+                Error::InvalidRecord => 29,
+                Error::InspectorNotFound => 30,
+                Error::InvalidShare => 31,
+                Error::NodeUpdateTooSoon => 32,
+                Error::SpendingCapExceeded => 33,
+                Error::OnlyGuardian => 34,
+                Error::OwnerStillActive => 35,
+                Error::ClaimNotReady => 36,
+                Error::TooManyDays => 37,
+                Error::MissingRole => 38,
+                Error::TierNotInCluster => 39,
+                Error::TierInactive => 40,
+                Error::TierActive => 41,
+                Error::TierInUse => 42,
+                Error::NoReadAccess => 43,
+                Error::InvalidPeriod => 44,
+                Error::BatchTooLarge => 45,
+                Error::GiftPending => 46,
+                Error::NoGift => 47,
+                Error::GiftExpired => 48,
+                Error::NodeAlreadyRegistered => 49,
+                Error::StakeLocked => 50,
+                Error::LateReport => 51,
+                Error::ContactTooLong => 52,
+                Error::ClusterNotFound => 53,
+                Error::InvalidReplicationFactor => 54,
+                Error::PeriodAlreadyRewarded => 55,
+                Error::NoEligibleNodes => 56,
+                Error::AppArchived => 57,
+                Error::NodeArchived => 58,
+                Error::NotArchived => 59,
+                Error::RetentionNotEnded => 60,
+                Error::AlreadySubscribed => 61,
+                Error::TrialUnavailable => 62,
+                Error::TrialUsed => 63,
+                Error::PromoExists => 64,
+                Error::InvalidPromo => 65,
+                Error::ArithmeticError => 66,
+                Error::NoMetrics => 67,
+                Error::DisputeExists => 68,
+                Error::NoDispute => 69,
+                Error::DisputeWindowEnded => 70,
+                Error::DayNotEnded => 71,
+                Error::DayAlreadyFinalized => 72,
+                Error::NotSuspended => 73,
+                Error::NodeLeaving => 74,
+                Error::PeriodNotNext => 75,
+                Error::WithdrawLocked => 76,
+                Error::SubscriptionActive => 77,
+                Error::NodeQueueFull => 78,
+                Error::RegistrationClosed => 79,
+            }
+        }
+
+        pub fn from_code(code: u16) -> Option<Self> {
+            Some(match code {
+                0 => Error::OnlyOwner,
+                1 => Error::OnlyInspector,
+                2 => Error::OnlyDDNManager,
+                3 => Error::SameDepositValue,
+                4 => Error::NoPermission,
+                5 => Error::InsufficientDeposit,
+                6 => Error::TransferFailed,
+                7 => Error::ZeroBalance,
+                8 => Error::InsufficientBalance,
+                9 => Error::InvalidAccount,
+                10 => Error::OverLimit,
+                11 => Error::TidOutOfBound,
+                12 => Error::ContractPaused,
+                13 => Error::ContractActive,
+                14 => Error::UnexpectedTimestamp,
+                15 => Error::NoSubscription,
+                16 => Error::NoFreeTier,
+                17 => Error::DDNNotFound,
+                18 => Error::DDNNoStatus,
+                19 => Error::TierFull,
+                20 => Error::NotSponsored,
+                21 => Error::AppAlreadySponsored,
+                22 => Error::StalePeriod,
+                23 => Error::NoPaymentSink,
+                24 => Error::PeriodNotEnded,
+                25 => Error::ExportAlreadyCommitted,
+                26 => Error::TierMismatch,
+                27 => Error::OnlyNamespaceAdmin,
+                28 => Error::ImportFinished,
+                29 => Error::InvalidRecord,
+                30 => Error::InspectorNotFound,
+                31 => Error::InvalidShare,
+                32 => Error::NodeUpdateTooSoon,
+                33 => Error::SpendingCapExceeded,
+                34 => Error::OnlyGuardian,
+                35 => Error::OwnerStillActive,
+                36 => Error::ClaimNotReady,
+                37 => Error::TooManyDays,
+                38 => Error::MissingRole,
+                39 => Error::TierNotInCluster,
+                40 => Error::TierInactive,
+                41 => Error::TierActive,
+                42 => Error::TierInUse,
+                43 => Error::NoReadAccess,
+                44 => Error::InvalidPeriod,
+                45 => Error::BatchTooLarge,
+                46 => Error::GiftPending,
+                47 => Error::NoGift,
+                48 => Error::GiftExpired,
+                49 => Error::NodeAlreadyRegistered,
+                50 => Error::StakeLocked,
+                51 => Error::LateReport,
+                52 => Error::ContactTooLong,
+                53 => Error::ClusterNotFound,
+                54 => Error::InvalidReplicationFactor,
+                55 => Error::PeriodAlreadyRewarded,
+                56 => Error::NoEligibleNodes,
+                57 => Error::AppArchived,
+                58 => Error::NodeArchived,
+                59 => Error::NotArchived,
+                60 => Error::RetentionNotEnded,
+                61 => Error::AlreadySubscribed,
+                62 => Error::TrialUnavailable,
+                63 => Error::TrialUsed,
+                64 => Error::PromoExists,
+                65 => Error::InvalidPromo,
+                66 => Error::ArithmeticError,
+                67 => Error::NoMetrics,
+                68 => Error::DisputeExists,
+                69 => Error::NoDispute,
+                70 => Error::DisputeWindowEnded,
+                71 => Error::DayNotEnded,
+                72 => Error::DayAlreadyFinalized,
+                73 => Error::NotSuspended,
+                74 => Error::NodeLeaving,
+                75 => Error::PeriodNotNext,
+                76 => Error::WithdrawLocked,
+                77 => Error::SubscriptionActive,
+                78 => Error::NodeQueueFull,
+                79 => Error::RegistrationClosed,
+                _ => return None,
+            })
+        }
+    }
+
+    impl Ddc {
+        #[ink(message)]
+        pub fn error_code(&self, error: Error) -> u16 {
+            error.code()
+        }
+
+        /// Return the error with the given code, if any
+        #[ink(message)]
+        pub fn decode_error(&self, code: u16) -> Option<Error> {
+            Error::from_code(code)
+        }
     }
 
     pub type Result<T> = core::result::Result<T, Error>;
 
+    /// An account which can only be changed with a delay
+    #[derive(
+        Default, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct DelayedAddress {
+        current: Option<AccountId>,
+        pending: Option<AccountId>,
+        effective_ms: u64,
+    }
+
+    impl DelayedAddress {
+        /// Return the account in effect at the given time
+        pub fn at_time(&self, now_ms: u64) -> Option<AccountId> {
+            if self.pending.is_some() && now_ms >= self.effective_ms {
+                self.pending
+            } else {
+                self.current
+            }
+        }
+
+        /// Replace the account after a delay
+        pub fn schedule(&mut self, address: AccountId, now_ms: u64, delay_ms: u64) {
+            self.current = self.at_time(now_ms);
+            self.pending = Some(address);
+            self.effective_ms = now_ms + delay_ms;
+        }
+    }
+
+    const ADDRESS_CHANGE_DELAY_MS: u64 = MS_PER_DAY;
+
+    /// A page of a list returned by the paginated queries.
+    /// Pass `next_cursor` to the same query to get the next page.
+    #[derive(Clone, PartialEq, Eq, Encode, Decode)]
+    #[cfg_attr(feature = "std", derive(Debug, scale_info::TypeInfo))]
+    pub struct Page<T> {
+        items: Vec<T>,
+        /// None if this is the last page.
+        next_cursor: Option<u32>,
+        /// Total number of items in the list.
+        total: u32,
+    }
+
+    const MAX_PAGE_LIMIT: u32 = 100;
+
+    /// Return the page of `limit` items starting at index `cursor`
+    fn paginate<T>(items: impl Iterator<Item = T>, total: u32, cursor: u32, limit: u32) -> Page<T> {
+        let limit = limit.min(MAX_PAGE_LIMIT);
+        let items: Vec<T> = items.skip(cursor as usize).take(limit as usize).collect();
+
+        let end = cursor.saturating_add(items.len() as u32);
+        let next_cursor = if end < total { Some(end) } else { None };
+
+        Page {
+            items,
+            next_cursor,
+            total,
+        }
+    }
+
     const MS_PER_DAY: u64 = 24 * 3600 * 1000;
+    /// Default length of the billing period. Metrics are kept for this many days, which is
+    /// therefore also the longest period.
     const PERIOD_DAYS: u64 = 31;
     const PERIOD_MS: u64 = PERIOD_DAYS * MS_PER_DAY;
 
-    fn get_current_period_days(subscription_start_ms: u64, now_ms: u64) -> (u64, u64) {
+    fn get_current_period_days(
+        subscription_start_ms: u64,
+        now_ms: u64,
+        period_days: u64,
+    ) -> (u64, u64) {
         let now_days = now_ms / MS_PER_DAY;
         let start_days = subscription_start_ms / MS_PER_DAY;
-        let period_elapsed_days = (now_days - start_days) % PERIOD_DAYS;
+        let period_elapsed_days = (now_days - start_days) % period_days;
         let period_start_days = now_days - period_elapsed_days;
         (period_start_days, now_days)
     }
 
+    /// Metrics are stored in slots reused every period. Reject the metrics of a day if its slot
+    /// belongs to a more recent period, either in storage or according to the current time.
+    fn enforce_not_stale(
+        day_start_ms: u64,
+        now_ms: u64,
+        stored_metrics: Option<&MetricValue>,
+    ) -> Result<()> {
+        let is_outdated = day_start_ms + PERIOD_MS <= now_ms - now_ms % MS_PER_DAY;
+        let is_overwriting_newer = stored_metrics.map_or(false, |m| m.start_ms > day_start_ms);
+
+        if is_outdated || is_overwriting_newer {
+            Err(Error::StalePeriod)
+        } else {
+            Ok(())
+        }
+    }
+
     fn enforce_time_is_start_of_day(ms: u64) -> Result<()> {
         if ms % MS_PER_DAY == 0 {
             Ok(())